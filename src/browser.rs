@@ -0,0 +1,387 @@
+use relm4::{
+    factory::{DynamicIndex, FactoryComponent, FactorySender, FactoryVecDeque}, gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt
+};
+use gtk::prelude::*;
+
+use std::net::SocketAddr;
+
+use crate::{lan, master, query::{self, ServerInfo}, uri::ServerUri};
+
+pub(crate) struct ServerBrowser {
+    family: usize,
+    /// Showing the LAN tab instead of master server lists
+    lan: bool,
+    servers: FactoryVecDeque<ServerRow>,
+    status: String,
+    busy: bool,
+    /// The family or tab changed during a refresh, whose result is stale
+    pending: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum BrowserMsg {
+    SelectFamily(u32),
+    SelectLan(bool),
+    Refresh,
+    Connect(DynamicIndex),
+    Details(DynamicIndex),
+    Favourite(DynamicIndex),
+}
+
+#[derive(Debug)]
+pub(crate) enum BrowserOutput {
+    /// A server link, handled like one clicked in a browser
+    Connect(String),
+    /// Label and server to add to the favourites
+    AddFavourite(String, ServerUri),
+    /// Scheme and address of a server to show details for
+    Details(String, SocketAddr),
+}
+
+#[derive(Debug)]
+pub(crate) enum BrowserCmd {
+    Done(Vec<ServerInfo>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ServerRow {
+    info: ServerInfo,
+}
+
+#[derive(Debug)]
+pub(crate) enum RowOutput {
+    Connect(DynamicIndex),
+    Details(DynamicIndex),
+    Favourite(DynamicIndex),
+}
+
+/// Column widths in characters, shared by the header and the rows.
+pub(crate) static COLUMNS: [(&str, i32); 5] = [("Map", 14), ("Type", 6), ("Players", 7), ("Ping", 5), ("", 18)];
+
+#[relm4::factory(pub(crate))]
+impl FactoryComponent for ServerRow {
+    type Init = ServerInfo;
+    type Input = ();
+    type Output = RowOutput;
+    type CommandOutput = ();
+    type ParentWidget = gtk::ListBox;
+
+    view! {
+        root = gtk::Box {
+            set_orientation: gtk::Orientation::Horizontal,
+            set_spacing: 8,
+            set_margin_all: 2,
+
+            gtk::Label {
+                set_text: &query::strip_colours(&self.info.hostname),
+                set_tooltip_text: Some(&self.info.addr.to_string()),
+                set_hexpand: true,
+                set_halign: gtk::Align::Start,
+                set_ellipsize: gtk::pango::EllipsizeMode::End,
+            },
+            gtk::Label {
+                set_text: &self.info.map,
+                set_width_chars: COLUMNS[0].1,
+                set_xalign: 0.0,
+                set_ellipsize: gtk::pango::EllipsizeMode::End,
+            },
+            gtk::Label {
+                set_text: &self.info.gametype,
+                set_width_chars: COLUMNS[1].1,
+                set_xalign: 0.0,
+            },
+            gtk::Label {
+                set_text: &format!("{}/{}", self.info.clients, self.info.max_clients),
+                set_width_chars: COLUMNS[2].1,
+                set_xalign: 0.0,
+            },
+            gtk::Label {
+                set_text: &format!("{}", self.info.ping),
+                set_width_chars: COLUMNS[3].1,
+                set_xalign: 0.0,
+            },
+            gtk::Button {
+                set_icon_name: "starred",
+                set_tooltip_text: Some("Add to favourites"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(RowOutput::Favourite(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "dialog-information",
+                set_tooltip_text: Some("Details"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(RowOutput::Details(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_label: if self.info.password { "Connect 🔒" } else { "Connect" },
+                set_width_request: 90,
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(RowOutput::Connect(index.clone())).unwrap();
+                },
+            },
+        }
+    }
+
+    fn init_model(info: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> ServerRow
+    {
+        ServerRow { info }
+    }
+}
+
+#[relm4::component(pub(crate))]
+impl Component for ServerBrowser {
+    type Init = ();
+    type Input = BrowserMsg;
+    type Output = BrowserOutput;
+    type CommandOutput = BrowserCmd;
+
+    view! {
+        gtk::Window {
+            set_title: Some("Server Browser"),
+            set_default_size: (760, 500),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::Box {
+                        add_css_class: "linked",
+
+                        #[name = "internet_button"]
+                        gtk::ToggleButton {
+                            set_label: "Internet",
+                            set_active: true,
+                            connect_toggled[sender] => move |btn| {
+                                if btn.is_active() {
+                                    sender.input(BrowserMsg::SelectLan(false));
+                                }
+                            },
+                        },
+                        gtk::ToggleButton {
+                            set_label: "LAN",
+                            set_group: Some(&internet_button),
+                            connect_toggled[sender] => move |btn| {
+                                if btn.is_active() {
+                                    sender.input(BrowserMsg::SelectLan(true));
+                                }
+                            },
+                        },
+                    },
+                    gtk::DropDown::from_strings(&family_labels) {
+                        #[watch]
+                        set_sensitive: !model.lan,
+                        connect_selected_notify[sender] => move |dd| {
+                            sender.input(BrowserMsg::SelectFamily(dd.selected()));
+                        },
+                    },
+                    gtk::Button {
+                        set_icon_name: "view-refresh",
+                        #[watch]
+                        set_sensitive: !model.busy,
+                        connect_clicked => BrowserMsg::Refresh,
+                    },
+                    gtk::Spinner {
+                        #[watch]
+                        set_spinning: model.busy,
+                    },
+                    gtk::Label {
+                        #[watch]
+                        set_text: &model.status,
+                        set_hexpand: true,
+                        set_halign: gtk::Align::End,
+                    },
+                },
+
+                #[name = "header"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 8,
+                    set_margin_start: 8,
+                    set_margin_end: 8,
+
+                    gtk::Label {
+                        set_markup: "<b>Server</b>",
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                    },
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                    #[local_ref]
+                    servers_box -> gtk::ListBox {
+                        set_selection_mode: gtk::SelectionMode::None,
+                    },
+                },
+            }
+        }
+    }
+
+    fn init(_: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let family_labels: Vec<&str> = master::FAMILIES.iter().map(|f| f.label).collect();
+
+        let servers = FactoryVecDeque::builder()
+            .launch(gtk::ListBox::default())
+            .forward(sender.input_sender(), |msg| match msg {
+                RowOutput::Connect(index) => BrowserMsg::Connect(index),
+                RowOutput::Details(index) => BrowserMsg::Details(index),
+                RowOutput::Favourite(index) => BrowserMsg::Favourite(index),
+            });
+
+        let model = ServerBrowser {
+            family: 0,
+            lan: false,
+            servers,
+            status: String::new(),
+            busy: false,
+            pending: false,
+        };
+        let servers_box = model.servers.widget();
+        let widgets = view_output!();
+
+        for (title, width) in COLUMNS {
+            let label = gtk::Label::new(None);
+            label.set_markup(&format!("<b>{title}</b>"));
+            label.set_width_chars(width);
+            label.set_xalign(0.0);
+            widgets.header.append(&label);
+        }
+
+        sender.input(BrowserMsg::Refresh);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            BrowserMsg::SelectFamily(family) => {
+                self.family = family as usize;
+                sender.input(BrowserMsg::Refresh);
+            }
+            BrowserMsg::SelectLan(lan) => {
+                self.lan = lan;
+                sender.input(BrowserMsg::Refresh);
+            }
+            BrowserMsg::Refresh => {
+                // Rows always belong to the selected family, Connect builds links with it
+                self.servers.guard().clear();
+                if self.busy {
+                    self.pending = true;
+                    self.status = String::from("Waiting for the previous refresh...");
+                    return;
+                }
+                self.busy = true;
+
+                if self.lan {
+                    self.status = String::from("Looking for servers on the local network...");
+                    sender.spawn_oneshot_command(|| {
+                        let mut servers = lan::discover(query::DEFAULT_TIMEOUT).unwrap_or_else(|e| {
+                            eprintln!("CoDLinux: LAN discovery failed: {e}");
+                            Vec::new()
+                        });
+                        servers.sort_by_key(|s| s.ping);
+                        BrowserCmd::Done(servers)
+                    });
+                    return;
+                }
+
+                let family = &master::FAMILIES[self.family];
+                self.status = format!("Asking master servers for {}...", family.label);
+                let masters = family.masters();
+                let protocol = family.protocol;
+                sender.spawn_oneshot_command(move || {
+                    let addrs = master::query_masters(&masters, protocol, query::DEFAULT_TIMEOUT);
+                    let mut servers = query::query_infos(&addrs, query::DEFAULT_TIMEOUT).unwrap_or_else(|e| {
+                        eprintln!("CoDLinux: Server query failed: {e}");
+                        Vec::new()
+                    });
+                    servers.sort_by_key(|s| s.ping);
+                    BrowserCmd::Done(servers)
+                });
+            }
+            BrowserMsg::Connect(index) => {
+                if let Some(row) = self.servers.get(index.current_index()) {
+                    let link = server_link(self.scheme_for(&row.info), &row.info);
+                    sender.output(BrowserOutput::Connect(link)).unwrap();
+                }
+            }
+            BrowserMsg::Details(index) => {
+                if let Some(row) = self.servers.get(index.current_index()) {
+                    let scheme = self.scheme_for(&row.info).to_string();
+                    sender.output(BrowserOutput::Details(scheme, row.info.addr)).unwrap();
+                }
+            }
+            BrowserMsg::Favourite(index) => {
+                if let Some(row) = self.servers.get(index.current_index()) {
+                    let label = query::strip_colours(&row.info.hostname);
+                    let server = server_uri(self.scheme_for(&row.info), &row.info);
+                    sender.output(BrowserOutput::AddFavourite(label, server)).unwrap();
+                }
+            }
+        }
+    }
+
+    fn update_cmd(&mut self, msg: Self::CommandOutput, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            BrowserCmd::Done(servers) => {
+                self.busy = false;
+                if self.pending {
+                    self.pending = false;
+                    sender.input(BrowserMsg::Refresh);
+                    return;
+                }
+                self.status = format!("{} servers", servers.len());
+                let mut guard = self.servers.guard();
+                for server in servers {
+                    guard.push_back(server);
+                }
+            }
+        }
+    }
+}
+
+impl ServerBrowser {
+    /// LAN servers can be either game, go by the protocol they report.
+    fn scheme_for(&self, server: &ServerInfo) -> &'static str
+    {
+        let selected = &master::FAMILIES[self.family];
+        if !self.lan {
+            return selected.scheme;
+        }
+        server.info.get("protocol")
+            .and_then(|p| p.parse().ok())
+            .and_then(master::family_for_protocol)
+            .unwrap_or(selected)
+            .scheme
+    }
+}
+
+pub(crate) fn server_uri(scheme: &str, server: &ServerInfo) -> ServerUri
+{
+    ServerUri {
+        scheme: scheme.to_string(),
+        host: server.addr.ip().to_string(),
+        port: server.addr.port(),
+        password: None,
+        fs_game: None,
+        name: None,
+    }
+}
+
+/// Link that connects to `server`, so browser joins take the same path as clicked links.
+pub(crate) fn server_link(scheme: &str, server: &ServerInfo) -> String
+{
+    server_uri(scheme, server).to_link()
+}
@@ -0,0 +1,61 @@
+/// Command line flags understood by codlinux. Anything that isn't a flag
+/// (URIs, game args) is kept in `rest` in its original order.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CliArgs {
+    /// `--dry-run`: print the resolved launch command instead of running it
+    pub dry_run: bool,
+    /// `--menu`: open the launcher even if a game is remembered
+    pub menu: bool,
+    /// `--dispatch`: the link came from the desktop entry, pick the install from the registry first
+    pub dispatch: bool,
+    /// `--master[=PORT]`: run the private master server without a window
+    pub master: Option<u16>,
+    pub rest: Vec<String>,
+}
+
+pub(crate) fn parse<I: IntoIterator<Item = String>>(args: I) -> CliArgs
+{
+    let mut cli = CliArgs::default();
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" => cli.dry_run = true,
+            "--menu" => cli.menu = true,
+            "--dispatch" => cli.dispatch = true,
+            "--master" => cli.master = Some(crate::master_server::configured_port()),
+            _ if arg.starts_with("--master=") => {
+                cli.master = arg["--master=".len()..].parse().ok().or_else(|| Some(crate::master_server::configured_port()));
+            }
+            _ => cli.rest.push(arg),
+        }
+    }
+    cli
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> CliArgs
+    {
+        parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn flags_are_taken_out_of_rest()
+    {
+        let cli = args(&["--dry-run", "iw1x://1.2.3.4:28960", "--menu", "+set", "name", "me"]);
+        assert!(cli.dry_run);
+        assert!(cli.menu);
+        assert!(!cli.dispatch);
+        assert_eq!(cli.master, None);
+        assert_eq!(cli.rest, ["iw1x://1.2.3.4:28960", "+set", "name", "me"]);
+    }
+
+    #[test]
+    fn master_takes_a_port()
+    {
+        assert_eq!(args(&["--master=20510"]).master, Some(20510));
+        assert!(args(&["--dispatch"]).dispatch);
+        assert!(args(&[]).rest.is_empty());
+    }
+}
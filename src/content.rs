@@ -0,0 +1,261 @@
+use relm4::{gtk, RelmWidgetExt};
+use gtk::prelude::*;
+
+use crate::{pk3::{self, Inventory}, query::ServerInfo, stats_window::fill_grid, uri::ServerUri, GameInfo};
+
+fn yes_no(present: bool) -> String
+{
+    String::from(if present { "yes" } else { "-" })
+}
+
+/// A pk3 a server has loaded, `folder` is only known from `sv_referencedPakNames`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ServerPak {
+    pub folder: Option<String>,
+    /// Lowercased, without `.pk3`
+    pub name: String,
+}
+
+/// Pk3s the server reports in `sv_referencedPakNames` (`main/zz_map awe/awe`),
+/// or `sv_pakNames` without folders. Only there when getstatus was asked.
+pub(crate) fn server_paks(info: &ServerInfo) -> Vec<ServerPak>
+{
+    let get = |key: &str| info.info.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str());
+    if let Some(referenced) = get("sv_referencedPakNames").filter(|v| !v.trim().is_empty()) {
+        return referenced.split_whitespace()
+            .map(|pak| match pak.rsplit_once('/') {
+                Some((folder, name)) => ServerPak { folder: Some(folder.to_string()), name: name.to_lowercase() },
+                None => ServerPak { folder: None, name: pak.to_lowercase() },
+            })
+            .collect();
+    }
+    get("sv_pakNames").unwrap_or_default().split_whitespace()
+        .map(|name| ServerPak { folder: None, name: name.to_lowercase() })
+        .collect()
+}
+
+/// What `game` lacks to play on the server behind `info`, one explanation
+/// per missing mod, pk3 or map. Blocking, it scans the install.
+pub(crate) fn missing_content(game: &GameInfo, info: &ServerInfo) -> Vec<String>
+{
+    let inventory = Inventory::scan(game);
+    let mod_folder = Some(info.fs_game.as_str()).filter(|m| !m.is_empty());
+    let mut missing = Vec::new();
+
+    if let Some(mod_folder) = mod_folder && !inventory.folders().iter().any(|f| f.eq_ignore_ascii_case(mod_folder)) {
+        missing.push(format!("The mod `{mod_folder}`, its pk3s go in a `{mod_folder}` folder next to the game"));
+    }
+
+    for pak in server_paks(info) {
+        let installed = inventory.pk3s.iter().any(|p| {
+            let stem = p.path.file_stem().unwrap_or_default().to_string_lossy().to_lowercase();
+            stem == pak.name && pak.folder.as_ref().is_none_or(|f| p.folder.eq_ignore_ascii_case(f))
+        });
+        if !installed {
+            let folder = pak.folder.map(|f| format!("{f}/")).unwrap_or_default();
+            missing.push(format!("The pk3 `{folder}{}.pk3` the server has loaded", pak.name));
+        }
+    }
+
+    let map = info.map.to_lowercase();
+    if !map.is_empty() && !inventory.loaded(game, mod_folder).any(|p| p.maps.contains(&map)) {
+        let elsewhere: Vec<String> = inventory.providers(&map).iter()
+            .map(|p| format!("{}/{}", p.folder, p.path.file_name().unwrap_or_default().to_string_lossy()))
+            .collect();
+        if elsewhere.is_empty() {
+            let folder = mod_folder.map(str::to_string)
+                .or_else(|| pk3::game_folders(game).last().map(|f| f.file_name().unwrap_or_default().to_string_lossy().to_string()))
+                .unwrap_or_default();
+            missing.push(format!("The map `{map}`, a pk3 with maps/mp/{map}.bsp in `{folder}`"));
+        }
+        else {
+            missing.push(format!("The map `{map}` is only in {}, which this server doesn't load", elsewhere.join(", ")));
+        }
+    }
+    missing
+}
+
+/// The server to launch with: `server` plus the mod `info` runs when the
+/// link doesn't name one and the mod folder exists here. Only the launch
+/// args get it, history keeps the link as given. A missing mod is left to
+/// `missing_content` to explain.
+pub(crate) fn launch_server(game: &GameInfo, server: &ServerUri, info: &ServerInfo) -> ServerUri
+{
+    let mut launch = server.clone();
+    if server.fs_game.is_some() || info.fs_game.is_empty() { return launch; }
+    let folder = pk3::mod_folders(game).iter()
+        .filter_map(|f| f.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .find(|n| n.eq_ignore_ascii_case(&info.fs_game));
+    match folder {
+        Some(folder) => {
+            println!("CoDLinux: {} runs {}, adding +set fs_game {}", server.address(), info.fs_game, folder);
+            launch.fs_game = Some(folder);
+        }
+        None => eprintln!("CoDLinux: {} runs the mod {}, which isn't installed", server.address(), info.fs_game),
+    }
+    launch
+}
+
+/// What the pk3s of `game` and its mods provide.
+pub(crate) fn show_content_window(app: &gtk::Application, game: &GameInfo)
+{
+    let inventory = Inventory::scan(game);
+
+    relm4::view! {
+        window = gtk::Window {
+            set_title: Some(&format!("Installed content: {}", game.name)),
+            set_default_size: (760, 520),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                #[name = "summary"]
+                gtk::Label {
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                },
+
+                gtk::Notebook {
+                    set_vexpand: true,
+
+                    append_page[Some(&gtk::Label::new(Some("Maps")))] = &gtk::ScrolledWindow {
+                        set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+                        #[name = "maps"]
+                        gtk::Grid {
+                            set_row_spacing: 4,
+                            set_column_spacing: 12,
+                            set_margin_all: 6,
+                        },
+                    },
+                    append_page[Some(&gtk::Label::new(Some("Archives")))] = &gtk::ScrolledWindow {
+                        set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+                        #[name = "archives"]
+                        gtk::Grid {
+                            set_row_spacing: 4,
+                            set_column_spacing: 12,
+                            set_margin_all: 6,
+                        },
+                    },
+                },
+            }
+        }
+    }
+
+    let mut summary_text = inventory.folders().iter()
+        .map(|folder| {
+            let pk3s: Vec<_> = inventory.pk3s.iter().filter(|p| p.folder == *folder).collect();
+            format!(
+                "<b>{}</b>: {} pk3s, {} maps, {} gametypes, {} string files",
+                gtk::glib::markup_escape_text(folder),
+                pk3s.len(),
+                pk3s.iter().map(|p| p.maps.len()).sum::<usize>(),
+                pk3s.iter().map(|p| p.gametypes.len()).sum::<usize>(),
+                pk3s.iter().map(|p| p.strings.len()).sum::<usize>(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !inventory.broken.is_empty() {
+        summary_text.push_str(&format!("\n{} unreadable pk3s, see the terminal output", inventory.broken.len()));
+    }
+    if summary_text.is_empty() {
+        summary_text = String::from("No pk3s found next to the game");
+    }
+    summary.set_markup(&summary_text);
+
+    // One row per map and pk3 providing it, later ones override earlier ones in game
+    let mut map_rows = Vec::new();
+    for pk3 in &inventory.pk3s {
+        for map in &pk3.maps {
+            map_rows.push(vec![
+                map.clone(),
+                pk3.folder.clone(),
+                pk3.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                yes_no(inventory.pk3s.iter().any(|p| p.arenas.contains(map))),
+                yes_no(inventory.pk3s.iter().any(|p| p.levelshots.contains(map))),
+            ]);
+        }
+    }
+    map_rows.sort();
+    fill_grid(&maps, &["Map", "Folder", "pk3", "Arena", "Levelshot"], map_rows.into_iter());
+
+    fill_grid(
+        &archives,
+        &["Folder", "pk3", "Files", "Size", "Maps", "Gametypes", "Strings", "Levelshots"],
+        inventory.pk3s.iter().map(|p| vec![
+            p.folder.clone(),
+            p.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            p.files.to_string(),
+            format!("{:.1} MB", p.size as f64 / (1024.0 * 1024.0)),
+            p.maps.len().to_string(),
+            p.gametypes.len().to_string(),
+            p.strings.len().to_string(),
+            p.levelshots.len().to_string(),
+        ]),
+    );
+
+    app.add_window(&window);
+    window.set_visible(true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query;
+
+    fn info(fields: &str) -> ServerInfo
+    {
+        ServerInfo::from_info(std::net::SocketAddr::from(([127, 0, 0, 1], 28960)), 0, query::parse_info_string(fields))
+    }
+
+    #[test]
+    fn pak_lists()
+    {
+        let referenced = info("\\sv_referencedPakNames\\main/pak0 AWE/awe_Main\\sv_pakNames\\pak0 awe_main zz_other");
+        assert_eq!(server_paks(&referenced), [
+            ServerPak { folder: Some(String::from("main")), name: String::from("pak0") },
+            ServerPak { folder: Some(String::from("AWE")), name: String::from("awe_main") },
+        ]);
+        let names_only = info("\\sv_pakNames\\pak0 zz_other");
+        assert_eq!(server_paks(&names_only)[1], ServerPak { folder: None, name: String::from("zz_other") });
+        assert!(server_paks(&info("\\mapname\\mp_harbor")).is_empty());
+    }
+
+    #[test]
+    fn missing_maps_mods_and_paks()
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-content-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        pk3::write_test_pk3(&dir.join("main").join("pak0.pk3"), &["maps/mp/mp_harbor.bsp"]);
+        pk3::write_test_pk3(&dir.join("awe").join("awe_main.pk3"), &["maps/mp/mp_awe.bsp"]);
+        let game = pk3::test_game(&dir, "Call of Duty");
+
+        assert!(missing_content(&game, &info("\\mapname\\mp_harbor\\sv_referencedPakNames\\main/pak0")).is_empty());
+        assert!(missing_content(&game, &info("\\mapname\\MP_AWE\\fs_game\\awe\\sv_pakNames\\pak0 awe_main")).is_empty());
+
+        let missing = missing_content(&game, &info("\\mapname\\mp_custom\\sv_referencedPakNames\\main/pak0 main/zz_custom"));
+        assert_eq!(missing.len(), 2);
+        assert!(missing[0].contains("main/zz_custom.pk3"));
+        assert!(missing[1].contains("maps/mp/mp_custom.bsp in `main`"));
+
+        // The map is there, but only for a mod this server doesn't run
+        let missing = missing_content(&game, &info("\\mapname\\mp_awe"));
+        assert_eq!(missing, ["The map `mp_awe` is only in awe/awe_main.pk3, which this server doesn't load"]);
+
+        let missing = missing_content(&game, &info("\\mapname\\mp_harbor\\fs_game\\pam"));
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].starts_with("The mod `pam`"));
+
+        // Installed mods go into the launch args only, a link's own always wins
+        let link = crate::uri::parse("cod1://127.0.0.1:28960").unwrap();
+        assert_eq!(launch_server(&game, &link, &info("\\fs_game\\AWE")).fs_game.as_deref(), Some("awe"));
+        assert_eq!(launch_server(&game, &link, &info("\\fs_game\\pam")).fs_game, None);
+        let modded = crate::uri::parse("cod1://127.0.0.1:28960?fs_game=mine").unwrap();
+        assert_eq!(launch_server(&game, &modded, &info("\\fs_game\\awe")).fs_game.as_deref(), Some("mine"));
+        assert_eq!(link.fs_game, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
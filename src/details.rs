@@ -0,0 +1,320 @@
+use relm4::{gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::glib;
+
+use std::{cell::RefCell, collections::VecDeque, net::SocketAddr, rc::Rc};
+
+use crate::{browser, query::{self, ServerInfo, ServerStatus}, util};
+
+/// Samples kept for the ping/player graph.
+static HISTORY_LEN: usize = 60;
+
+/// Live view of one server, refreshed with `getstatus`.
+pub struct ServerDetails {
+    scheme: String,
+    addr: SocketAddr,
+    status: Option<ServerStatus>,
+    error: Option<String>,
+    busy: bool,
+    /// (ping, players) per poll, oldest first
+    history: Rc<RefCell<VecDeque<(u32, u32)>>>,
+    timer: Option<glib::SourceId>,
+}
+
+#[derive(Debug)]
+pub enum DetailsMsg {
+    Poll,
+    Connect,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum DetailsOutput {
+    Connect(String),
+}
+
+#[derive(Debug)]
+pub enum DetailsCmd {
+    Status(Option<ServerStatus>),
+}
+
+#[relm4::component(pub)]
+impl Component for ServerDetails {
+    type Init = (String, SocketAddr);
+    type Input = DetailsMsg;
+    type Output = DetailsOutput;
+    type CommandOutput = DetailsCmd;
+
+    view! {
+        gtk::Window {
+            #[watch]
+            set_title: Some(&format!("Server {}", model.addr)),
+            set_default_size: (480, 560),
+            connect_close_request[sender] => move |_| {
+                sender.input(DetailsMsg::Close);
+                glib::Propagation::Proceed
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Label {
+                    #[watch]
+                    set_markup: &model.status.as_ref()
+                        .map(|s| format!("<big><b>{}</b></big>", query::colour_markup(s.cvar("sv_hostname"))))
+                        .unwrap_or_else(|| model.addr.to_string()),
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: gtk::pango::EllipsizeMode::End,
+                },
+                gtk::Label {
+                    #[watch]
+                    set_text: &model.summary(),
+                    set_halign: gtk::Align::Start,
+                },
+
+                #[name = "graph"]
+                gtk::DrawingArea {
+                    set_content_height: 80,
+                    set_hexpand: true,
+                },
+                gtk::Label {
+                    set_markup: "<small><span foreground=\"#3070e0\">ping</span>  <span foreground=\"#30a030\">players</span></small>",
+                    set_halign: gtk::Align::End,
+                },
+
+                gtk::Label {
+                    set_markup: "<b>Players</b>",
+                    set_halign: gtk::Align::Start,
+                },
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                    #[name = "players"]
+                    gtk::Grid {
+                        set_row_spacing: 2,
+                        set_column_spacing: 12,
+                    },
+                },
+
+                gtk::Expander {
+                    set_label: Some("Server variables"),
+
+                    #[wrap(Some)]
+                    set_child = &gtk::ScrolledWindow {
+                        set_min_content_height: 120,
+                        set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                        #[name = "cvars"]
+                        gtk::Grid {
+                            set_row_spacing: 2,
+                            set_column_spacing: 12,
+                        },
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+                    set_halign: gtk::Align::End,
+
+                    gtk::Spinner {
+                        #[watch]
+                        set_spinning: model.busy,
+                    },
+                    gtk::Button {
+                        set_label: "Connect",
+                        connect_clicked => DetailsMsg::Connect,
+                    },
+                },
+            }
+        }
+    }
+
+    fn init((scheme, addr): Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let interval = util::load_setting("status_poll_interval").unwrap_or_default().parse::<u32>().unwrap_or(5).max(1);
+        let input = sender.input_sender().clone();
+        let timer = glib::timeout_add_seconds_local(interval, move || {
+            if input.send(DetailsMsg::Poll).is_ok() {
+                glib::ControlFlow::Continue
+            }
+            else {
+                glib::ControlFlow::Break
+            }
+        });
+
+        let model = ServerDetails {
+            scheme,
+            addr,
+            status: None,
+            error: None,
+            busy: false,
+            history: Rc::new(RefCell::new(VecDeque::new())),
+            timer: Some(timer),
+        };
+        let widgets = view_output!();
+
+        let history = model.history.clone();
+        widgets.graph.set_draw_func(move |_, cr, width, height| {
+            draw_history(&history.borrow(), cr, width as f64, height as f64);
+        });
+
+        sender.input(DetailsMsg::Poll);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            DetailsMsg::Poll => {
+                if self.busy || self.timer.is_none() { return; }
+                self.busy = true;
+                let addr = self.addr;
+                sender.spawn_oneshot_command(move || {
+                    DetailsCmd::Status(query::query_status(addr, query::DEFAULT_TIMEOUT).unwrap_or_else(|e| {
+                        eprintln!("CoDLinux: getstatus {addr} failed: {e}");
+                        None
+                    }))
+                });
+            }
+            DetailsMsg::Connect => {
+                let mut info = ServerInfo::from_info(self.addr, 0, Default::default());
+                if let Some(status) = &self.status {
+                    info = ServerInfo::from_info(self.addr, status.ping, status.cvars.clone());
+                }
+                sender.output(DetailsOutput::Connect(browser::server_link(&self.scheme, &info))).unwrap();
+            }
+            DetailsMsg::Close => {
+                if let Some(timer) = self.timer.take() {
+                    timer.remove();
+                }
+            }
+        }
+    }
+
+    fn update_cmd_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::CommandOutput, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            DetailsCmd::Status(status) => {
+                self.busy = false;
+                match status {
+                    Some(status) => {
+                        let mut history = self.history.borrow_mut();
+                        history.push_back((status.ping, status.players.len() as u32));
+                        while history.len() > HISTORY_LEN {
+                            history.pop_front();
+                        }
+                        drop(history);
+
+                        fill_players(&widgets.players, &status);
+                        fill_cvars(&widgets.cvars, &status);
+                        widgets.graph.queue_draw();
+                        self.error = None;
+                        self.status = Some(status);
+                    }
+                    None => self.error = Some(String::from("No answer")),
+                }
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+}
+
+impl ServerDetails {
+    fn summary(&self) -> String
+    {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        let Some(status) = &self.status else {
+            return String::from("Querying...");
+        };
+        let gametype = if status.cvars.contains_key("g_gametype") { status.cvar("g_gametype") } else { status.cvar("gametype") };
+        format!(
+            "{}  •  {}  •  {}/{} players  •  {} ms",
+            status.cvar("mapname"),
+            gametype,
+            status.players.len(),
+            status.cvar("sv_maxclients"),
+            status.ping,
+        )
+    }
+}
+
+fn clear_grid(grid: &gtk::Grid)
+{
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+}
+
+fn fill_players(grid: &gtk::Grid, status: &ServerStatus)
+{
+    clear_grid(grid);
+    for (col, title) in ["Name", "Score", "Ping"].iter().enumerate() {
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!("<b>{title}</b>"));
+        label.set_xalign(0.0);
+        grid.attach(&label, col as i32, 0, 1, 1);
+    }
+
+    let mut players = status.players.clone();
+    players.sort_by_key(|p| std::cmp::Reverse(p.score));
+    for (row, player) in players.iter().enumerate() {
+        let row = row as i32 + 1;
+        let name = gtk::Label::new(None);
+        name.set_markup(&query::colour_markup(&player.name));
+        name.set_xalign(0.0);
+        name.set_hexpand(true);
+        grid.attach(&name, 0, row, 1, 1);
+        grid.attach(&gtk::Label::new(Some(&player.score.to_string())), 1, row, 1, 1);
+        grid.attach(&gtk::Label::new(Some(&player.ping.to_string())), 2, row, 1, 1);
+    }
+}
+
+fn fill_cvars(grid: &gtk::Grid, status: &ServerStatus)
+{
+    clear_grid(grid);
+    for (row, (key, value)) in status.cvars.iter().enumerate() {
+        let key = gtk::Label::new(Some(key));
+        key.set_xalign(0.0);
+        key.set_selectable(true);
+        let value_label = gtk::Label::new(None);
+        value_label.set_markup(&query::colour_markup(value));
+        value_label.set_xalign(0.0);
+        value_label.set_selectable(true);
+        grid.attach(&key, 0, row as i32, 1, 1);
+        grid.attach(&value_label, 1, row as i32, 1, 1);
+    }
+}
+
+fn draw_history(history: &VecDeque<(u32, u32)>, cr: &gtk::cairo::Context, width: f64, height: f64)
+{
+    if history.len() < 2 {
+        return;
+    }
+    let max_ping = history.iter().map(|(p, _)| *p).max().unwrap_or(1).max(1) as f64;
+    let max_players = history.iter().map(|(_, n)| *n).max().unwrap_or(1).max(1) as f64;
+    let step = width / (HISTORY_LEN - 1) as f64;
+    let offset = (HISTORY_LEN - history.len()) as f64 * step;
+
+    // Ping in blue, players in green, each scaled to its own maximum
+    let series: [((f64, f64, f64), Vec<f64>); 2] = [
+        ((0.19, 0.44, 0.88), history.iter().map(|(p, _)| *p as f64 / max_ping).collect()),
+        ((0.19, 0.63, 0.19), history.iter().map(|(_, n)| *n as f64 / max_players).collect()),
+    ];
+    for ((r, g, b), values) in series {
+        cr.set_source_rgb(r, g, b);
+        cr.set_line_width(1.5);
+        for (i, value) in values.iter().enumerate() {
+            let x = offset + i as f64 * step;
+            let y = height - 2.0 - value * (height - 4.0);
+            if i == 0 { cr.move_to(x, y); } else { cr.line_to(x, y); }
+        }
+        let _ = cr.stroke();
+    }
+}
@@ -0,0 +1,496 @@
+use relm4::{gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt};
+use gtk::prelude::*;
+
+use std::{collections::{HashMap, HashSet}, fs::{self, File}, io::{self, Write}, path::PathBuf, time::{Duration, SystemTime}};
+use chrono::{DateTime, Local};
+
+use crate::{mods, pk3::{self, Inventory}, util, GameInfo};
+
+/// Quarantined pk3s are moved here, inside their folder.
+pub(crate) static QUARANTINE_DIR: &str = "codlinux_quarantine";
+
+/// Files this much newer than `main/pak0.pk3` didn't come with the install.
+static INSTALL_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn conf_dir() -> PathBuf
+{
+    util::my_exe_path().unwrap().join("codlinux_conf")
+}
+
+/// md5s of the stock pk3s, built in. Same format as `stock_pk3s.md5`.
+static BUILTIN_STOCK_MD5S: &str = include_str!("../assets/stock_pk3s.md5");
+
+/// `md5sum` output, `<md5>  <path>` per line, as md5 to lowercase file name.
+fn parse_md5s(text: &str) -> HashMap<String, String>
+{
+    text.lines()
+        .filter_map(|l| l.split_once(char::is_whitespace))
+        .filter(|(h, _)| h.len() == 32 && h.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|(h, path)| {
+            // `md5sum -b` marks binary files with `*`
+            let path = path.trim().trim_start_matches('*');
+            (h.to_lowercase(), path.rsplit('/').next().unwrap_or(path).to_lowercase())
+        })
+        .collect()
+}
+
+/// md5s built in and from `stock_pk3s.md5`, with the file names they belong to.
+pub(crate) fn stock_hashes() -> HashMap<String, String>
+{
+    let configured = fs::read_to_string(conf_dir().join("stock_pk3s.md5")).unwrap_or_default();
+    let mut hashes = parse_md5s(BUILTIN_STOCK_MD5S);
+    hashes.extend(parse_md5s(&configured));
+    hashes
+}
+
+/// `<folder>/<file>.pk3` of everything installed from the mod manager or kept here.
+pub(crate) fn load_user_installed() -> HashSet<String>
+{
+    fs::read_to_string(conf_dir().join("user_pk3s.txt")).unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+pub(crate) fn record_user_installed(pk3s: &[String]) -> io::Result<()>
+{
+    let known = load_user_installed();
+    let mut file = File::options().create(true).append(true).open(conf_dir().join("user_pk3s.txt"))?;
+    for pk3 in pk3s.iter().filter(|p| !known.contains(*p)) {
+        writeln!(file, "{pk3}")?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Origin {
+    /// Came with the game, by hash or install time
+    Stock,
+    /// Installed from the mod manager or kept on purpose
+    User,
+    /// Anything newer, most likely pulled from a server
+    Downloaded,
+}
+
+impl Origin {
+    pub(crate) fn label(self) -> &'static str
+    {
+        match self {
+            Origin::Stock => "Stock",
+            Origin::User => "Installed",
+            Origin::Downloaded => "Downloaded",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Pk3File {
+    pub path: PathBuf,
+    /// `main`, `uo` or a mod folder
+    pub folder: String,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub origin: Origin,
+    pub quarantined: bool,
+    pub maps: Vec<String>,
+}
+
+impl Pk3File {
+    /// How `user_pk3s.txt` refers to it, wherever it was moved.
+    pub(crate) fn key(&self) -> String
+    {
+        format!("{}/{}", self.folder, self.path.file_name().unwrap_or_default().to_string_lossy())
+    }
+}
+
+/// True for the `pak*.pk3` the game ships in `main` and `uo`.
+fn is_stock_name(file: &Pk3File, own: &[PathBuf]) -> bool
+{
+    let in_own = own.iter().any(|f| f.file_name().is_some_and(|n| n.to_string_lossy() == file.folder));
+    in_own && file.path.file_name().is_some_and(|n| n.to_string_lossy().to_lowercase().starts_with("pak"))
+}
+
+/// Where a pk3 came from. Anything matching a stock md5 is stock whatever
+/// its date. The name only counts for pk3s no stock md5 is known for: a
+/// `pak*.pk3` whose md5 is known but doesn't match came from elsewhere.
+fn classify(file: &Pk3File, own: &[PathBuf], stock: &HashMap<String, String>, user: &HashSet<String>, installed: Option<SystemTime>) -> Origin
+{
+    if user.contains(&file.key()) {
+        return Origin::User;
+    }
+    let name = file.path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    let hash_known = stock.values().any(|n| *n == name);
+    let hash_matches = !stock.is_empty() && util::md5_file(&file.path).is_ok_and(|h| stock.contains_key(&h));
+    let by_name = !hash_known && is_stock_name(file, own);
+    let by_date = matches!((file.modified, installed), (Some(m), Some(i)) if m <= i + INSTALL_WINDOW);
+    if hash_matches || by_name || by_date {
+        Origin::Stock
+    }
+    else {
+        Origin::Downloaded
+    }
+}
+
+/// Every pk3 in the game's folders, quarantined ones included. Blocking:
+/// with a stock hash list every pk3 gets hashed.
+pub(crate) fn scan(game: &GameInfo) -> Vec<Pk3File>
+{
+    let stock = stock_hashes();
+    let user = load_user_installed();
+    let inventory = Inventory::scan(game);
+    let own = pk3::game_folders(game);
+    // pak0.pk3 is as old as the install
+    let installed = own.first()
+        .and_then(|main| fs::metadata(main.join("pak0.pk3")).ok())
+        .or_else(|| fs::metadata(&game.path).ok())
+        .and_then(|m| m.modified().ok());
+
+    let mut files = Vec::new();
+    for folder in mods::managed_folders(game) {
+        let name = folder.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let loaded = pk3::pk3s_in(&folder).into_iter().map(|p| (p, false));
+        let quarantined = pk3::pk3s_in(&folder.join(QUARANTINE_DIR)).into_iter().map(|p| (p, true));
+        for (path, quarantined) in loaded.chain(quarantined) {
+            let metadata = fs::metadata(&path).ok();
+            let mut file = Pk3File {
+                folder: name.clone(),
+                size: metadata.as_ref().map_or(0, |m| m.len()),
+                modified: metadata.and_then(|m| m.modified().ok()),
+                origin: Origin::Downloaded,
+                quarantined,
+                maps: inventory.pk3s.iter().find(|p| p.path == path).map(|p| p.maps.clone()).unwrap_or_default(),
+                path,
+            };
+            file.origin = classify(&file, &own, &stock, &user, installed);
+            // Quarantined pk3s aren't loaded, so they don't show up in the index
+            if quarantined && file.maps.is_empty() {
+                file.maps = pk3::entries(&file.path).map(|e| {
+                    e.iter()
+                        .filter_map(|e| e.name.to_lowercase().strip_prefix("maps/mp/")?.strip_suffix(".bsp").map(str::to_string))
+                        .filter(|m| !m.contains('/'))
+                        .collect()
+                }).unwrap_or_default();
+            }
+            files.push(file);
+        }
+    }
+    files
+}
+
+/// Downloaded, stock and user-installed pk3s of one game.
+pub(crate) struct DownloadsWindow {
+    game: GameInfo,
+    files: Vec<Pk3File>,
+    scanning: bool,
+    /// Something changed while scanning, scan again once it's done
+    rescan_queued: bool,
+    show_all: bool,
+    status: String,
+}
+
+#[derive(Debug)]
+pub(crate) enum DownloadsMsg {
+    Rescan,
+    ShowAll(bool),
+    Quarantine(PathBuf),
+    Restore(PathBuf),
+    /// Marks a pk3 as installed on purpose
+    Keep(String),
+    AskDelete(PathBuf),
+    Delete(PathBuf),
+}
+
+#[derive(Debug)]
+pub(crate) enum DownloadsCmd {
+    Scanned(Vec<Pk3File>),
+}
+
+#[relm4::component(pub(crate))]
+impl Component for DownloadsWindow {
+    type Init = GameInfo;
+    type Input = DownloadsMsg;
+    type Output = ();
+    type CommandOutput = DownloadsCmd;
+
+    view! {
+        gtk::Window {
+            set_title: Some(&format!("Downloaded pk3s: {}", model.game.name)),
+            set_default_size: (820, 480),
+            // Reused by the mod manager, which rescans when showing it again
+            set_hide_on_close: true,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::CheckButton {
+                        set_label: Some("Show stock and installed pk3s too"),
+                        set_active: model.show_all,
+                        connect_toggled[sender] => move |btn| {
+                            sender.input(DownloadsMsg::ShowAll(btn.is_active()));
+                        },
+                    },
+                    gtk::Spinner {
+                        #[watch]
+                        set_spinning: model.scanning,
+                        set_hexpand: true,
+                        set_halign: gtk::Align::End,
+                    },
+                    gtk::Button {
+                        set_icon_name: "view-refresh",
+                        set_tooltip_text: Some("Rescan"),
+                        #[watch]
+                        set_sensitive: !model.scanning,
+                        connect_clicked => DownloadsMsg::Rescan,
+                    },
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                    #[name = "grid"]
+                    gtk::Grid {
+                        set_row_spacing: 4,
+                        set_column_spacing: 12,
+                    },
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_text: &model.status,
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                },
+            }
+        }
+    }
+
+    fn init(game: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let model = DownloadsWindow {
+            game,
+            files: Vec::new(),
+            scanning: false,
+            rescan_queued: false,
+            show_all: false,
+            status: String::new(),
+        };
+        let widgets = view_output!();
+        sender.input(DownloadsMsg::Rescan);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::Input, sender: ComponentSender<Self>, root: &Self::Root)
+    {
+        let moved = match msg {
+            DownloadsMsg::Rescan => Ok(String::new()),
+            DownloadsMsg::ShowAll(show_all) => {
+                self.show_all = show_all;
+                self.fill(&widgets.grid, &sender);
+                self.update_view(widgets, sender);
+                return;
+            }
+            DownloadsMsg::Quarantine(path) => mods::move_pk3(&path, Some(QUARANTINE_DIR)).map(|_| format!("Quarantined {}", path.display())),
+            DownloadsMsg::Restore(path) => mods::move_pk3(&path, None).map(|_| format!("Restored {}", path.display())),
+            DownloadsMsg::Keep(key) => record_user_installed(std::slice::from_ref(&key)).map(|_| format!("Keeping {key}")),
+            DownloadsMsg::AskDelete(path) => {
+                let dialog = gtk::AlertDialog::builder()
+                    .modal(true)
+                    .message(format!("Delete {}?", path.file_name().unwrap_or_default().to_string_lossy()))
+                    .detail(format!("{} will be removed for good. Quarantine keeps it around instead.", path.display()))
+                    .buttons(["Delete", "Cancel"])
+                    .default_button(1)
+                    .cancel_button(1)
+                    .build();
+                let input = sender.input_sender().clone();
+                dialog.choose(Some(root), None::<&gtk::gio::Cancellable>, move |choice| {
+                    if matches!(choice, Ok(0)) {
+                        input.emit(DownloadsMsg::Delete(path.clone()));
+                    }
+                });
+                return;
+            }
+            DownloadsMsg::Delete(path) => fs::remove_file(&path).map(|_| format!("Deleted {}", path.display())),
+        };
+        match moved {
+            Ok(status) => {
+                if !status.is_empty() {
+                    println!("CoDLinux: {status}");
+                }
+                self.status = status;
+            }
+            Err(e) => self.status = format!("Failed: {e}"),
+        }
+
+        self.rescan(&sender);
+        self.update_view(widgets, sender);
+    }
+
+    fn update_cmd_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::CommandOutput, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            DownloadsCmd::Scanned(files) => {
+                self.scanning = false;
+                self.files = files;
+                if std::mem::take(&mut self.rescan_queued) {
+                    self.rescan(&sender);
+                }
+            }
+        }
+        self.fill(&widgets.grid, &sender);
+        self.update_view(widgets, sender);
+    }
+}
+
+impl DownloadsWindow {
+    fn rescan(&mut self, sender: &ComponentSender<Self>)
+    {
+        if self.scanning {
+            self.rescan_queued = true;
+            return;
+        }
+        self.scanning = true;
+        let game = self.game.clone();
+        sender.spawn_oneshot_command(move || DownloadsCmd::Scanned(scan(&game)));
+    }
+
+    fn fill(&self, grid: &gtk::Grid, sender: &ComponentSender<Self>)
+    {
+        while let Some(child) = grid.first_child() {
+            grid.remove(&child);
+        }
+        for (col, title) in ["pk3", "Folder", "Origin", "Size", "Modified", "Maps", ""].iter().enumerate() {
+            let label = gtk::Label::new(None);
+            label.set_markup(&format!("<b>{title}</b>"));
+            label.set_xalign(0.0);
+            grid.attach(&label, col as i32, 0, 1, 1);
+        }
+
+        let shown = self.files.iter().filter(|f| self.show_all || f.origin == Origin::Downloaded || f.quarantined);
+        for (row, file) in shown.enumerate() {
+            let row = row as i32 + 1;
+            let origin = if file.quarantined { format!("{} (quarantined)", file.origin.label()) } else { file.origin.label().to_string() };
+            let cells = [
+                file.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                file.folder.clone(),
+                origin,
+                format!("{:.1} MB", file.size as f64 / (1024.0 * 1024.0)),
+                file.modified.map(|m| DateTime::<Local>::from(m).format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default(),
+                file.maps.join(", "),
+            ];
+            for (col, text) in cells.iter().enumerate() {
+                let label = gtk::Label::new(Some(text));
+                label.set_xalign(0.0);
+                label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+                label.set_max_width_chars(if col == 5 { 30 } else { 24 });
+                label.set_tooltip_text(Some(text));
+                grid.attach(&label, col as i32, row, 1, 1);
+            }
+
+            let actions = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+            let input = sender.input_sender().clone();
+            let path = file.path.clone();
+            let (label, msg): (&str, fn(PathBuf) -> DownloadsMsg) = if file.quarantined {
+                ("Restore", DownloadsMsg::Restore)
+            } else {
+                ("Quarantine", DownloadsMsg::Quarantine)
+            };
+            let button = gtk::Button::with_label(label);
+            button.connect_clicked(gtk::glib::clone!(#[strong] input, #[strong] path, move |_| input.emit(msg(path.clone()))));
+            actions.append(&button);
+
+            if file.origin == Origin::Downloaded {
+                let keep = gtk::Button::with_label("Keep");
+                keep.set_tooltip_text(Some("Installed on purpose, stop listing it as downloaded"));
+                let key = file.key();
+                keep.connect_clicked(gtk::glib::clone!(#[strong] input, move |_| input.emit(DownloadsMsg::Keep(key.clone()))));
+                actions.append(&keep);
+            }
+
+            let delete = gtk::Button::from_icon_name("user-trash");
+            delete.set_tooltip_text(Some("Delete"));
+            delete.connect_clicked(move |_| input.emit(DownloadsMsg::AskDelete(path.clone())));
+            actions.append(&delete);
+            grid.attach(&actions, 6, row, 1, 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stock_paks_are_never_downloads()
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-downloads-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let game = pk3::test_game(&dir, "United Offensive");
+        let own = pk3::game_folders(&game);
+        let installed = SystemTime::now() - INSTALL_WINDOW * 30;
+        let file = |folder: &str, name: &str, contents: &[u8], modified: SystemTime| {
+            let path = dir.join(folder).join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            Pk3File {
+                path,
+                folder: folder.to_string(),
+                size: contents.len() as u64,
+                modified: Some(modified),
+                origin: Origin::Downloaded,
+                quarantined: false,
+                maps: Vec::new(),
+            }
+        };
+        let now = SystemTime::now();
+        let patched = file("uo", "pakuo03.pk3", b"patch", now);
+        let renamed = file("main", "localized_english_pak1.pk3", b"stock", now);
+        let downloaded = file("main", "zz_custom.pk3", b"custom", now);
+        let old = file("main", "zz_old.pk3", b"old", installed + Duration::from_secs(60));
+        let in_mod = file("awe", "pak_awe.pk3", b"awe", now);
+        // Named like a stock pak, but its md5 is known and this isn't it
+        let impostor = file("main", "pak5.pk3", b"from a server", now);
+
+        let stock = parse_md5s(&format!(
+            "{}  main/localized_english_pak1.pk3\n{:x} *main/pak5.pk3\n",
+            util::md5_file(&renamed.path).unwrap(),
+            md5::compute(b"the real pak5"),
+        ));
+        let none = HashSet::new();
+        let origin = |f: &Pk3File, user: &HashSet<String>| classify(f, &own, &stock, user, Some(installed));
+
+        // Patched long after the install, still the game's own
+        assert_eq!(origin(&patched, &none), Origin::Stock);
+        assert_eq!(origin(&renamed, &none), Origin::Stock);
+        assert_eq!(origin(&old, &none), Origin::Stock);
+        assert_eq!(origin(&downloaded, &none), Origin::Downloaded);
+        assert_eq!(origin(&downloaded, &[downloaded.key()].into()), Origin::User);
+        // A mod's pak isn't one the game shipped
+        assert_eq!(origin(&in_mod, &none), Origin::Downloaded);
+        assert_eq!(origin(&impostor, &none), Origin::Downloaded);
+        // The hash decides without a date to go by
+        assert_eq!(classify(&renamed, &own, &stock, &none, None), Origin::Stock);
+        assert_eq!(classify(&downloaded, &own, &stock, &none, None), Origin::Downloaded);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn md5sum_lines()
+    {
+        let hashes = parse_md5s("# comment\n0123456789ABCDEF0123456789abcdef  main/PAK0.pk3\n0123456789abcdef0123456789abcdee *uo/pakuo00.pk3\nnot a hash  x.pk3\n");
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes["0123456789abcdef0123456789abcdef"], "pak0.pk3");
+        assert_eq!(hashes["0123456789abcdef0123456789abcdee"], "pakuo00.pk3");
+        assert!(parse_md5s(BUILTIN_STOCK_MD5S).values().all(|n| n.ends_with(".pk3")));
+    }
+}
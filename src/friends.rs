@@ -0,0 +1,139 @@
+use relm4::{gtk, RelmWidgetExt};
+use gtk::prelude::*;
+
+use std::{collections::HashSet, fs, io, net::SocketAddr, path::PathBuf};
+
+use crate::{query::{self, ServerStatus}, servers::Favourite, util};
+
+/// A watched player seen on a favourite server.
+#[derive(Debug, Clone)]
+pub(crate) struct Sighting {
+    /// As the server shows it, colour codes stripped
+    pub player: String,
+    pub favourite: Favourite,
+    pub addr: SocketAddr,
+}
+
+fn list_file() -> PathBuf
+{
+    util::my_exe_path().unwrap().join("codlinux_conf").join("friends.txt")
+}
+
+/// Player names to watch for, one per line in `friends.txt`.
+pub(crate) fn load_watch_list() -> Vec<String>
+{
+    fs::read_to_string(list_file())
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+pub(crate) fn save_watch_list(names: &[String]) -> io::Result<()>
+{
+    let mut contents = names.join("\n");
+    contents.push('\n');
+    fs::write(list_file(), contents)
+}
+
+/// Names match without colour codes and case-insensitively.
+pub(crate) fn normalise(name: &str) -> String
+{
+    query::strip_colours(name).trim().to_lowercase()
+}
+
+/// Seconds between two rounds of `getstatus`, `friend_watch_interval` in codlinux.cfg.
+pub(crate) fn interval() -> u32
+{
+    util::load_setting("friend_watch_interval").unwrap_or_default().parse::<u32>().unwrap_or(60).max(10)
+}
+
+/// Watched players in `statuses`, `favourites` gives the link for each server.
+pub(crate) fn find_friends(statuses: &[ServerStatus], favourites: &[(SocketAddr, Favourite)], watch: &[String]) -> Vec<Sighting>
+{
+    let watch: HashSet<String> = watch.iter().map(|n| normalise(n)).collect();
+    let mut found = Vec::new();
+    for status in statuses {
+        let Some((_, favourite)) = favourites.iter().find(|(addr, _)| *addr == status.addr) else { continue; };
+        for player in &status.players {
+            if watch.contains(&normalise(&player.name)) {
+                found.push(Sighting {
+                    player: query::strip_colours(&player.name).trim().to_string(),
+                    favourite: favourite.clone(),
+                    addr: status.addr,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// Queries every favourite and returns who's online. Blocking.
+pub(crate) fn scan(favourites: Vec<Favourite>, watch: &[String]) -> Vec<Sighting>
+{
+    let favourites: Vec<(SocketAddr, Favourite)> = favourites.into_iter()
+        .filter_map(|f| Some((query::resolve(&f.server.address()).ok()?, f)))
+        .collect();
+    let addrs: Vec<SocketAddr> = favourites.iter().map(|(addr, _)| *addr).collect();
+    if addrs.is_empty() {
+        return Vec::new();
+    }
+    let statuses = query::query_statuses(&addrs, query::DEFAULT_TIMEOUT).unwrap_or_else(|e| {
+        eprintln!("CoDLinux: Friend watch query failed: {e}");
+        Vec::new()
+    });
+    find_friends(&statuses, &favourites, watch)
+}
+
+pub(crate) fn show_watch_window(app: &gtk::Application)
+{
+    relm4::view! {
+        window = gtk::Window {
+            set_title: Some("Friend Watch"),
+            set_default_size: (360, 360),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Label {
+                    set_text: "Player names to watch for on favourite servers, one per line. Colour codes and case don't matter.",
+                    set_wrap: true,
+                    set_xalign: 0.0,
+                },
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                    #[name = "names_view"]
+                    gtk::TextView {
+                        set_monospace: true,
+                    },
+                },
+                gtk::Button {
+                    set_label: "Save",
+                    set_halign: gtk::Align::End,
+                    connect_clicked[names_view, window] => move |_| {
+                        let buffer = names_view.buffer();
+                        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                        let names: Vec<String> = text.lines()
+                            .map(|l| l.trim().to_string())
+                            .filter(|l| !l.is_empty())
+                            .collect();
+                        match save_watch_list(&names) {
+                            Ok(()) => window.close(),
+                            Err(e) => eprintln!("CoDLinux: Failed to save the watch list: {e}"),
+                        }
+                    },
+                },
+            }
+        }
+    }
+
+    names_view.buffer().set_text(&load_watch_list().join("\n"));
+
+    app.add_window(&window);
+    window.set_visible(true);
+}
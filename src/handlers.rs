@@ -0,0 +1,232 @@
+use relm4::{gtk, RelmWidgetExt};
+use gtk::prelude::*;
+
+use crate::{registry::{self, Registry}, util, GameInfo};
+
+/// Link schemes codlinux registers itself for.
+pub(crate) static SCHEMES: [&str; 4] = ["iw1x", "t1x", "cod1", "coduo"];
+
+/// Games that can open links of `scheme`, preferred first. Client
+/// extensions come before the vanilla executables they run on.
+pub(crate) fn candidates(scheme: &str) -> &'static [&'static str]
+{
+    match scheme {
+        "iw1x" | "cod1" => &["IW1X", "Call of Duty", "CoDMP"],
+        "t1x" | "coduo" => &["T1X", "United Offensive", "CoDUOMP"],
+        _ => &[],
+    }
+}
+
+fn setting_key(scheme: &str) -> String
+{
+    format!("uri_handler_{scheme}")
+}
+
+/// Game chosen in settings for `scheme`, empty for automatic.
+pub(crate) fn chosen_handler(scheme: &str) -> String
+{
+    util::load_setting(&setting_key(scheme)).unwrap_or_default()
+}
+
+pub(crate) fn set_chosen_handler(scheme: &str, game: &str) -> std::io::Result<()>
+{
+    util::save_setting(&setting_key(scheme), game)
+}
+
+/// Picks the game that opens links of `scheme`: the one chosen in
+/// settings if it's still installed, otherwise the first candidate found.
+pub(crate) fn handler_for<'a>(scheme: &str, games: &'a [GameInfo]) -> Option<&'a GameInfo>
+{
+    let chosen = chosen_handler(scheme);
+    if !chosen.is_empty() {
+        if let Some(game) = games.iter().find(|g| g.name == chosen && candidates(scheme).contains(&g.name.as_str())) {
+            return Some(game);
+        }
+        println!("CoDLinux: `{chosen}` is not available for {scheme}:// links, picking automatically");
+    }
+
+    candidates(scheme).iter()
+        .find_map(|name| games.iter().find(|g| g.name == *name))
+}
+
+/// True if this folder is a UO install, which decides the desktop file name.
+pub(crate) fn is_uo(games: &[GameInfo]) -> bool
+{
+    games.iter().any(|g| g.version == "1.51")
+}
+
+/// Records this install in the registry, writes the desktop files and
+/// points every scheme some known install can handle at the dispatcher.
+pub(crate) fn register(games: &[GameInfo]) -> std::io::Result<()>
+{
+    let registry = registry::update(games)?;
+
+    if SCHEMES.iter().any(|scheme| handler_for(scheme, games).is_some()) {
+        util::create_desktop_file(&is_uo(games), util::my_exe_path()?.to_str().unwrap())?;
+    }
+
+    let schemes: Vec<&str> = SCHEMES.iter()
+        .copied()
+        .filter(|scheme| registry.any_handles(scheme))
+        .collect();
+    if schemes.is_empty() {
+        return Ok(());
+    }
+
+    // Every copy points the entry at the same dispatcher, not at itself
+    let dispatcher = match registry.dispatcher() {
+        Some(install) => install.executable.clone(),
+        None => util::my_exe()?,
+    };
+    util::create_link_desktop_file(&dispatcher)?;
+    for scheme in schemes {
+        util::reg_uri_scheme(scheme, util::LINK_DESKTOP_FILE)?;
+    }
+    Ok(())
+}
+
+fn install_text(scheme: &str) -> String
+{
+    let registry = Registry::load().unwrap_or_default();
+    let here = util::my_exe_path().unwrap_or_default();
+    match registry.install_for(scheme) {
+        Some(install) if install.path == here => String::from("this folder"),
+        Some(install) => install.path.to_string_lossy().to_string(),
+        None => String::from("none"),
+    }
+}
+
+fn registration_text(scheme: &str) -> String
+{
+    match util::query_uri_scheme(scheme) {
+        Some(current) if current == util::LINK_DESKTOP_FILE => format!("✔ {current}"),
+        Some(current) => format!("✘ {current}"),
+        None => String::from("✘ not registered"),
+    }
+}
+
+pub(crate) fn show_handlers_window(app: &gtk::Application, games: Vec<GameInfo>)
+{
+    relm4::view! {
+        window = gtk::Window {
+            set_title: Some("Link handlers"),
+            set_default_size: (560, 200),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                #[name = "grid"]
+                gtk::Grid {
+                    set_row_spacing: 6,
+                    set_column_spacing: 12,
+
+                    attach[0, 0, 1, 1] = &gtk::Label {
+                        set_markup: "<b>Link</b>",
+                        set_halign: gtk::Align::Start,
+                    },
+                    attach[1, 0, 1, 1] = &gtk::Label {
+                        set_markup: "<b>Opens with</b>",
+                        set_halign: gtk::Align::Start,
+                    },
+                    attach[2, 0, 1, 1] = &gtk::Label {
+                        set_markup: "<b>Install</b>",
+                        set_halign: gtk::Align::Start,
+                    },
+                    attach[3, 0, 1, 1] = &gtk::Label {
+                        set_markup: "<b>Registered</b>",
+                        set_halign: gtk::Align::Start,
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+                    set_halign: gtk::Align::End,
+
+                    #[name = "prefer_button"]
+                    gtk::Button {
+                        set_label: "Open links with this folder",
+                    },
+
+                    #[name = "repair_button"]
+                    gtk::Button {
+                        set_label: "Repair registration",
+                    },
+                },
+            }
+        }
+    }
+
+    let mut status_labels = Vec::new();
+    for (row, scheme) in SCHEMES.iter().enumerate() {
+        let row = row as i32 + 1;
+        let available: Vec<&str> = candidates(scheme).iter()
+            .copied()
+            .filter(|name| games.iter().any(|g| g.name == *name))
+            .collect();
+
+        let mut choices = vec!["Automatic"];
+        choices.extend(available.iter().copied());
+        let dropdown = gtk::DropDown::from_strings(&choices);
+        let chosen = chosen_handler(scheme);
+        if let Some(pos) = available.iter().position(|name| *name == chosen) {
+            dropdown.set_selected(pos as u32 + 1);
+        }
+        dropdown.set_sensitive(!available.is_empty());
+
+        let scheme_owned = scheme.to_string();
+        dropdown.connect_selected_notify(move |dd| {
+            let game = match dd.selected() {
+                0 => "",
+                i => available[i as usize - 1],
+            };
+            set_chosen_handler(&scheme_owned, game).unwrap();
+        });
+
+        let install = gtk::Label::new(Some(&install_text(scheme)));
+        install.set_halign(gtk::Align::Start);
+        install.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+
+        let status = gtk::Label::new(Some(&registration_text(scheme)));
+        status.set_halign(gtk::Align::Start);
+
+        grid.attach(&gtk::Label::new(Some(&format!("{scheme}://"))), 0, row, 1, 1);
+        grid.attach(&dropdown, 1, row, 1, 1);
+        grid.attach(&install, 2, row, 1, 1);
+        grid.attach(&status, 3, row, 1, 1);
+        status_labels.push((scheme.to_string(), install, status));
+    }
+    let status_labels = std::rc::Rc::new(status_labels);
+
+    let refresh = {
+        let status_labels = status_labels.clone();
+        move || {
+            for (scheme, install, status) in status_labels.iter() {
+                install.set_text(&install_text(scheme));
+                status.set_text(&registration_text(scheme));
+            }
+        }
+    };
+
+    prefer_button.connect_clicked({
+        let refresh = refresh.clone();
+        move |_| {
+            if let Err(e) = registry::prefer_this_install().and_then(|_| util::create_link_desktop_file(&util::my_exe()?)) {
+                eprintln!("CoDLinux: Failed to update install registry: {e}");
+            }
+            refresh();
+        }
+    });
+
+    repair_button.connect_clicked(move |_| {
+        if let Err(e) = register(&games) {
+            eprintln!("CoDLinux: Failed to register link handlers: {e}");
+        }
+        refresh();
+    });
+
+    app.add_window(&window);
+    window.set_visible(true);
+}
@@ -0,0 +1,365 @@
+use relm4::{
+    factory::{DynamicIndex, FactoryComponent, FactorySender}, gtk
+};
+use gtk::prelude::*;
+
+use std::{
+    fs::{self, File}, io::{self, Write}, os::unix::process::CommandExt, path::PathBuf,
+    process::{Child, Command, Stdio}, thread, time::{Duration, Instant}
+};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{launch::{self, LaunchPlan}, master, pk3, server_config::ServerConfig, systemd, util, GameInfo};
+
+/// How long a server gets to exit after SIGTERM before it's killed.
+static STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A dedicated server instance, saved in `hosted.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct HostedServer {
+    pub name: String,
+    /// Name of the detected game whose install and prefix are used
+    pub game: String,
+    pub port: u16,
+    /// Config executed at startup, relative to the game's main folder
+    pub config: String,
+    /// Run under `xvfb-run` so no display is needed
+    #[serde(default)]
+    pub headless: bool,
+    /// Anything else to pass, e.g. `+set fs_game mymod`
+    #[serde(default)]
+    pub args: String,
+}
+
+fn conf_file() -> PathBuf
+{
+    util::my_exe_path().unwrap().join("codlinux_conf").join("hosted.json")
+}
+
+pub(crate) fn load_hosted() -> Vec<HostedServer>
+{
+    let Ok(contents) = fs::read_to_string(conf_file()) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        eprintln!("CoDLinux: Ignoring broken hosted.json: {}", e);
+        Vec::new()
+    })
+}
+
+pub(crate) fn save_hosted(servers: &[HostedServer]) -> io::Result<()>
+{
+    let json = serde_json::to_string_pretty(servers).map_err(io::Error::other)?;
+    fs::write(conf_file(), json)
+}
+
+impl HostedServer {
+    /// The name as it goes into file and unit names. Names with anything
+    /// but letters, digits, `-` and `_` get those replaced and a hash of
+    /// the real name added, so `my server` and `my-server` stay apart.
+    pub(crate) fn file_name(&self) -> String
+    {
+        if !self.name.is_empty() && self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return self.name.clone();
+        }
+        let name: String = self.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect();
+        format!("{}-{}", name, &format!("{:x}", md5::compute(self.name.as_bytes()))[..8])
+    }
+}
+
+pub(crate) fn log_file(server: &HostedServer) -> PathBuf
+{
+    util::my_exe_path().unwrap().join("codlinux_conf").join("logs").join(format!("{}-{}.log", server.file_name(), server.port))
+}
+
+/// The vanilla multiplayer executable next to `game`, clients like IW1X
+/// can't run dedicated.
+pub(crate) fn dedicated_executable(game: &GameInfo) -> Option<PathBuf>
+{
+    let family = master::family_for_game(&game.name)?;
+    let name = if family.scheme == "coduo" { "CoDUOMP.exe" } else { "CoDMP.exe" };
+    let executable = game.path.parent()?.join(name);
+    executable.exists().then_some(executable)
+}
+
+/// Where `+exec` finds the server's config: `uo` for United Offensive, `main` otherwise.
+pub(crate) fn config_path(game: &GameInfo, server: &HostedServer) -> Option<PathBuf>
+{
+    Some(pk3::game_folders(game).last()?.join(&server.config))
+}
+
+/// `fs_game` from the extra args, if any.
+pub(crate) fn fs_game(server: &HostedServer) -> Option<&str>
+{
+    let words: Vec<&str> = server.args.split_whitespace().collect();
+    words.windows(3)
+        .find(|w| matches!(w[0], "+set" | "+seta") && w[1].eq_ignore_ascii_case("fs_game"))
+        .map(|w| w[2])
+}
+
+/// The game log (`g_log`, `games_mp.log` by default), written to the mod's
+/// folder or the game's main one.
+pub(crate) fn games_log(game: &GameInfo, server: &HostedServer) -> Option<PathBuf>
+{
+    let config = ServerConfig::load(&config_path(game, server)?).unwrap_or_default();
+    let log = config.get("g_log").filter(|l| !l.is_empty()).unwrap_or("games_mp.log");
+    let folder = match fs_game(server) {
+        Some(mod_folder) => game.path.parent()?.join(mod_folder),
+        None => pk3::game_folders(game).pop()?,
+    };
+    Some(folder.join(log))
+}
+
+/// Lowest port from 28960 up that no other instance uses.
+pub(crate) fn free_port(servers: &[HostedServer]) -> u16
+{
+    (28960..).find(|p| !servers.iter().any(|s| s.port == *p)).unwrap()
+}
+
+/// Launch plan for `server`, the game's prefix and envars with dedicated args.
+pub(crate) fn plan(game: &GameInfo, server: &HostedServer) -> Option<LaunchPlan>
+{
+    let mut plan = launch::resolve(game);
+    plan.executable = dedicated_executable(game)?;
+    plan.args = format!(
+        "+set dedicated 2 +set net_port {} +exec {} {}",
+        server.port,
+        launch::shell_quote(&server.config),
+        server.args,
+    ).trim().to_string();
+    if server.headless {
+        plan.wrappers.insert(0, String::from("xvfb-run -a"));
+    }
+    Some(plan)
+}
+
+/// Starts the server in its own process group with output appended to its log.
+pub(crate) fn start(server: &HostedServer, plan: &LaunchPlan) -> io::Result<Child>
+{
+    let log_path = log_file(server);
+    fs::create_dir_all(log_path.parent().unwrap())?;
+    let mut log = File::options().create(true).append(true).open(&log_path)?;
+    writeln!(log, "==== {} Starting {}", Local::now().format("%Y-%m-%d %H:%M:%S"), server.name)?;
+    writeln!(log, "{}", plan.to_shell())?;
+
+    println!("CoDLinux: Starting dedicated server `{}` on port {}", server.name, server.port);
+    Command::new("bash")
+        .arg("-c")
+        .arg(plan.to_shell())
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .process_group(0)
+        .spawn()
+}
+
+/// Sends `signal` to every process in `group`, true if any received it.
+fn signal_group(group: u32, signal: &str) -> bool
+{
+    Command::new("kill")
+        .args([signal, "--", &format!("-{group}")])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Waits until nothing is left in the group of `child`, killing it once
+/// `STOP_TIMEOUT` has passed.
+fn wait_group(mut child: Child)
+{
+    let group = child.id();
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    loop {
+        // Reap the leader, a zombie still counts as part of the group
+        let _ = child.try_wait();
+        if !signal_group(group, "-0") {
+            return;
+        }
+        if Instant::now() >= deadline {
+            eprintln!("CoDLinux: Dedicated server (pid {group}) didn't stop in time, killing it");
+            signal_group(group, "-KILL");
+            let _ = child.wait();
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Stops the whole process group, wine and wrappers included. Waits in the
+/// background, the handle finishes once the server is gone.
+pub(crate) fn stop(child: Child) -> thread::JoinHandle<()>
+{
+    // Fails if it already exited, `wait_group` still reaps it
+    signal_group(child.id(), "-TERM");
+    thread::spawn(move || wait_group(child))
+}
+
+/// `systemctl is-active` of the server's unit, None without one. Blocking.
+pub(crate) fn service_status(server: &HostedServer) -> Option<String>
+{
+    systemd::is_installed(server).then(|| systemd::status(server))
+}
+
+/// A row in "Servers I host", owns the running process.
+#[derive(Debug)]
+pub(crate) struct HostedRow {
+    pub server: HostedServer,
+    pub process: Option<Child>,
+    /// Why it's not running, if it failed
+    pub error: Option<String>,
+    /// `systemctl is-active` of its unit, None without one
+    pub service: Option<String>,
+}
+
+impl HostedRow {
+    pub(crate) fn status(&self) -> String
+    {
+        let status = match (&self.process, &self.error) {
+            (Some(child), _) => format!("Running (pid {})", child.id()),
+            (None, Some(error)) => error.clone(),
+            (None, None) => String::from("Stopped"),
+        };
+        match &self.service {
+            Some(service) => format!("{status} • systemd service {service}"),
+            None => status,
+        }
+    }
+
+    /// Notices a server that exited on its own.
+    pub(crate) fn poll(&mut self)
+    {
+        let Some(child) = &mut self.process else { return; };
+        if let Ok(Some(status)) = child.try_wait() {
+            println!("CoDLinux: Dedicated server `{}` exited: {}", self.server.name, status);
+            self.error = (!status.success()).then(|| format!("Exited ({status})"));
+            self.process = None;
+        }
+    }
+}
+
+impl Drop for HostedRow {
+    fn drop(&mut self)
+    {
+        if let Some(child) = self.process.take() {
+            stop(child);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum HostedOutput {
+    Start(DynamicIndex),
+    Stop(DynamicIndex),
+    Restart(DynamicIndex),
+    OpenLog(DynamicIndex),
+    EditConfig(DynamicIndex),
+    ShowStats(DynamicIndex),
+    ToggleService(DynamicIndex),
+    Remove(DynamicIndex),
+}
+
+#[relm4::factory(pub(crate))]
+impl FactoryComponent for HostedRow {
+    type Init = HostedServer;
+    type Input = ();
+    type Output = HostedOutput;
+    type CommandOutput = ();
+    type ParentWidget = gtk::Box;
+
+    view! {
+        root = gtk::Box {
+            set_orientation: gtk::Orientation::Horizontal,
+            set_spacing: 6,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_hexpand: true,
+
+                gtk::Label {
+                    set_text: &format!("{} • {} • port {}", self.server.name, self.server.game, self.server.port),
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: gtk::pango::EllipsizeMode::End,
+                },
+                gtk::Label {
+                    #[watch]
+                    set_markup: &format!("<small>{}</small>", gtk::glib::markup_escape_text(&self.status())),
+                    set_halign: gtk::Align::Start,
+                },
+            },
+
+            gtk::Button {
+                set_icon_name: "media-playback-start",
+                set_tooltip_text: Some("Start"),
+                #[watch]
+                set_sensitive: self.process.is_none(),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::Start(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "media-playback-stop",
+                set_tooltip_text: Some("Stop"),
+                #[watch]
+                set_sensitive: self.process.is_some(),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::Stop(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "view-refresh",
+                set_tooltip_text: Some("Restart"),
+                #[watch]
+                set_sensitive: self.process.is_some(),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::Restart(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "document-edit",
+                set_tooltip_text: Some("Edit config and map rotation"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::EditConfig(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "x-office-spreadsheet",
+                set_tooltip_text: Some("Statistics from games_mp.log"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::ShowStats(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "system-run",
+                #[watch]
+                set_tooltip_text: Some(if self.service.is_some() { "Remove systemd service" } else { "Install as systemd user service" }),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::ToggleService(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "text-x-generic",
+                set_tooltip_text: Some("Open log"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::OpenLog(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "list-remove",
+                set_tooltip_text: Some("Remove"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(HostedOutput::Remove(index.clone())).unwrap();
+                },
+            },
+        }
+    }
+
+    fn init_model(server: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> HostedRow
+    {
+        // The first `PollHosted` fills in the real status
+        let service = systemd::is_installed(&server).then(|| String::from("unknown"));
+        HostedRow { server, process: None, error: None, service }
+    }
+}
@@ -0,0 +1,74 @@
+use std::{collections::BTreeMap, io, net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket}, process::Command, time::{Duration, Instant}};
+
+use crate::query::{self, ServerInfo};
+
+/// Ports dedicated servers pick when several run on one machine.
+pub(crate) static LAN_PORTS: [u16; 4] = [28960, 28961, 28962, 28963];
+
+/// Broadcast addresses of every local IPv4 interface, from `ip -o -4 addr show`.
+/// Always includes 255.255.255.255.
+pub(crate) fn broadcast_addresses() -> Vec<Ipv4Addr>
+{
+    let mut addresses = vec![Ipv4Addr::BROADCAST];
+    match Command::new("ip").args(["-o", "-4", "addr", "show"]).output() {
+        Ok(output) => {
+            for address in parse_broadcasts(&String::from_utf8_lossy(&output.stdout)) {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+        Err(e) => eprintln!("CoDLinux: Failed to list network interfaces: {e}"),
+    }
+    addresses
+}
+
+/// Picks the `brd` field out of each line of `ip -o -4 addr show`.
+pub(crate) fn parse_broadcasts(output: &str) -> Vec<Ipv4Addr>
+{
+    output.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            words.find(|w| *w == "brd")?;
+            words.next()?.parse().ok()
+        })
+        .collect()
+}
+
+/// Broadcasts `getinfo` on the LAN ports of every interface and collects
+/// whoever answers within `timeout`.
+pub(crate) fn discover(timeout: Duration) -> io::Result<Vec<ServerInfo>>
+{
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+
+    let packet = query::oob_packet("getinfo codlinux");
+    let sent = Instant::now();
+    for address in broadcast_addresses() {
+        for port in LAN_PORTS {
+            if let Err(e) = socket.send_to(&packet, SocketAddrV4::new(address, port)) {
+                eprintln!("CoDLinux: Broadcast to {address}:{port} failed: {e}");
+            }
+        }
+    }
+
+    let mut servers: BTreeMap<SocketAddr, ServerInfo> = BTreeMap::new();
+    let deadline = sent + timeout;
+    let mut buffer = [0u8; 65536];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        socket.set_read_timeout(Some(left.max(Duration::from_millis(1))))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        // Several broadcasts can reach the same server, keep the first answer
+        if servers.contains_key(&from) { continue; }
+        if let Some((command, body)) = query::parse_oob(&buffer[..len]) && command == "infoResponse" {
+            let ping = sent.elapsed().as_millis() as u32;
+            let info = query::parse_info_string(&String::from_utf8_lossy(body));
+            servers.insert(from, ServerInfo::from_info(from, ping, info));
+        }
+    }
+    Ok(servers.into_values().collect())
+}
@@ -0,0 +1,298 @@
+use relm4::{gtk, RelmWidgetExt};
+use gtk::prelude::*;
+
+use std::{env, collections::BTreeMap, path::PathBuf};
+use serde::Serialize;
+
+use crate::{util, GameInfo};
+
+static DEFAULT_ENVARS: &str = "MESA_EXTENSION_MAX_YEAR=2008 force_s3tc_enable=true __GL_ExtensionStringVersion=17700";
+static DEFAULT_ARGS: &str = "+set r_ignorehwgamma 1";
+static DEFAULT_RUNNER: &str = "wine";
+
+/// Everything `launch_game` needs, with all defaults already applied.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LaunchPlan {
+    pub game: String,
+    pub executable: PathBuf,
+    pub working_dir: PathBuf,
+    pub wine_prefix: String,
+    /// The envars as the user wrote them, `$VAR` and quotes included
+    #[serde(skip)]
+    pub envars: String,
+    /// `envars` split up, for display only
+    pub env: BTreeMap<String, String>,
+    pub wrappers: Vec<String>,
+    pub runner: String,
+    pub args: String,
+}
+
+impl LaunchPlan {
+    /// Shell line that can be pasted into a terminal.
+    pub(crate) fn to_shell(&self) -> String
+    {
+        let mut parts: Vec<String> = Vec::new();
+        parts.push(format!("cd {} &&", shell_quote(&self.working_dir.to_string_lossy())));
+        // Double quoted like it always was, so `$XDG_DATA_HOME/...` still expands
+        parts.push(format!("WINEPREFIX={}", shell_double_quote(&self.wine_prefix)));
+        // Left to the shell, like it always was, so `$HOME/...` and `VAR="a b"` keep working
+        if !self.envars.trim().is_empty() {
+            parts.push(self.envars.trim().to_string());
+        }
+        parts.extend(self.wrappers.iter().cloned());
+        parts.push(self.runner.clone());
+        parts.push(shell_quote(&self.executable.to_string_lossy()));
+        if !self.args.is_empty() {
+            parts.push(self.args.clone());
+        }
+        parts.join(" ")
+    }
+
+    pub(crate) fn to_json(&self) -> String
+    {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+/// Resolves the prefix, envars, args, runner and wrappers for `game`
+/// the same way the launcher does, without starting anything.
+pub(crate) fn resolve(game: &GameInfo) -> LaunchPlan
+{
+    let wine_prefix = if game.wineprefix.trim().is_empty() {
+        let wineprefix = util::load_setting("default_wine_prefix").unwrap_or_default();
+        if wineprefix.is_empty() { String::from("$HOME/.wine") } else { wineprefix }
+    }
+    else {
+        game.wineprefix.to_string()
+    };
+
+    let envars = if game.envars.is_empty() { DEFAULT_ENVARS } else { game.envars.as_str() };
+    let args = if game.args.trim().is_empty() { DEFAULT_ARGS } else { game.args.trim() };
+
+    let runner = util::load_setting("wine_runner").unwrap_or_default();
+    let runner = if runner.trim().is_empty() { DEFAULT_RUNNER.to_string() } else { runner.trim().to_string() };
+
+    let wrappers = util::load_setting("wrappers").unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+
+    let working_dir = game.path.parent().map(|p| p.to_path_buf()).unwrap_or_else(|| util::my_exe_path().unwrap());
+
+    LaunchPlan {
+        game: game.name.clone(),
+        executable: game.path.clone(),
+        working_dir,
+        wine_prefix: expand_home(&wine_prefix),
+        envars: envars.to_string(),
+        env: parse_envars(envars),
+        wrappers,
+        runner,
+        args: args.to_string(),
+    }
+}
+
+/// Turns `A=1 B="x y"` into a map, splitting on unquoted whitespace.
+/// Words without `=` are ignored, nothing is expanded.
+pub(crate) fn parse_envars(envars: &str) -> BTreeMap<String, String>
+{
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    for c in envars.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, _) if c.is_whitespace() => {
+                if !word.is_empty() { words.push(std::mem::take(&mut word)); }
+            }
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() { words.push(word); }
+
+    words.iter()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn expand_home(path: &str) -> String
+{
+    let home = env::var("HOME").unwrap_or_default();
+    if let Some(rest) = path.strip_prefix("$HOME") {
+        format!("{home}{rest}")
+    }
+    else if let Some(rest) = path.strip_prefix("${HOME}") {
+        format!("{home}{rest}")
+    }
+    else if let Some(rest) = path.strip_prefix('~') {
+        format!("{home}{rest}")
+    }
+    else {
+        path.to_string()
+    }
+}
+
+pub(crate) fn shell_quote(s: &str) -> String
+{
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "/._-+=:,@%".contains(c)) {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Quotes `s` for the shell and leaves `$VARS` in it to be expanded.
+fn shell_double_quote(s: &str) -> String
+{
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Prints the plan for `--dry-run`.
+pub(crate) fn print_plan(plan: &LaunchPlan)
+{
+    println!("CoDLinux: Dry run for `{}`", plan.game);
+    println!("{}", plan.to_shell());
+    println!("{}", plan.to_json());
+}
+
+pub(crate) fn show_command_window(app: &gtk::Application, plan: &LaunchPlan)
+{
+    let shell = plan.to_shell();
+    let json = plan.to_json();
+
+    relm4::view! {
+        window = gtk::Window {
+            set_title: Some(&format!("Launch command: {}", plan.game)),
+            set_default_size: (600, 400),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Label {
+                    set_markup: "<b>Shell</b>",
+                    set_halign: gtk::Align::Start,
+                },
+                gtk::ScrolledWindow {
+                    set_min_content_height: 60,
+                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                    #[name = "shell_view"]
+                    gtk::TextView {
+                        set_editable: false,
+                        set_monospace: true,
+                        set_wrap_mode: gtk::WrapMode::WordChar,
+                    },
+                },
+                gtk::Button {
+                    set_label: "Copy shell command",
+                    set_halign: gtk::Align::End,
+                    connect_clicked[shell = shell.clone()] => move |btn| {
+                        btn.display().clipboard().set_text(&shell);
+                    },
+                },
+
+                gtk::Label {
+                    set_markup: "<b>JSON</b>",
+                    set_halign: gtk::Align::Start,
+                },
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                    #[name = "json_view"]
+                    gtk::TextView {
+                        set_editable: false,
+                        set_monospace: true,
+                    },
+                },
+                gtk::Button {
+                    set_label: "Copy JSON",
+                    set_halign: gtk::Align::End,
+                    connect_clicked[json = json.clone()] => move |btn| {
+                        btn.display().clipboard().set_text(&json);
+                    },
+                },
+            }
+        }
+    }
+
+    shell_view.buffer().set_text(&shell);
+    json_view.buffer().set_text(&json);
+
+    app.add_window(&window);
+    window.set_visible(true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envars_split_on_unquoted_whitespace()
+    {
+        let env = parse_envars(r#"A=1  B="x y" C='$HOME/z' junk D="""#);
+        assert_eq!(env.len(), 4);
+        assert_eq!(env["A"], "1");
+        assert_eq!(env["B"], "x y");
+        assert_eq!(env["C"], "$HOME/z");
+        assert_eq!(env["D"], "");
+    }
+
+    #[test]
+    fn envars_stay_raw_in_the_shell_line()
+    {
+        let envars = r#"VAR="a b" DXVK_CONFIG_FILE=$HOME/dxvk.conf"#;
+        let plan = LaunchPlan {
+            game: String::from("CoD"),
+            executable: PathBuf::from("/games/cod/CoDMP.exe"),
+            working_dir: PathBuf::from("/games/cod"),
+            wine_prefix: String::from("/home/me/.wine"),
+            envars: envars.to_string(),
+            env: parse_envars(envars),
+            wrappers: vec![String::from("gamemoderun")],
+            runner: String::from("wine"),
+            args: String::from("+set r_ignorehwgamma 1"),
+        };
+        assert_eq!(
+            plan.to_shell(),
+            r#"cd /games/cod && WINEPREFIX="/home/me/.wine" VAR="a b" DXVK_CONFIG_FILE=$HOME/dxvk.conf gamemoderun wine /games/cod/CoDMP.exe +set r_ignorehwgamma 1"#,
+        );
+        assert!(plan.to_json().contains(r#""VAR": "a b""#));
+    }
+
+    #[test]
+    fn shell_quote_only_quotes_when_needed()
+    {
+        assert_eq!(shell_quote("/games/cod/CoDMP.exe"), "/games/cod/CoDMP.exe");
+        assert_eq!(shell_quote("1.2.3.4:28960"), "1.2.3.4:28960");
+        assert_eq!(shell_quote(""), "''");
+        assert_eq!(shell_quote("Call of Duty"), "'Call of Duty'");
+        assert_eq!(shell_quote("[::1]:28960"), "'[::1]:28960'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn wine_prefix_keeps_shell_expansion()
+    {
+        assert_eq!(shell_double_quote("$XDG_DATA_HOME/wineprefixes/cod"), r#""$XDG_DATA_HOME/wineprefixes/cod""#);
+        assert_eq!(shell_double_quote(r#"/a "b"\`c`"#), r#""/a \"b\"\\\`c\`""#);
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!("XDG_DATA_HOME=/data; echo {}", shell_double_quote("$XDG_DATA_HOME/cod prefix")))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim_end(), "/data/cod prefix");
+    }
+}
@@ -3,16 +3,47 @@ static VERSION: &str = env!("CARGO_PKG_VERSION");
 static GNAME_STYLE: &str = "font-family=\"Ubuntu\" font-weight=\"bold\" font-size=\"xx-large\"";
 
 use relm4::{
-    factory::{DynamicIndex, FactoryComponent, FactorySender, FactoryVecDeque}, gtk, Component, ComponentParts, ComponentSender, RelmApp, RelmWidgetExt, Worker, WorkerController
+    factory::{DynamicIndex, FactoryComponent, FactorySender, FactoryVecDeque}, gtk, Component, ComponentController, ComponentParts, ComponentSender, Controller, MessageBroker, RelmApp, RelmWidgetExt, Worker, WorkerController
 };
 use gtk::Orientation;
-use gtk::prelude::{BoxExt, ButtonExt, GtkWindowExt, OrientableExt, WidgetExt, PopoverExt, GridExt, EditableExt};
+use gtk::prelude::{ApplicationExt, ApplicationExtManual, BoxExt, ButtonExt, CheckButtonExt, DisplayExt, FileExt, GtkApplicationExt, GtkWindowExt, ListModelExt, OrientableExt, WidgetExt, PopoverExt, GridExt, EditableExt, EntryExt};
 use util::my_exe_path;
 
 mod util;
 mod updater;
-
-use std::{io, env, fs::create_dir_all, path::PathBuf, collections::BTreeMap};
+mod launch;
+mod cli;
+mod splash;
+mod uri;
+mod handlers;
+mod registry;
+mod query;
+mod master;
+mod browser;
+mod details;
+mod servers;
+mod quickplay;
+mod slots;
+mod friends;
+mod lan;
+mod master_server;
+mod master_window;
+mod rcon;
+mod hosting;
+mod pk3;
+mod server_config;
+mod systemd;
+mod stats;
+mod stats_window;
+mod practice;
+mod content;
+mod mods;
+mod downloads;
+
+use std::{io, env, fs::create_dir_all, path::PathBuf, net::SocketAddr, collections::{BTreeMap, HashSet, VecDeque}};
+
+/// Lets links received through `open` (D-Bus activation) reach the app.
+static APP_BROKER: MessageBroker<AppMsg> = MessageBroker::new();
 
 //use relm4_icons_build;
 //use relm4_icons;
@@ -34,7 +65,10 @@ struct GameInfo {
 }
 
 #[derive(Debug, Clone)]
-enum GameMsg {}
+enum GameMsg {
+    /// The remembered game was forgotten, it can be remembered again
+    Forgotten,
+}
 
 #[derive(Debug)]
 enum GameOutput {
@@ -42,12 +76,54 @@ enum GameOutput {
     Removed(DynamicIndex),
     Edited(DynamicIndex, String, String, String),
     Remembered(DynamicIndex),
+    ShowCommand(DynamicIndex),
+    QuickPlay(DynamicIndex),
+    Practice(DynamicIndex),
+    ShowContent(DynamicIndex),
+    ShowMods(DynamicIndex),
 }
 
 struct App {
     games: FactoryVecDeque<GameInfo>,
+    favourites: FactoryVecDeque<servers::ServerEntry>,
+    recent: FactoryVecDeque<servers::ServerEntry>,
+    hosted: FactoryVecDeque<hosting::HostedRow>,
+    /// Games that have a dedicated executable, for the "Servers I host" form
+    host_games: gtk::StringList,
     scanner: WorkerController<Scanner>,
     launcher: WorkerController<GameLauncher>,
+    autolaunch: Option<GameInfo>,
+    splash: Option<Controller<splash::Splash>>,
+    browser: Option<Controller<browser::ServerBrowser>>,
+    master_window: Option<Controller<master_window::MasterWindow>>,
+    details: Vec<Controller<details::ServerDetails>>,
+    consoles: Vec<Controller<rcon::RconConsole>>,
+    stats_windows: Vec<Controller<stats_window::StatsWindow>>,
+    mod_managers: Vec<Controller<mods::ModManager>>,
+    /// Set when the game was started without showing the launcher window (remembered game, links)
+    quit_after_game: bool,
+    running: Option<GameInfo>,
+    /// Local server the running game practices on, stopped with it
+    practice: Option<practice::PracticeServer>,
+    /// Links that arrived while a game was running
+    queued_links: VecDeque<String>,
+    /// Game args given after the link on the command line, for that link only
+    link_args: Vec<String>,
+    /// Join waiting for a free slot on a full server
+    waiting: Option<slots::SlotWait>,
+    /// Watched players already notified about, with their server
+    friends_online: HashSet<(String, SocketAddr)>,
+    watching_friends: bool,
+    /// A `systemctl is-active` round for the hosted servers is running
+    polling_services: bool,
+}
+
+struct AppInit {
+    games: Vec<GameInfo>,
+    /// Remembered game to launch after the splash countdown
+    autolaunch: Option<GameInfo>,
+    /// Args after the link on the command line
+    link_args: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -60,7 +136,61 @@ enum AppMsg {
     ExitGame,
     UpdateGame(DynamicIndex, String, String, String),
     ShowUpdater,
+    ShowHandlers,
+    ShowBrowser,
+    Favourite(servers::EntryOutput),
+    Recent(servers::EntryOutput),
+    /// Label and server of a new favourite
+    AddFavourite(String, uri::ServerUri),
+    /// Index into `master::FAMILIES` and what was typed, an address or a link
+    AddFavouriteAddress(u32, String),
+    ShowDetails(String, SocketAddr),
     RememberGame(DynamicIndex),
+    ForgetGame,
+    ShowCommand(DynamicIndex),
+    StartAutoLaunch,
+    AutoLaunch,
+    OpenLauncher,
+    OpenLink(String),
+    LinkChoice(String, i32),
+    /// Answer to the missing content warning, true to join anyway
+    JoinChoice(GameInfo, uri::ServerUri, Box<query::ServerInfo>, bool),
+    QuickPlay(DynamicIndex),
+    Practice(DynamicIndex),
+    ShowContent(DynamicIndex),
+    ShowMods(DynamicIndex),
+    /// Game name and fs_game
+    LaunchMod(String, String),
+    /// Game name, map and gametype
+    StartPractice(String, String, String),
+    PollSlot,
+    CancelWait,
+    ShowFriends,
+    WatchFriends,
+    ShowMasterServer,
+    SaveRconPassword(u64, Option<String>),
+    Hosted(hosting::HostedOutput),
+    /// Index into `host_games` and the new instance
+    AddHosted(u32, hosting::HostedServer),
+    PollHosted,
+}
+
+#[derive(Debug)]
+enum AppCmd {
+    /// Like `SlotChecked`, for the server Quick Play picked
+    QuickPlayFound(Result<(GameInfo, uri::ServerUri, query::ServerInfo, Vec<String>), String>),
+    /// Server state right before joining it, None if it didn't answer,
+    /// and the maps and mods it needs that aren't installed
+    SlotChecked(GameInfo, uri::ServerUri, Option<query::ServerInfo>, Vec<String>),
+    SlotPolled(Option<query::ServerInfo>),
+    FriendsSeen(Vec<friends::Sighting>),
+    PracticeReady(GameInfo, Result<practice::PracticeServer, String>),
+    /// A hosted server being restarted has exited
+    HostedStopped(DynamicIndex),
+    /// Its systemd service was installed or removed
+    ServiceToggled(DynamicIndex, Result<(), String>),
+    /// `systemctl is-active` of each hosted server with a unit
+    ServicesPolled(Vec<(hosting::HostedServer, Option<String>)>),
 }
 
 struct Scanner;
@@ -169,6 +299,51 @@ impl FactoryComponent for GameInfo {
                     set_size_request: (32,32)
                 },
 
+                gtk::Button {
+                    set_icon_name: "media-seek-forward",
+                    set_tooltip_text: Some("Quick Play"),
+                    connect_clicked[sender, index] => move |_| {
+                        sender.output(GameOutput::QuickPlay(index.clone())).unwrap();
+                    },
+                    set_size_request: (32,32)
+                },
+
+                gtk::Button {
+                    set_icon_name: "input-gaming",
+                    set_tooltip_text: Some("Practice on a local server"),
+                    connect_clicked[sender, index] => move |_| {
+                        sender.output(GameOutput::Practice(index.clone())).unwrap();
+                    },
+                    set_size_request: (32,32)
+                },
+
+                gtk::Button {
+                    set_icon_name: "package-x-generic",
+                    set_tooltip_text: Some("Installed maps and mods"),
+                    connect_clicked[sender, index] => move |_| {
+                        sender.output(GameOutput::ShowContent(index.clone())).unwrap();
+                    },
+                    set_size_request: (32,32)
+                },
+
+                gtk::Button {
+                    set_icon_name: "application-x-addon",
+                    set_tooltip_text: Some("Mods"),
+                    connect_clicked[sender, index] => move |_| {
+                        sender.output(GameOutput::ShowMods(index.clone())).unwrap();
+                    },
+                    set_size_request: (32,32)
+                },
+
+                gtk::Button {
+                    set_icon_name: "utilities-terminal",
+                    set_tooltip_text: Some("Show command"),
+                    connect_clicked[sender, index] => move |_| {
+                        sender.output(GameOutput::ShowCommand(index.clone())).unwrap();
+                    },
+                    set_size_request: (32,32)
+                },
+
                 gtk::MenuButton {
                     set_icon_name: "document-edit",
                     set_direction: gtk::ArrowType::Right,
@@ -251,14 +426,21 @@ impl FactoryComponent for GameInfo {
     {
         info
     }
+
+    fn update_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::Input, _sender: FactorySender<Self>)
+    {
+        match msg {
+            GameMsg::Forgotten => widgets.remember_button.set_sensitive(true),
+        }
+    }
 }
 
 #[relm4::component]
 impl Component for App {
-    type Init = Vec<GameInfo>;
+    type Init = AppInit;
     type Input = AppMsg;
     type Output = ();
-    type CommandOutput = ();
+    type CommandOutput = AppCmd;
 
     view! {
         gtk::Window {
@@ -299,6 +481,41 @@ impl Component for App {
                                     set_label: "Add Dummy Game",
                                     connect_clicked => AppMsg::AddGame,
                                 },
+                                gtk::Button {
+                                    set_label: "Server Browser",
+                                    connect_clicked[sender, more_popover] => move |_| {
+                                        more_popover.popdown();
+                                        sender.input(AppMsg::ShowBrowser);
+                                    },
+                                },
+                                gtk::Button {
+                                    set_label: "Private Master Server",
+                                    connect_clicked[sender, more_popover] => move |_| {
+                                        more_popover.popdown();
+                                        sender.input(AppMsg::ShowMasterServer);
+                                    },
+                                },
+                                gtk::Button {
+                                    set_label: "Friend Watch",
+                                    connect_clicked[sender, more_popover] => move |_| {
+                                        more_popover.popdown();
+                                        sender.input(AppMsg::ShowFriends);
+                                    },
+                                },
+                                gtk::Button {
+                                    set_label: "Forget remembered game",
+                                    connect_clicked[sender, more_popover] => move |_| {
+                                        more_popover.popdown();
+                                        sender.input(AppMsg::ForgetGame);
+                                    },
+                                },
+                                gtk::Button {
+                                    set_label: "Link Handlers",
+                                    connect_clicked[sender, more_popover] => move |_| {
+                                        more_popover.popdown();
+                                        sender.input(AppMsg::ShowHandlers);
+                                    },
+                                },
                                 gtk::Button {
                                     set_label: "Check for Updates",
                                     connect_clicked[sender, more_popover] => move |_| {
@@ -359,16 +576,165 @@ impl Component for App {
                     },
                 },
 
+                gtk::Box {
+                    set_orientation: Orientation::Horizontal,
+                    set_spacing: 6,
+                    #[watch]
+                    set_visible: model.waiting.is_some(),
+
+                    gtk::Spinner {
+                        #[watch]
+                        set_spinning: model.waiting.is_some(),
+                    },
+                    gtk::Label {
+                        #[watch]
+                        set_text: &model.waiting.as_ref().map(|w| w.status()).unwrap_or_default(),
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                        set_ellipsize: gtk::pango::EllipsizeMode::End,
+                    },
+                    gtk::Button {
+                        set_label: "Cancel",
+                        connect_clicked => AppMsg::CancelWait,
+                    },
+                },
+
                 #[local_ref]
                 games_box -> gtk::Box {
                     set_orientation: Orientation::Vertical,
                     set_spacing: 5,
                 },
+
+                gtk::Expander {
+                    set_label: Some("Servers"),
+                    set_margin_top: 6,
+
+                    #[wrap(Some)]
+                    set_child = &gtk::Box {
+                        set_orientation: Orientation::Vertical,
+                        set_spacing: 5,
+                        set_margin_top: 6,
+
+                        gtk::Label {
+                            set_markup: "<b>Favourites</b>",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[local_ref]
+                        favourites_box -> gtk::Box {
+                            set_orientation: Orientation::Vertical,
+                            set_spacing: 5,
+                        },
+                        gtk::Box {
+                            set_orientation: Orientation::Horizontal,
+                            set_spacing: 6,
+
+                            #[name = "favourite_family"]
+                            gtk::DropDown::from_strings(&master::FAMILIES.iter().map(|f| f.label).collect::<Vec<_>>()) {},
+                            #[name = "favourite_address"]
+                            gtk::Entry {
+                                set_hexpand: true,
+                                set_placeholder_text: Some("host:port or server link"),
+                            },
+                            gtk::Button {
+                                set_label: "Add favourite",
+                                connect_clicked[sender, favourite_family, favourite_address] => move |_| {
+                                    sender.input(AppMsg::AddFavouriteAddress(favourite_family.selected(), favourite_address.text().trim().to_string()));
+                                    favourite_address.set_text("");
+                                },
+                            },
+                        },
+
+                        gtk::Label {
+                            set_markup: "<b>Recently joined</b>",
+                            set_halign: gtk::Align::Start,
+                        },
+                        #[local_ref]
+                        recent_box -> gtk::Box {
+                            set_orientation: Orientation::Vertical,
+                            set_spacing: 5,
+                        },
+                    },
+                },
+
+                gtk::Expander {
+                    set_label: Some("Servers I host"),
+
+                    #[wrap(Some)]
+                    set_child = &gtk::Box {
+                        set_orientation: Orientation::Vertical,
+                        set_spacing: 5,
+                        set_margin_top: 6,
+
+                        #[local_ref]
+                        hosted_box -> gtk::Box {
+                            set_orientation: Orientation::Vertical,
+                            set_spacing: 5,
+                        },
+
+                        gtk::Grid {
+                            set_row_spacing: 6,
+                            set_column_spacing: 12,
+
+                            attach[0, 0, 1, 1] = &gtk::Label {
+                                set_markup: "<b>Game</b>",
+                                set_halign: gtk::Align::Start,
+                            },
+                            #[name = "host_game"]
+                            attach[1, 0, 1, 1] = &gtk::DropDown {
+                                set_model: Some(&model.host_games),
+                            },
+                            attach[0, 1, 1, 1] = &gtk::Label {
+                                set_markup: "<b>Name</b>",
+                                set_halign: gtk::Align::Start,
+                            },
+                            #[name = "host_name"]
+                            attach[1, 1, 1, 1] = &gtk::Entry {
+                                set_text: "server",
+                                set_hexpand: true,
+                            },
+                            attach[0, 2, 1, 1] = &gtk::Label {
+                                set_markup: "<b>Port</b>",
+                                set_halign: gtk::Align::Start,
+                            },
+                            #[name = "host_port"]
+                            attach[1, 2, 1, 1] = &gtk::SpinButton::with_range(1.0, 65535.0, 1.0) {
+                                set_value: hosting::free_port(&hosted) as f64,
+                            },
+                            attach[0, 3, 1, 1] = &gtk::Label {
+                                set_markup: "<b>Config</b>",
+                                set_halign: gtk::Align::Start,
+                            },
+                            #[name = "host_config"]
+                            attach[1, 3, 1, 1] = &gtk::Entry {
+                                set_text: "server.cfg",
+                            },
+                            #[name = "host_headless"]
+                            attach[1, 4, 1, 1] = &gtk::CheckButton {
+                                set_label: Some("Headless (xvfb-run)"),
+                            },
+                        },
+                        gtk::Button {
+                            set_label: "Add server",
+                            set_halign: gtk::Align::End,
+                            connect_clicked[sender, host_game, host_name, host_port, host_config, host_headless] => move |_| {
+                                let server = hosting::HostedServer {
+                                    name: host_name.text().trim().to_string(),
+                                    game: String::new(),
+                                    port: host_port.value() as u16,
+                                    config: host_config.text().trim().to_string(),
+                                    headless: host_headless.is_active(),
+                                    args: String::new(),
+                                };
+                                sender.input(AppMsg::AddHosted(host_game.selected(), server));
+                            },
+                        },
+                    },
+                },
             }
         }
     }
 
-    fn init(games_list: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    fn init(init: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
     {
         let games = FactoryVecDeque::builder()
         .launch_default()
@@ -377,8 +743,41 @@ impl Component for App {
             GameOutput::Removed(index) => AppMsg::RemoveGame(index),
             GameOutput::Edited(index, wp, ev, ag) => AppMsg::UpdateGame(index, wp, ev, ag),
             GameOutput::Remembered(index) => AppMsg::RememberGame(index),
+            GameOutput::ShowCommand(index) => AppMsg::ShowCommand(index),
+            GameOutput::QuickPlay(index) => AppMsg::QuickPlay(index),
+            GameOutput::Practice(index) => AppMsg::Practice(index),
+            GameOutput::ShowContent(index) => AppMsg::ShowContent(index),
+            GameOutput::ShowMods(index) => AppMsg::ShowMods(index),
         });
 
+        let mut favourites = FactoryVecDeque::builder()
+            .launch_default()
+            .forward(sender.input_sender(), AppMsg::Favourite);
+        let mut recent = FactoryVecDeque::builder()
+            .launch_default()
+            .forward(sender.input_sender(), AppMsg::Recent);
+        {
+            let mut guard = favourites.guard();
+            for favourite in servers::load_favourites() {
+                guard.push_back(servers::ServerEntry::from_favourite(favourite));
+            }
+            let mut guard = recent.guard();
+            for entry in servers::load_history().into_iter().take(servers::RECENT_SHOWN) {
+                guard.push_back(servers::ServerEntry::from_history(entry));
+            }
+        }
+
+        let hosted_servers = hosting::load_hosted();
+        let mut hosted = FactoryVecDeque::builder()
+            .launch_default()
+            .forward(sender.input_sender(), AppMsg::Hosted);
+        {
+            let mut guard = hosted.guard();
+            for server in hosted_servers.iter().cloned() {
+                guard.push_back(server);
+            }
+        }
+
         let scanner = Scanner::builder()
             .detach_worker(())
             .forward(sender.input_sender(), AppMsg::AddGames);
@@ -387,21 +786,79 @@ impl Component for App {
             .detach_worker(())
             .forward(sender.input_sender(), |_| AppMsg::ExitGame);
 
-        let model = App { games, scanner, launcher };
+        let model = App {
+            games,
+            favourites,
+            recent,
+            hosted,
+            host_games: gtk::StringList::new(&[]),
+            scanner,
+            launcher,
+            autolaunch: init.autolaunch,
+            splash: None,
+            browser: None,
+            master_window: None,
+            details: Vec::new(),
+            consoles: Vec::new(),
+            stats_windows: Vec::new(),
+            mod_managers: Vec::new(),
+            quit_after_game: false,
+            running: None,
+            practice: None,
+            queued_links: VecDeque::new(),
+            link_args: init.link_args,
+            waiting: None,
+            friends_online: HashSet::new(),
+            watching_friends: false,
+            polling_services: false,
+        };
         let games_box = model.games.widget();
+        let favourites_box = model.favourites.widget();
+        let recent_box = model.recent.widget();
+        let hosted_box = model.hosted.widget();
+        let hosted = hosted_servers;
         let widgets = view_output!();
 
-        sender.input_sender().send(AppMsg::AddGames(games_list)).unwrap();
+        sender.input_sender().send(AppMsg::AddGames(init.games)).unwrap();
+        if model.autolaunch.is_some() {
+            sender.input(AppMsg::StartAutoLaunch);
+        }
+
+        let input = sender.input_sender().clone();
+        gtk::glib::timeout_add_seconds_local(friends::interval(), move || {
+            if input.send(AppMsg::WatchFriends).is_ok() {
+                gtk::glib::ControlFlow::Continue
+            }
+            else {
+                gtk::glib::ControlFlow::Break
+            }
+        });
+        sender.input(AppMsg::WatchFriends);
+
+        let input = sender.input_sender().clone();
+        gtk::glib::timeout_add_seconds_local(2, move || {
+            if input.send(AppMsg::PollHosted).is_ok() {
+                gtk::glib::ControlFlow::Continue
+            }
+            else {
+                gtk::glib::ControlFlow::Break
+            }
+        });
+        sender.input(AppMsg::PollHosted);
 
         ComponentParts { model, widgets }
     }
 
-    fn update(&mut self, msg: Self::Input, _sender: ComponentSender<Self>, root: &Self::Root)
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, root: &Self::Root)
     {
         let mut games_guard = self.games.guard();
         match msg {
             AppMsg::AddGames(list) => {
                 for game in list {
+                    let known = (0..self.host_games.n_items()).any(|i| self.host_games.string(i).as_deref() == Some(game.name.as_str()));
+                    if !known && hosting::dedicated_executable(&game).is_some() {
+                        self.host_games.append(&game.name);
+                    }
                     games_guard.push_back(game);
                 }
             }
@@ -417,6 +874,7 @@ impl Component for App {
             }
             AppMsg::RefreshGames => {
                 games_guard.clear();
+                self.host_games.splice(0, self.host_games.n_items(), &[]);
                 self.scanner.emit(());
             }
             AppMsg::RemoveGame(index) => {
@@ -430,11 +888,24 @@ impl Component for App {
                 if &game.name == "Dummy Game" { return; }
 
                 root.set_visible(false);
+                self.quit_after_game = false;
+                self.running = Some(game.clone());
                 self.launcher.emit(game.clone());
                 //util::launch_game(game).unwrap();
                 //root.set_visible(true);
             }
             AppMsg::ExitGame => {
+                self.running = None;
+                // Dropping it stops the server
+                self.practice = None;
+                if let Some(link) = self.queued_links.pop_front() {
+                    sender.input(AppMsg::OpenLink(link));
+                    return;
+                }
+                if self.quit_after_game {
+                    root.application().unwrap().quit();
+                    return;
+                }
                 root.set_visible(true);
             }
             AppMsg::UpdateGame(index, wp, ev, ag) => {
@@ -453,11 +924,536 @@ impl Component for App {
             AppMsg::ShowUpdater => {
                 updater::show_update_window(root.application().unwrap());
             }
+            AppMsg::ShowBrowser => {
+                let browser = browser::ServerBrowser::builder()
+                    .launch(())
+                    .forward(sender.input_sender(), |msg| match msg {
+                        browser::BrowserOutput::Connect(link) => AppMsg::OpenLink(link),
+                        browser::BrowserOutput::Details(scheme, addr) => AppMsg::ShowDetails(scheme, addr),
+                        browser::BrowserOutput::AddFavourite(label, server) => AppMsg::AddFavourite(label, server),
+                    });
+                root.application().unwrap().add_window(browser.widget());
+                browser.widget().present();
+                self.browser = Some(browser);
+            }
+            AppMsg::Favourite(action) => {
+                let mut favourites = self.favourites.guard();
+                match action {
+                    servers::EntryOutput::Connect(index) => {
+                        if let Some(entry) = favourites.get(index.current_index()) {
+                            sender.input(AppMsg::OpenLink(entry.server.to_link()));
+                        }
+                    }
+                    servers::EntryOutput::CopyLink(index) => {
+                        if let Some(entry) = favourites.get(index.current_index()) {
+                            root.display().clipboard().set_text(&servers::share_link(&entry.server));
+                        }
+                    }
+                    servers::EntryOutput::Edited(index, label, password) => {
+                        if let Some(entry) = favourites.get(index.current_index()) {
+                            let mut entry = entry.clone();
+                            if !label.is_empty() {
+                                entry.label = label;
+                            }
+                            entry.server.password = if password.is_empty() { None } else { Some(password) };
+                            // Rows don't watch their model, replace it to show the new label
+                            favourites.remove(index.current_index());
+                            favourites.insert(index.current_index(), entry);
+                        }
+                    }
+                    servers::EntryOutput::Remove(index) => {
+                        favourites.remove(index.current_index());
+                    }
+                    servers::EntryOutput::Rcon(index) => {
+                        let Some(entry) = favourites.get(index.current_index()) else { return; };
+                        self.consoles.retain(|c| c.widget().is_visible());
+                        let console = rcon::RconConsole::builder()
+                            .launch((entry.id, entry.label.clone(), entry.server.clone(), entry.rcon_password.clone()))
+                            .forward(sender.input_sender(), |msg| match msg {
+                                rcon::RconOutput::SavePassword(id, password) => AppMsg::SaveRconPassword(id, password),
+                            });
+                        root.application().unwrap().add_window(console.widget());
+                        console.widget().present();
+                        self.consoles.push(console);
+                        return;
+                    }
+                    servers::EntryOutput::Details(index) => {
+                        if let Some(msg) = favourites.get(index.current_index()).and_then(|e| details_msg(&e.server)) {
+                            sender.input(msg);
+                        }
+                        return;
+                    }
+                    servers::EntryOutput::AddFavourite(_) => (),
+                }
+                let list: Vec<servers::Favourite> = favourites.iter().map(|e| e.to_favourite()).collect();
+                servers::save_favourites(&list).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save favourites: {e}"));
+            }
+            AppMsg::Recent(action) => {
+                let recent = self.recent.guard();
+                match action {
+                    servers::EntryOutput::Connect(index) => {
+                        if let Some(entry) = recent.get(index.current_index()) {
+                            sender.input(AppMsg::OpenLink(entry.server.to_link()));
+                        }
+                    }
+                    servers::EntryOutput::CopyLink(index) => {
+                        if let Some(entry) = recent.get(index.current_index()) {
+                            root.display().clipboard().set_text(&servers::share_link(&entry.server));
+                        }
+                    }
+                    servers::EntryOutput::Details(index) => {
+                        if let Some(msg) = recent.get(index.current_index()).and_then(|e| details_msg(&e.server)) {
+                            sender.input(msg);
+                        }
+                    }
+                    servers::EntryOutput::AddFavourite(index) => {
+                        if let Some(entry) = recent.get(index.current_index()) {
+                            sender.input(AppMsg::AddFavourite(entry.label.clone(), entry.server.clone()));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            AppMsg::AddFavourite(label, server) => {
+                let mut favourites = self.favourites.guard();
+                if favourites.iter().any(|e| e.server.scheme == server.scheme && e.server.address() == server.address()) {
+                    util::notify(&format!("{} is already a favourite", server.address()), 3000, false).unwrap();
+                    return;
+                }
+                favourites.push_back(servers::ServerEntry::from_favourite(servers::Favourite { label, server, rcon_password: None }));
+                let list: Vec<servers::Favourite> = favourites.iter().map(|e| e.to_favourite()).collect();
+                servers::save_favourites(&list).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save favourites: {e}"));
+            }
+            AppMsg::AddFavouriteAddress(family, address) => {
+                if address.is_empty() { return; }
+                let link = if uri::is_link(&address) {
+                    address
+                }
+                else {
+                    let scheme = master::FAMILIES.get(family as usize).map_or("cod1", |f| f.scheme);
+                    format!("{scheme}://{address}")
+                };
+                match uri::parse(&link) {
+                    Ok(server) => sender.input(AppMsg::AddFavourite(server.address(), server)),
+                    Err(e) => util::notify(&format!("Invalid server address: {e}"), 5000, false).unwrap(),
+                }
+            }
+            AppMsg::SaveRconPassword(id, password) => {
+                let mut favourites = self.favourites.guard();
+                let Some(i) = favourites.iter().position(|e| e.id == id) else {
+                    // Removed while the console was open
+                    return;
+                };
+                if let Some(entry) = favourites.get_mut(i) {
+                    entry.rcon_password = password;
+                }
+                let list: Vec<servers::Favourite> = favourites.iter().map(|e| e.to_favourite()).collect();
+                servers::save_favourites(&list).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save favourites: {e}"));
+            }
+            AppMsg::AddHosted(game, mut server) => {
+                let Some(game) = self.host_games.string(game) else {
+                    util::notify("No installed game can run a dedicated server", 5000, false).unwrap();
+                    return;
+                };
+                let mut hosted = self.hosted.guard();
+                if server.name.is_empty() || hosted.iter().any(|h| h.server.port == server.port) {
+                    util::notify(&format!("Pick a name and a port other servers don't use (port {} is taken)", server.port), 5000, false).unwrap();
+                    return;
+                }
+                server.game = game.to_string();
+                hosted.push_back(server);
+                let list: Vec<hosting::HostedServer> = hosted.iter().map(|h| h.server.clone()).collect();
+                hosting::save_hosted(&list).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save hosted servers: {e}"));
+            }
+            AppMsg::Hosted(action) => {
+                let mut hosted = self.hosted.guard();
+                match action {
+                    hosting::HostedOutput::Start(index) | hosting::HostedOutput::Restart(index) => {
+                        let Some(row) = hosted.get_mut(index.current_index()) else { return; };
+                        if row.service.as_deref() == Some("active") {
+                            row.error = Some(String::from("Already running as a systemd service"));
+                            return;
+                        }
+                        if let Some(child) = row.process.take() {
+                            // Start again once the port is free
+                            let stopped = hosting::stop(child);
+                            sender.spawn_oneshot_command(move || {
+                                let _ = stopped.join();
+                                AppCmd::HostedStopped(index)
+                            });
+                            return;
+                        }
+                        let Some(game) = games_guard.iter().find(|g| g.name == row.server.game) else {
+                            row.error = Some(format!("{} is not installed here", row.server.game));
+                            return;
+                        };
+                        let game = load_game_settings(game.clone()).unwrap();
+                        let Some(plan) = hosting::plan(&game, &row.server) else {
+                            row.error = Some(format!("No dedicated executable next to {}", game.path.display()));
+                            return;
+                        };
+                        match hosting::start(&row.server, &plan) {
+                            Ok(child) => {
+                                row.process = Some(child);
+                                row.error = None;
+                            }
+                            Err(e) => row.error = Some(format!("Failed to start: {e}")),
+                        }
+                    }
+                    hosting::HostedOutput::Stop(index) => {
+                        if let Some(row) = hosted.get_mut(index.current_index()) {
+                            if let Some(child) = row.process.take() {
+                                hosting::stop(child);
+                            }
+                            row.error = None;
+                        }
+                    }
+                    hosting::HostedOutput::OpenLog(index) => {
+                        if let Some(row) = hosted.get(index.current_index()) {
+                            let log = hosting::log_file(&row.server);
+                            if let Err(e) = std::process::Command::new("xdg-open").arg(&log).spawn() {
+                                eprintln!("CoDLinux: Failed to open {}: {e}", log.display());
+                            }
+                        }
+                    }
+                    hosting::HostedOutput::EditConfig(index) => {
+                        let Some(row) = hosted.get(index.current_index()) else { return; };
+                        let Some(game) = games_guard.iter().find(|g| g.name == row.server.game) else {
+                            util::notify(&format!("{} is not installed here", row.server.game), 5000, false).unwrap();
+                            return;
+                        };
+                        let Some(path) = hosting::config_path(game, &row.server) else { return; };
+                        let (maps, gametypes) = pk3::maps_and_gametypes(game, hosting::fs_game(&row.server));
+                        server_config::show_editor_window(&root.application().unwrap(), path, maps, gametypes);
+                    }
+                    hosting::HostedOutput::ShowStats(index) => {
+                        let Some(row) = hosted.get(index.current_index()) else { return; };
+                        let Some(log) = games_guard.iter()
+                            .find(|g| g.name == row.server.game)
+                            .and_then(|game| hosting::games_log(game, &row.server)) else {
+                            util::notify(&format!("{} is not installed here", row.server.game), 5000, false).unwrap();
+                            return;
+                        };
+                        self.stats_windows.retain(|w| w.widget().is_visible());
+                        let window = stats_window::StatsWindow::builder()
+                            .launch((row.server.name.clone(), log))
+                            .detach();
+                        root.application().unwrap().add_window(window.widget());
+                        window.widget().present();
+                        self.stats_windows.push(window);
+                    }
+                    hosting::HostedOutput::ToggleService(index) => {
+                        let Some(row) = hosted.get_mut(index.current_index()) else { return; };
+                        let server = row.server.clone();
+                        let plan = if systemd::is_installed(&server) {
+                            None
+                        }
+                        else {
+                            let Some(game) = games_guard.iter().find(|g| g.name == server.game) else {
+                                row.error = Some(format!("{} is not installed here", server.game));
+                                return;
+                            };
+                            let game = load_game_settings(game.clone()).unwrap();
+                            let Some(plan) = hosting::plan(&game, &server) else {
+                                row.error = Some(format!("No dedicated executable next to {}", game.path.display()));
+                                return;
+                            };
+                            Some(plan)
+                        };
+                        // The service takes over the port, stop our own copy first
+                        let stopping = plan.is_some().then(|| row.process.take()).flatten().map(hosting::stop);
+                        row.error = None;
+                        sender.spawn_oneshot_command(move || {
+                            let result = match plan {
+                                Some(plan) => {
+                                    if let Some(stopping) = stopping {
+                                        let _ = stopping.join();
+                                    }
+                                    systemd::enable(&server, &plan)
+                                }
+                                None => systemd::disable(&server),
+                            };
+                            AppCmd::ServiceToggled(index, result.map_err(|e| e.to_string()))
+                        });
+                    }
+                    hosting::HostedOutput::Remove(index) => {
+                        // Dropping the row stops the server
+                        hosted.remove(index.current_index());
+                        let list: Vec<hosting::HostedServer> = hosted.iter().map(|h| h.server.clone()).collect();
+                        hosting::save_hosted(&list).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save hosted servers: {e}"));
+                    }
+                }
+            }
+            AppMsg::PollHosted => {
+                let mut hosted = self.hosted.guard();
+                for i in 0..hosted.len() {
+                    // Only touch running rows, get_mut redraws them
+                    if hosted.get(i).is_some_and(|r| r.process.is_some()) {
+                        hosted.get_mut(i).unwrap().poll();
+                    }
+                }
+                // systemctl can be slow, ask it off the main loop
+                if self.polling_services { return; }
+                self.polling_services = true;
+                let servers: Vec<hosting::HostedServer> = hosted.iter().map(|r| r.server.clone()).collect();
+                sender.spawn_oneshot_command(move || {
+                    AppCmd::ServicesPolled(servers.into_iter().map(|s| {
+                        let service = hosting::service_status(&s);
+                        (s, service)
+                    }).collect())
+                });
+            }
+            AppMsg::ShowDetails(scheme, addr) => {
+                // Windows closed earlier have stopped polling, drop them
+                self.details.retain(|d| d.widget().is_visible());
+                let details = details::ServerDetails::builder()
+                    .launch((scheme, addr))
+                    .forward(sender.input_sender(), |msg| match msg {
+                        details::DetailsOutput::Connect(link) => AppMsg::OpenLink(link),
+                    });
+                root.application().unwrap().add_window(details.widget());
+                details.widget().present();
+                self.details.push(details);
+            }
+            AppMsg::ShowHandlers => {
+                let games: Vec<GameInfo> = games_guard.iter().cloned().collect();
+                handlers::show_handlers_window(&root.application().unwrap(), games);
+            }
             AppMsg::RememberGame(index) => {
                 if let Some(game) = games_guard.get_mut(index.current_index()) {
                     util::save_setting("saved_game", &game.name).unwrap();
                 }
             }
+            AppMsg::ForgetGame => {
+                util::save_setting("saved_game", "").unwrap();
+                games_guard.broadcast(GameMsg::Forgotten);
+            }
+            AppMsg::StartAutoLaunch => {
+                let Some(game) = &self.autolaunch else { return; };
+                let delay = util::load_setting("autolaunch_delay").unwrap_or_default().parse::<u32>().unwrap_or(3);
+
+                if delay == 0 {
+                    sender.input(AppMsg::AutoLaunch);
+                }
+                else {
+                    let splash = splash::Splash::builder()
+                        .launch((game.name.clone(), delay))
+                        .forward(sender.input_sender(), |msg| match msg {
+                            splash::SplashOutput::Launch => AppMsg::AutoLaunch,
+                            splash::SplashOutput::OpenLauncher => AppMsg::OpenLauncher,
+                        });
+                    root.application().unwrap().add_window(splash.widget());
+                    splash.widget().present();
+                    self.splash = Some(splash);
+                }
+            }
+            AppMsg::AutoLaunch => {
+                self.splash = None;
+                if let Some(game) = self.autolaunch.take() {
+                    self.quit_after_game = true;
+                    self.running = Some(game.clone());
+                    self.launcher.emit(game);
+                }
+            }
+            AppMsg::OpenLauncher => {
+                self.splash = None;
+                self.autolaunch = None;
+                root.set_visible(true);
+            }
+            AppMsg::OpenLink(link) => {
+                if let Some(running) = &self.running {
+                    let address = uri::parse(&link).map(|s| s.address()).unwrap_or_else(|_| link.clone());
+                    let dialog = gtk::AlertDialog::builder()
+                        .modal(false)
+                        .message(format!("{} is already running", running.name))
+                        .detail(format!("Reconnect to {address} now, join it after the game exits, or ignore the link?"))
+                        .buttons(["Reconnect", "Queue", "Ignore"])
+                        .default_button(0)
+                        .cancel_button(2)
+                        .build();
+                    let sender = sender.clone();
+                    dialog.choose(None::<&gtk::Window>, None::<&gtk::gio::Cancellable>, move |choice| {
+                        sender.input(AppMsg::LinkChoice(link.clone(), choice.unwrap_or(2)));
+                    });
+                    return;
+                }
+
+                // A newer link replaces the one waiting for a slot
+                self.waiting = None;
+
+                let games: Vec<GameInfo> = games_guard.iter().cloned().collect();
+                match game_for_link(&link, &games) {
+                    Ok((game, server)) => {
+                        self.splash = None;
+                        self.autolaunch = None;
+                        let extra = std::mem::take(&mut self.link_args);
+                        sender.spawn_oneshot_command(move || {
+                            let info = slots::check(&server);
+                            let (launch, missing) = match &info {
+                                Some(info) => (content::launch_server(&game, &server, info), content::missing_content(&game, info)),
+                                None => (server.clone(), Vec::new()),
+                            };
+                            AppCmd::SlotChecked(game_with_server(&game, &launch, &extra), server, info, missing)
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("CoDLinux: {e}");
+                        util::notify(&e, 5000, false).unwrap();
+                        if !root.is_visible() && self.splash.is_none() {
+                            root.application().unwrap().quit();
+                        }
+                    }
+                }
+            }
+            AppMsg::LinkChoice(link, choice) => {
+                match choice {
+                    0 => {
+                        self.queued_links.push_front(link);
+                        if let Some(running) = &self.running {
+                            util::stop_game().unwrap_or_else(|e| eprintln!("CoDLinux: Failed to stop `{}`: {e}", running.name));
+                        }
+                    }
+                    1 => self.queued_links.push_back(link),
+                    _ => println!("CoDLinux: Ignoring link {link}"),
+                }
+            }
+            AppMsg::JoinChoice(game, server, info, join) => {
+                // The guard borrows `self.games` until it's dropped
+                drop(games_guard);
+                if !join {
+                    if self.running.is_none() && !root.is_visible() && self.splash.is_none() {
+                        root.application().unwrap().quit();
+                    }
+                }
+                else if self.running.is_some() {
+                    self.queue_join(&server);
+                }
+                else {
+                    self.join_or_wait(game, server, Some(*info), &sender, root);
+                }
+            }
+            AppMsg::ShowCommand(index) => {
+                if let Some(game) = games_guard.get(index.current_index()) {
+                    launch::show_command_window(&root.application().unwrap(), &launch::resolve(game));
+                }
+            }
+            AppMsg::QuickPlay(index) => {
+                let Some(game) = games_guard.get(index.current_index()) else { return; };
+                if master::family_for_game(&game.name).is_none() {
+                    util::notify(&format!("Quick Play doesn't know servers for {}", game.name), 5000, false).unwrap();
+                    return;
+                }
+                util::notify(&format!("Looking for a {} server...", game.name), 2000, false).unwrap();
+                let game = game.clone();
+                sender.spawn_oneshot_command(move || {
+                    let found = quickplay::find_server(&game).and_then(|mut info| {
+                        let family = master::family_for_game(&game.name).ok_or("Unknown game")?;
+                        let server = browser::server_uri(family.scheme, &info);
+                        slots::complete(&mut info);
+                        let launch = content::launch_server(&game, &server, &info);
+                        let missing = content::missing_content(&game, &info);
+                        Ok((game_with_server(&game, &launch, &[]), server, info, missing))
+                    });
+                    AppCmd::QuickPlayFound(found)
+                });
+            }
+            AppMsg::Practice(index) => {
+                let Some(game) = games_guard.get(index.current_index()) else { return; };
+                if hosting::dedicated_executable(game).is_none() {
+                    util::notify(&format!("No dedicated executable next to {}", game.path.display()), 5000, false).unwrap();
+                    return;
+                }
+                let input = sender.input_sender().clone();
+                let name = game.name.clone();
+                practice::show_window(&root.application().unwrap(), game, move |map, gametype| {
+                    input.emit(AppMsg::StartPractice(name.clone(), map, gametype));
+                });
+            }
+            AppMsg::ShowContent(index) => {
+                if let Some(game) = games_guard.get(index.current_index()) {
+                    content::show_content_window(&root.application().unwrap(), game);
+                }
+            }
+            AppMsg::ShowMods(index) => {
+                let Some(game) = games_guard.get(index.current_index()) else { return; };
+                self.mod_managers.retain(|m| m.widget().is_visible());
+                let manager = mods::ModManager::builder()
+                    .launch(game.clone())
+                    .forward(sender.input_sender(), |msg| match msg {
+                        mods::ModOutput::Launch(name, fs_game) => AppMsg::LaunchMod(name, fs_game),
+                    });
+                root.application().unwrap().add_window(manager.widget());
+                manager.widget().present();
+                self.mod_managers.push(manager);
+            }
+            AppMsg::LaunchMod(name, fs_game) => {
+                if self.running.is_some() { return; }
+                let Some(game) = games_guard.iter().find(|g| g.name == name) else { return; };
+                let mut game = load_game_settings(game.clone()).unwrap();
+                game.args = format!("{} +set fs_game {}", game.args, launch::shell_quote(&fs_game));
+
+                root.set_visible(false);
+                self.quit_after_game = false;
+                self.running = Some(game.clone());
+                self.launcher.emit(game);
+            }
+            AppMsg::StartPractice(name, map, gametype) => {
+                if self.running.is_some() { return; }
+                let Some(game) = games_guard.iter().find(|g| g.name == name) else { return; };
+                let game = load_game_settings(game.clone()).unwrap();
+                util::notify(&format!("Starting a practice server on {map}..."), 2000, false).unwrap();
+                sender.spawn_oneshot_command(move || {
+                    let server = practice::start(&game, &map, &gametype);
+                    AppCmd::PracticeReady(game, server)
+                });
+            }
+            AppMsg::PollSlot => {
+                let Some(wait) = &mut self.waiting else { return; };
+                if wait.expired() {
+                    let message = format!("Gave up waiting for a slot on {}", wait.server.address());
+                    println!("CoDLinux: {message}");
+                    util::notify(&message, 5000, false).unwrap();
+                    self.waiting = None;
+                    return;
+                }
+                if wait.polling { return; }
+                wait.polling = true;
+                let addr = wait.addr;
+                sender.spawn_oneshot_command(move || {
+                    AppCmd::SlotPolled(query::query_info(addr, query::DEFAULT_TIMEOUT).unwrap_or_else(|e| {
+                        eprintln!("CoDLinux: getinfo {addr} failed: {e}");
+                        None
+                    }))
+                });
+            }
+            AppMsg::ShowFriends => {
+                friends::show_watch_window(&root.application().unwrap());
+            }
+            AppMsg::ShowMasterServer => {
+                // Keeps running while hidden, reuse it
+                let window = self.master_window.get_or_insert_with(|| {
+                    let window = master_window::MasterWindow::builder().launch(()).detach();
+                    root.application().unwrap().add_window(window.widget());
+                    window
+                });
+                window.widget().present();
+            }
+            AppMsg::WatchFriends => {
+                if self.watching_friends { return; }
+                let watch = friends::load_watch_list();
+                let favourites: Vec<servers::Favourite> = self.favourites.iter().map(|e| e.to_favourite()).collect();
+                if watch.is_empty() || favourites.is_empty() {
+                    self.friends_online.clear();
+                    return;
+                }
+                self.watching_friends = true;
+                sender.spawn_oneshot_command(move || AppCmd::FriendsSeen(friends::scan(favourites, &watch)));
+            }
+            AppMsg::CancelWait => {
+                if let Some(wait) = self.waiting.take() {
+                    println!("CoDLinux: No longer waiting for {}", wait.server.address());
+                }
+            }
         }
 
         /*if util::GAME_RUNNING.load(Ordering::Relaxed) {
@@ -467,6 +1463,220 @@ impl Component for App {
             root.set_visible(true);
         }*/
     }
+
+    fn update_cmd(&mut self, msg: Self::CommandOutput, sender: ComponentSender<Self>, root: &Self::Root)
+    {
+        match msg {
+            AppCmd::QuickPlayFound(result) => {
+                let (game, server, info, missing) = match result {
+                    Ok(found) => found,
+                    Err(e) => {
+                        eprintln!("CoDLinux: Quick Play: {e}");
+                        util::notify(&e, 5000, false).unwrap();
+                        return;
+                    }
+                };
+                // A game may have started while we were querying
+                if self.running.is_some() { return; }
+
+                println!("CoDLinux: Quick Play picked {} ({}, {} ms)", info.addr, info.map, info.ping);
+                // Same missing content question as a clicked link
+                self.update_cmd(AppCmd::SlotChecked(game, server, Some(info), missing), sender, root);
+            }
+            AppCmd::SlotChecked(game, server, info, missing) => {
+                // A game may have started while we were checking the server
+                if self.running.is_some() {
+                    self.queue_join(&server);
+                    return;
+                }
+                let info = match info {
+                    Some(info) if !missing.is_empty() => info,
+                    info => {
+                        self.join_or_wait(game, server, info, &sender, root);
+                        return;
+                    }
+                };
+                println!("CoDLinux: {} needs {}", server.address(), missing.join("; "));
+                let dialog = gtk::AlertDialog::builder()
+                    .modal(false)
+                    .message(format!("Missing content for {}", query::strip_colours(&info.hostname)))
+                    .detail(format!(
+                        "This server needs:\n{}\n\nThe game may download it slowly or drop the connection.",
+                        missing.iter().map(|m| format!("• {m}")).collect::<Vec<_>>().join("\n"),
+                    ))
+                    .buttons(["Continue anyway", "Cancel"])
+                    .default_button(1)
+                    .cancel_button(1)
+                    .build();
+                let sender = sender.clone();
+                dialog.choose(None::<&gtk::Window>, None::<&gtk::gio::Cancellable>, move |choice| {
+                    sender.input(AppMsg::JoinChoice(game.clone(), server.clone(), Box::new(info.clone()), matches!(choice, Ok(0))));
+                });
+            }
+            AppCmd::SlotPolled(info) => {
+                let Some(wait) = &mut self.waiting else { return; };
+                wait.polling = false;
+                let Some(info) = info else { return; };
+                if info.is_full() {
+                    wait.last = info;
+                    return;
+                }
+
+                let (game, server, hidden) = (wait.game.clone(), wait.server.clone(), wait.hidden);
+                self.waiting = None;
+                if self.running.is_some() {
+                    self.queue_join(&server);
+                    return;
+                }
+                util::notify(&format!("A slot opened on {}", query::strip_colours(&info.hostname)), 5000, false).unwrap();
+                if hidden {
+                    root.set_visible(false);
+                }
+                self.join(game, &server, root);
+            }
+            AppCmd::PracticeReady(game, result) => {
+                let server = match result {
+                    Ok(server) => server,
+                    Err(e) => {
+                        eprintln!("CoDLinux: {e}");
+                        util::notify(&e, 5000, false).unwrap();
+                        return;
+                    }
+                };
+                // Dropping the server stops it
+                if self.running.is_some() { return; }
+                let Some(family) = master::family_for_game(&game.name) else { return; };
+                let uri = uri::ServerUri {
+                    scheme: family.scheme.to_string(),
+                    host: String::from("localhost"),
+                    port: server.port,
+                    password: None,
+                    fs_game: None,
+                    name: None,
+                };
+                let game = game_with_server(&game, &uri, &[]);
+                self.quit_after_game = !root.is_visible();
+                root.set_visible(false);
+                self.practice = Some(server);
+                self.running = Some(game.clone());
+                self.launcher.emit(game);
+            }
+            AppCmd::FriendsSeen(sightings) => {
+                self.watching_friends = false;
+                let online: HashSet<(String, SocketAddr)> = sightings.iter()
+                    .map(|s| (friends::normalise(&s.player), s.addr))
+                    .collect();
+                for sighting in sightings {
+                    if self.friends_online.contains(&(friends::normalise(&sighting.player), sighting.addr)) {
+                        continue;
+                    }
+                    let message = format!("{} is playing on {}", sighting.player, sighting.favourite.label);
+                    let link = sighting.favourite.server.to_link();
+                    let input = sender.input_sender().clone();
+                    // Waits for the notification to close, keep it off the main loop
+                    std::thread::spawn(move || {
+                        if util::notify_action(&message, "Join") {
+                            input.send(AppMsg::OpenLink(link)).ok();
+                        }
+                    });
+                }
+                self.friends_online = online;
+            }
+            AppCmd::HostedStopped(index) => {
+                sender.input(AppMsg::Hosted(hosting::HostedOutput::Start(index)));
+            }
+            AppCmd::ServiceToggled(index, result) => {
+                if let Err(e) = result {
+                    eprintln!("CoDLinux: {e}");
+                    if let Some(row) = self.hosted.guard().get_mut(index.current_index()) {
+                        row.error = Some(e);
+                    }
+                }
+                sender.input(AppMsg::PollHosted);
+            }
+            AppCmd::ServicesPolled(statuses) => {
+                self.polling_services = false;
+                let mut hosted = self.hosted.guard();
+                for (server, service) in statuses {
+                    let Some(i) = hosted.iter().position(|r| r.server == server) else { continue; };
+                    // Only touch rows that changed, get_mut redraws them
+                    if hosted.get(i).is_some_and(|r| r.service != service) {
+                        hosted.get_mut(i).unwrap().service = service;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hosted and practice servers don't outlive codlinux.
+    fn shutdown(&mut self, _widgets: &mut Self::Widgets, _output: relm4::Sender<Self::Output>)
+    {
+        let mut stopping = Vec::new();
+        let mut hosted = self.hosted.guard();
+        for i in 0..hosted.len() {
+            if let Some(child) = hosted.get_mut(i).and_then(|row| row.process.take()) {
+                stopping.push(hosting::stop(child));
+            }
+        }
+        stopping.extend(self.practice.take().and_then(|server| server.stop()));
+        for handle in stopping {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl App {
+    /// Joins, or waits for a slot first if `info` says the server is full.
+    fn join_or_wait(&mut self, game: GameInfo, server: uri::ServerUri, info: Option<query::ServerInfo>, sender: &ComponentSender<Self>, root: &gtk::Window)
+    {
+        match info {
+            Some(info) if info.is_full() => {
+                let message = format!("{} is full, joining when a slot opens", server.address());
+                println!("CoDLinux: {message}");
+                util::notify(&message, 5000, false).unwrap();
+
+                let hidden = !root.is_visible();
+                root.set_visible(true);
+                let input = sender.input_sender().clone();
+                self.waiting = Some(slots::SlotWait::start(game, server, info, hidden, move || {
+                    input.send(AppMsg::PollSlot).is_ok()
+                }));
+            }
+            // Servers that don't answer queries may still let us in
+            _ => self.join(game, &server, root),
+        }
+    }
+
+    /// Joins `server` once the running game exits, like a queued link.
+    fn queue_join(&mut self, server: &uri::ServerUri)
+    {
+        let running = self.running.as_ref().map(|g| g.name.clone()).unwrap_or_default();
+        let message = format!("{running} is running, joining {} after it exits", server.address());
+        println!("CoDLinux: {message}");
+        util::notify(&message, 5000, false).unwrap();
+        self.queued_links.push_back(server.to_link());
+    }
+
+    /// Records the join and starts `game`, which already carries the connect args.
+    fn join(&mut self, game: GameInfo, server: &uri::ServerUri, root: &gtk::Window)
+    {
+        match servers::record_join(&game.name, server) {
+            Ok(entry) => {
+                let mut recent = self.recent.guard();
+                recent.push_front(servers::ServerEntry::from_history(entry));
+                while recent.len() > servers::RECENT_SHOWN {
+                    recent.pop_back();
+                }
+            }
+            Err(e) => eprintln!("CoDLinux: Failed to record server history: {e}"),
+        }
+
+        self.quit_after_game = !root.is_visible();
+        root.set_visible(false);
+        util::notify(&format!("Launching {}...", game.name), 2000, false).unwrap();
+        self.running = Some(game.clone());
+        self.launcher.emit(game);
+    }
 }
 
 fn load_game_settings(mut game: GameInfo) -> io::Result<GameInfo>
@@ -493,6 +1703,42 @@ fn load_game_settings(mut game: GameInfo) -> io::Result<GameInfo>
     Ok(game.clone())
 }
 
+/// The game that opens `link`, without connect args yet: the server's mod
+/// may still be added before joining.
+fn game_for_link(link: &str, games: &[GameInfo]) -> Result<(GameInfo, uri::ServerUri), String>
+{
+    let server = uri::parse(link).map_err(|e| format!("Invalid server link: {e}"))?;
+    let game = handlers::handler_for(&server.scheme, games)
+        .ok_or_else(|| format!("No installed game can open {}:// links", server.scheme))?;
+
+    Ok((game.clone(), server))
+}
+
+/// Opens the details window of a favourite or recent server.
+fn details_msg(server: &uri::ServerUri) -> Option<AppMsg>
+{
+    match query::resolve(&server.address()) {
+        Ok(addr) => Some(AppMsg::ShowDetails(server.scheme.clone(), addr)),
+        Err(e) => {
+            util::notify(&format!("Can't resolve {}: {e}", server.address()), 5000, false).unwrap();
+            None
+        }
+    }
+}
+
+/// `game` with its saved settings, the connect args for `server` and
+/// `extra` args from the command line.
+fn game_with_server(game: &GameInfo, server: &uri::ServerUri, extra: &[String]) -> GameInfo
+{
+    let mut link_args = server.to_args();
+    link_args.push(String::from("+set r_ignorehwgamma 1"));
+    link_args.extend(extra.iter().cloned());
+
+    let mut game = load_game_settings(game.clone()).unwrap(); // TODO check if there's a better way to do this
+    game.args = format!("{} {}", &game.args, link_args.join(" "));
+    game
+}
+
 fn scan_games() -> Result<Vec<GameInfo>, String>
 {
     let cfgdir = my_exe_path().unwrap().join("codlinux_conf");
@@ -537,6 +1783,17 @@ fn main() -> io::Result<()>
     if util::load_setting("default_wine_prefix").unwrap().is_empty() {
         util::save_setting("default_wine_prefix", "$HOME/.wine").unwrap();
     }
+
+    // Headless modes: no display, no desktop files
+    let cli = cli::parse(std::env::args().skip(1));
+    if let Some(port) = cli.master {
+        return master_server::run_headless(port);
+    }
+    if cli.rest.first().is_some_and(|a| a == "server") {
+        let games = scan_games().unwrap();
+        return systemd::run_cli(&cli.rest[1..], &games);
+    }
+
     util::extract_icon()?;
 
     let resolution = util::get_display_mode();
@@ -552,70 +1809,46 @@ fn main() -> io::Result<()>
     }
 
     println!("CoDLinux: Looking for game executables...");
-    let mut uo = false;
-    let mut cod1 = false;
-    let mut iw1x = false;
-    let mut t1x = false;
     let games = scan_games().unwrap();
 
     if games.is_empty() {
         println!("CoDLinux: No game executables found.");
     }
 
-    for game in &games {
-        match game.version.as_str() {
-            "1.1" => cod1 = true,
-            "1.51" => uo = true,
-            _ => ()
-        }
-        match game.name.as_str() {
-            "IW1X" => iw1x = true,
-            "T1X" => t1x = true,
-            _ => ()
-        }
-    }
-
-    if cod1 || uo {
-        util::create_desktop_file(&uo, my_exe_path().unwrap().to_str().unwrap())?;
-    }
-    if t1x && uo {
-        util::reg_uri_scheme("t1x")?;
-    }
-    else if iw1x && cod1 {
-        util::reg_uri_scheme("iw1x")?;
-    }
+    handlers::register(&games)?;
 
     let mut launched = false;
-    let mut args: Vec<String> = std::env::args().skip(1).collect::<Vec<_>>();
-    if let Some(first_arg) = args.get(0) {
-        if first_arg.starts_with("iw1x://") || first_arg.starts_with("t1x://") {
-            let _match = if first_arg.starts_with("iw1x://") { "iw1x://" } else { "t1x://" };
-            let stripped = first_arg.trim_start_matches(_match);
-            let parts: Vec<&str> = stripped.split(':').collect();
-            let ip = parts.get(0).unwrap_or(&"127.0.0.1");
-            let port = parts.get(1).unwrap_or(&"28960");
-
-            let scheme = first_arg.split(":").nth(0).unwrap().to_uppercase(); // IW1X or T1X
-
-            args[0] = format!("+connect {}:{}", ip, port);
-            args[1] = String::from("+set r_ignorehwgamma 1");
-
-            let args_str = args.join(" ");
-            if iw1x || t1x {
-                for game in &games {
-                    if game.name == scheme {
-                        util::notify(&format!("Launching {scheme}..."), 2000, false).unwrap();
-                        let mut game = load_game_settings(game.clone()).unwrap(); // TODO check if there's a better way to do this
-                        game.args = format!("{} {}", &game.args, &args_str);
-                        util::launch_game(&game)?;
-                        launched = true;
-                    }
+    let mut args: Vec<String> = cli.rest.clone();
+    if cli.dispatch && args.first().is_some_and(|a| uri::is_link(a)) {
+        let scheme = args[0].split_once("://").unwrap().0.to_lowercase();
+        match registry::dispatch(&scheme)? {
+            registry::Dispatch::Here => (),
+            registry::Dispatch::Elsewhere(executable) => {
+                let mut handed_args = args.clone();
+                if cli.dry_run {
+                    handed_args.push(String::from("--dry-run"));
                 }
+                return Err(registry::hand_off(&executable, &handed_args));
+            }
+            registry::Dispatch::Nowhere => {
+                eprintln!("CoDLinux: No known install can open `{scheme}://` links");
+                util::notify(&format!("No installed game can open {scheme}:// links"), 5000, false).unwrap();
+                return Ok(());
             }
         }
     }
 
-    if !launched {
+    let link = if args.first().is_some_and(|a| uri::is_link(a)) { Some(args.remove(0)) } else { None };
+    if let Some(link) = &link && cli.dry_run {
+        match game_for_link(link, &games) {
+            Ok((game, server)) => launch::print_plan(&launch::resolve(&game_with_server(&game, &server, &args))),
+            Err(e) => eprintln!("CoDLinux: {e}"),
+        }
+        launched = true;
+    }
+
+    let mut autolaunch = None;
+    if !launched && !cli.menu && link.is_none() {
         let args_str = args.join(" ");
         let saved_game = util::load_setting("saved_game").unwrap();
         if !saved_game.is_empty() {
@@ -625,16 +1858,51 @@ fn main() -> io::Result<()>
 
                     game.args = format!("{} {}", &game.args, &args_str);
 
-                    util::launch_game(&game)?;
-                    launched = true;
+                    if cli.dry_run {
+                        launch::print_plan(&launch::resolve(&game));
+                        launched = true;
+                    }
+                    else {
+                        autolaunch = Some(game);
+                    }
                 }
             }
         }
     }
 
+    if !launched && cli.dry_run {
+        for game in &games {
+            launch::print_plan(&launch::resolve(&load_game_settings(game.clone()).unwrap()));
+        }
+        launched = true;
+    }
+
     if !launched {
-        let app = RelmApp::new("wolfpack.kazam.codlinux");
-        app.run::<App>(games);
+        // Unique per install: starting this copy again, or clicking a link the
+        // dispatcher routed to it, ends up in this process through `activate`/`open`
+        let app_id = registry::application_id(&my_exe_path()?);
+        let app = gtk::Application::new(Some(&app_id), gtk::gio::ApplicationFlags::HANDLES_OPEN);
+        app.connect_activate(|app| {
+            if let Some(window) = app.windows().first() {
+                window.present();
+            }
+        });
+        app.connect_open(|app, files, _hint| {
+            if app.windows().is_empty() {
+                app.activate();
+            }
+            for file in files {
+                APP_BROKER.send(AppMsg::OpenLink(file.uri().to_string()));
+            }
+        });
+
+        let mut app_args = vec![env::args().next().unwrap_or_default()];
+        app_args.extend(link.clone());
+        RelmApp::from_app(app)
+            .with_broker(&APP_BROKER)
+            .with_args(app_args)
+            .visible_on_activate(autolaunch.is_none() && link.is_none())
+            .run::<App>(AppInit { games, autolaunch, link_args: if link.is_some() { args } else { Vec::new() } });
     }
 
     Ok(())
@@ -0,0 +1,230 @@
+use std::{io, net::{Ipv4Addr, SocketAddr, SocketAddrV4}, time::{Duration, Instant}};
+
+use crate::{query, util};
+
+/// A game as far as master servers are concerned.
+pub(crate) struct GameFamily {
+    pub label: &'static str,
+    /// Link scheme used to connect, see `handlers::candidates`
+    pub scheme: &'static str,
+    pub protocol: u32,
+    /// codlinux.cfg key holding a comma separated master list
+    pub setting: &'static str,
+    pub default_masters: &'static [&'static str],
+}
+
+pub(crate) static FAMILIES: [GameFamily; 2] = [
+    GameFamily {
+        label: "Call of Duty",
+        scheme: "cod1",
+        protocol: 1,
+        setting: "master_servers_cod1",
+        default_masters: &["codmaster.activision.com:20510", "master.cod.pm:20510"],
+    },
+    GameFamily {
+        label: "United Offensive",
+        scheme: "coduo",
+        protocol: 22,
+        setting: "master_servers_coduo",
+        default_masters: &["coduomaster.activision.com:20610", "master.cod.pm:20610"],
+    },
+];
+
+impl GameFamily {
+    pub(crate) fn masters(&self) -> Vec<String>
+    {
+        let configured = util::load_setting(self.setting).unwrap_or_default();
+        if configured.trim().is_empty() {
+            return self.default_masters.iter().map(|m| m.to_string()).collect();
+        }
+        configured.split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect()
+    }
+}
+
+pub(crate) fn family_for_game(name: &str) -> Option<&'static GameFamily>
+{
+    FAMILIES.iter().find(|f| crate::handlers::candidates(f.scheme).contains(&name))
+}
+
+/// `iw1x` and `cod1` links both belong to Call of Duty, and so on.
+pub(crate) fn family_for_scheme(scheme: &str) -> Option<&'static GameFamily>
+{
+    let candidates = crate::handlers::candidates(scheme);
+    FAMILIES.iter().find(|f| !candidates.is_empty() && crate::handlers::candidates(f.scheme) == candidates)
+}
+
+/// Family of a server that reported `protocol` in its info, used for LAN
+/// servers that weren't listed by a master.
+pub(crate) fn family_for_protocol(protocol: u32) -> Option<&'static GameFamily>
+{
+    FAMILIES.iter().find(|f| f.protocol == protocol)
+}
+
+/// `getservers <protocol> full empty`
+pub(crate) fn getservers_packet(protocol: u32) -> Vec<u8>
+{
+    query::oob_packet(&format!("getservers {protocol} full empty"))
+}
+
+/// Parses one `getserversResponse` packet into the addresses it lists.
+/// The bool is true once the `\EOT` (or `\EOF`) marker was seen, masters
+/// may split long lists over several packets.
+pub(crate) fn parse_getservers_response(packet: &[u8]) -> Option<(Vec<SocketAddr>, bool)>
+{
+    let (command, mut body) = query::parse_oob(packet)?;
+    if command != "getserversResponse" {
+        return None;
+    }
+
+    let mut servers = Vec::new();
+    while let Some(rest) = body.strip_prefix(b"\\") {
+        if rest.starts_with(b"EOT") || rest.starts_with(b"EOF") {
+            return Some((servers, true));
+        }
+        if rest.len() < 6 {
+            break;
+        }
+        let ip = Ipv4Addr::new(rest[0], rest[1], rest[2], rest[3]);
+        let port = u16::from_be_bytes([rest[4], rest[5]]);
+        if port != 0 && !ip.is_unspecified() {
+            servers.push(SocketAddr::V4(SocketAddrV4::new(ip, port)));
+        }
+        body = &rest[6..];
+    }
+    Some((servers, false))
+}
+
+/// Builds `getserversResponse` packets for `servers`, the counterpart of
+/// `parse_getservers_response`.
+pub(crate) fn getservers_response(servers: &[SocketAddrV4]) -> Vec<Vec<u8>>
+{
+    // Stay well under a typical MTU like real masters do
+    let chunks: Vec<&[SocketAddrV4]> = if servers.is_empty() { vec![&[]] } else { servers.chunks(200).collect() };
+    let last = chunks.len() - 1;
+    chunks.into_iter().enumerate().map(|(i, chunk)| {
+        let mut packet = query::oob_packet("getserversResponse");
+        for server in chunk {
+            packet.push(b'\\');
+            packet.extend_from_slice(&server.ip().octets());
+            packet.extend_from_slice(&server.port().to_be_bytes());
+        }
+        if i == last {
+            packet.extend_from_slice(b"\\EOT\0\0\0");
+        }
+        packet
+    }).collect()
+}
+
+/// Asks one master server for its list.
+pub(crate) fn query_master(master: SocketAddr, protocol: u32, timeout: Duration) -> io::Result<Vec<SocketAddr>>
+{
+    let socket = query::bind_for(&[master])?;
+    socket.send_to(&getservers_packet(protocol), master)?;
+
+    let mut servers = Vec::new();
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 65536];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        socket.set_read_timeout(Some(left.max(Duration::from_millis(1))))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        if from != master { continue; }
+        if let Some((mut list, done)) = parse_getservers_response(&buffer[..len]) {
+            servers.append(&mut list);
+            if done { break; }
+        }
+    }
+    Ok(servers)
+}
+
+/// Asks every master and merges the lists, skipping masters that fail.
+pub(crate) fn query_masters(masters: &[String], protocol: u32, timeout: Duration) -> Vec<SocketAddr>
+{
+    let mut servers: Vec<SocketAddr> = Vec::new();
+    for master in masters {
+        let result = query::resolve(master).and_then(|addr| query_master(addr, protocol, timeout));
+        match result {
+            Ok(list) => {
+                println!("CoDLinux: {} listed {} servers", master, list.len());
+                for server in list {
+                    if !servers.contains(&server) {
+                        servers.push(server);
+                    }
+                }
+            }
+            Err(e) => eprintln!("CoDLinux: Master server {} failed: {}", master, e),
+        }
+    }
+    servers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn servers(count: u8) -> Vec<SocketAddrV4>
+    {
+        (1..=count).map(|i| SocketAddrV4::new(Ipv4Addr::new(10, 0, i / 100, i % 100), 28960 + i as u16)).collect()
+    }
+
+    #[test]
+    fn getservers_response_round_trip()
+    {
+        let listed = servers(3);
+        let packets = getservers_response(&listed);
+        assert_eq!(packets.len(), 1);
+        let (parsed, done) = parse_getservers_response(&packets[0]).unwrap();
+        assert!(done);
+        assert_eq!(parsed, listed.iter().map(|s| SocketAddr::V4(*s)).collect::<Vec<_>>());
+
+        let (parsed, done) = parse_getservers_response(&getservers_response(&[])[0]).unwrap();
+        assert!(parsed.is_empty() && done);
+        assert!(parse_getservers_response(&query::oob_packet("infoResponse\n")).is_none());
+    }
+
+    #[test]
+    fn zero_and_truncated_entries_are_skipped()
+    {
+        let mut packet = query::oob_packet("getserversResponse");
+        packet.extend_from_slice(b"\\\x00\x00\x00\x00\x6f\x60");
+        packet.extend_from_slice(b"\\\x01\x02\x03\x04\x00\x00");
+        packet.extend_from_slice(b"\\\x01\x02\x03\x04\x6f\x60");
+        packet.extend_from_slice(b"\\\x01\x02");
+        let (parsed, done) = parse_getservers_response(&packet).unwrap();
+        assert_eq!(parsed, [SocketAddr::from(([1, 2, 3, 4], 28512))]);
+        assert!(!done);
+    }
+
+    #[test]
+    fn query_a_local_master()
+    {
+        // 250 servers take two packets, only the last one ends with EOT
+        let listed = servers(250);
+        let packets = getservers_response(&listed);
+        assert_eq!(packets.len(), 2);
+        let master = query::fake_server(move |packet| {
+            match query::parse_oob(packet) {
+                Some((cmd, rest)) if cmd == "getservers" && rest.starts_with(b"1 ") => packets.clone(),
+                _ => Vec::new(),
+            }
+        });
+
+        let found = query_master(master, 1, Duration::from_secs(2)).unwrap();
+        assert_eq!(found.len(), 250);
+        assert_eq!(found[0], SocketAddr::V4(listed[0]));
+
+        // Wrong protocol: nothing comes back, the query times out empty
+        let started = Instant::now();
+        assert!(query_master(master, 22, Duration::from_millis(300)).unwrap().is_empty());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let merged = query_masters(&[master.to_string(), master.to_string()], 1, Duration::from_secs(2));
+        assert_eq!(merged.len(), 250);
+    }
+}
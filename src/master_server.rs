@@ -0,0 +1,243 @@
+use std::{
+    collections::BTreeMap, io, net::{SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}
+};
+
+use crate::{master, query::{self, ServerInfo}, util};
+
+/// Default UDP port, the one Call of Duty clients ask first.
+pub(crate) static DEFAULT_PORT: u16 = 20510;
+/// Servers heartbeat every few minutes, forget them after this much silence.
+static SERVER_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// How long a `getinfo` challenge stays valid.
+static CHALLENGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Registered servers, shared with whoever shows them.
+pub(crate) type ServerList = Arc<Mutex<BTreeMap<SocketAddrV4, (ServerInfo, Instant)>>>;
+
+/// A minimal master server: dedicated servers register with `heartbeat`,
+/// we check them with `getinfo` and list them in `getserversResponse`.
+pub(crate) struct MasterServer {
+    socket: UdpSocket,
+    servers: ServerList,
+    /// Challenges sent with `getinfo`, by server address
+    challenges: BTreeMap<SocketAddr, (String, Instant)>,
+}
+
+/// `private_master_port` in codlinux.cfg.
+pub(crate) fn configured_port() -> u16
+{
+    util::load_setting("private_master_port").unwrap_or_default().parse().unwrap_or(DEFAULT_PORT)
+}
+
+impl MasterServer {
+    pub(crate) fn bind(port: u16) -> io::Result<MasterServer>
+    {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+        Ok(MasterServer { socket, servers: ServerList::default(), challenges: BTreeMap::new() })
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr>
+    {
+        self.socket.local_addr()
+    }
+
+    pub(crate) fn servers(&self) -> ServerList
+    {
+        self.servers.clone()
+    }
+
+    /// Serves until `stop` is set.
+    pub(crate) fn run(mut self, stop: Arc<AtomicBool>) -> io::Result<()>
+    {
+        let mut buffer = [0u8; 16384];
+        while !stop.load(Ordering::Relaxed) {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    let packet = buffer[..len].to_vec();
+                    if let Err(e) = self.handle(&packet, from) {
+                        eprintln!("CoDLinux: Master server: Failed to answer {from}: {e}");
+                    }
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => (),
+                Err(e) => return Err(e),
+            }
+            self.prune();
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &[u8], from: SocketAddr) -> io::Result<()>
+    {
+        let Some((command, body)) = query::parse_oob(packet) else { return Ok(()); };
+        let body = String::from_utf8_lossy(body);
+        match command.as_str() {
+            "heartbeat" => self.heartbeat(body.trim(), from),
+            "infoResponse" => {
+                self.register(&body, from);
+                Ok(())
+            }
+            "getservers" => self.getservers(body.trim(), from),
+            _ => Ok(()),
+        }
+    }
+
+    /// `heartbeat COD-1` asks to be listed, `heartbeat flatline` to be removed.
+    fn heartbeat(&mut self, game: &str, from: SocketAddr) -> io::Result<()>
+    {
+        let SocketAddr::V4(addr) = from else { return Ok(()); };
+        if game.eq_ignore_ascii_case("flatline") {
+            if self.servers.lock().unwrap().remove(&addr).is_some() {
+                println!("CoDLinux: Master server: {addr} shut down");
+            }
+            return Ok(());
+        }
+        let challenge = challenge();
+        self.socket.send_to(&query::oob_packet(&format!("getinfo {challenge}")), from)?;
+        self.challenges.insert(from, (challenge, Instant::now()));
+        Ok(())
+    }
+
+    /// Lists a server once it answered our challenge.
+    fn register(&mut self, body: &str, from: SocketAddr)
+    {
+        let SocketAddr::V4(addr) = from else { return; };
+        let Some((challenge, _)) = self.challenges.remove(&from) else { return; };
+        let info = query::parse_info_string(body);
+        if info.get("challenge") != Some(&challenge) {
+            eprintln!("CoDLinux: Master server: {from} answered with the wrong challenge");
+            return;
+        }
+        let info = ServerInfo::from_info(from, 0, info);
+        let mut servers = self.servers.lock().unwrap();
+        if !servers.contains_key(&addr) {
+            println!("CoDLinux: Master server: Registered {} ({})", addr, query::strip_colours(&info.hostname));
+        }
+        servers.insert(addr, (info, Instant::now()));
+    }
+
+    /// `getservers <protocol> [empty] [full]`
+    fn getservers(&self, args: &str, from: SocketAddr) -> io::Result<()>
+    {
+        let mut words = args.split_whitespace();
+        let Some(protocol) = words.next().and_then(|p| p.parse::<u32>().ok()) else { return Ok(()); };
+        let keywords: Vec<&str> = words.collect();
+        let (empty, full) = (keywords.contains(&"empty"), keywords.contains(&"full"));
+
+        let matching: Vec<SocketAddrV4> = self.servers.lock().unwrap().iter()
+            .filter(|(_, (info, _))| server_protocol(info) == Some(protocol))
+            .filter(|(_, (info, _))| empty || info.clients > 0)
+            .filter(|(_, (info, _))| full || !info.is_full())
+            .map(|(addr, _)| *addr)
+            .collect();
+        for packet in master::getservers_response(&matching) {
+            self.socket.send_to(&packet, from)?;
+        }
+        Ok(())
+    }
+
+    fn prune(&mut self)
+    {
+        self.challenges.retain(|_, (_, sent)| sent.elapsed() < CHALLENGE_TIMEOUT);
+        self.servers.lock().unwrap().retain(|addr, (_, seen)| {
+            let alive = seen.elapsed() < SERVER_TIMEOUT;
+            if !alive {
+                println!("CoDLinux: Master server: {addr} timed out");
+            }
+            alive
+        });
+    }
+}
+
+pub(crate) fn server_protocol(info: &ServerInfo) -> Option<u32>
+{
+    info.info.get("protocol").and_then(|p| p.parse().ok())
+}
+
+/// Not meant to be secret, only to match answers to our own `getinfo`.
+fn challenge() -> String
+{
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+    format!("{:08x}", nanos ^ std::process::id())
+}
+
+/// `codlinux --master`: serve in the foreground until killed.
+pub(crate) fn run_headless(port: u16) -> io::Result<()>
+{
+    let server = MasterServer::bind(port)?;
+    println!("CoDLinux: Master server listening on {}", server.local_addr()?);
+    println!("CoDLinux: Point dedicated servers at it with +set sv_master1 <this host>:{port}");
+    server.run(Arc::new(AtomicBool::new(false)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Waits up to 2 seconds for the list to satisfy `done`.
+    fn wait_for(servers: &ServerList, done: impl Fn(&BTreeMap<SocketAddrV4, (ServerInfo, Instant)>) -> bool) -> bool
+    {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            if done(&servers.lock().unwrap()) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    /// A dedicated server that heartbeats and answers the challenge,
+    /// with `challenge` replaced when given.
+    fn heartbeat(master: SocketAddr, challenge: Option<&str>) -> SocketAddr
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        socket.send_to(&query::oob_packet("heartbeat COD-1"), master).unwrap();
+
+        let mut buffer = [0u8; 1024];
+        let (len, _) = socket.recv_from(&mut buffer).unwrap();
+        let (command, body) = query::parse_oob(&buffer[..len]).unwrap();
+        assert_eq!(command, "getinfo");
+        let sent = String::from_utf8_lossy(body).trim().to_string();
+        let challenge = challenge.unwrap_or(&sent);
+        let info = format!("infoResponse\n\\challenge\\{challenge}\\protocol\\1\\hostname\\Test\\clients\\2\\sv_maxclients\\16");
+        socket.send_to(&query::oob_packet(&info), master).unwrap();
+        socket.local_addr().unwrap()
+    }
+
+    #[test]
+    fn heartbeat_getinfo_register()
+    {
+        let server = MasterServer::bind(0).unwrap();
+        let master = SocketAddr::from(([127, 0, 0, 1], server.local_addr().unwrap().port()));
+        let servers = server.servers();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || server.run(stop)
+        });
+
+        let game_server = heartbeat(master, None);
+        let SocketAddr::V4(v4) = game_server else { unreachable!() };
+        assert!(wait_for(&servers, |list| list.contains_key(&v4)));
+        assert_eq!(servers.lock().unwrap()[&v4].0.hostname, "Test");
+
+        // Listed for its own protocol only
+        assert_eq!(master::query_master(master, 1, Duration::from_secs(2)).unwrap(), [game_server]);
+        assert!(master::query_master(master, 22, Duration::from_millis(500)).unwrap().is_empty());
+
+        // A wrong challenge doesn't get listed
+        let impostor = heartbeat(master, Some("nope"));
+        let SocketAddr::V4(impostor) = impostor else { unreachable!() };
+        assert!(!wait_for(&servers, |list| list.contains_key(&impostor)));
+
+        let socket = UdpSocket::bind(game_server).unwrap();
+        socket.send_to(&query::oob_packet("heartbeat flatline"), master).unwrap();
+        assert!(wait_for(&servers, |list| list.is_empty()));
+
+        stop.store(true, Ordering::Relaxed);
+        thread.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use relm4::{gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::glib;
+
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use crate::{master, master_server::{self, MasterServer, ServerList}, query};
+
+/// Runs the built-in master server and lists what registered with it.
+pub struct MasterWindow {
+    port: u16,
+    /// Stop flag and server list of the running master
+    running: Option<(Arc<AtomicBool>, ServerList)>,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum MasterMsg {
+    SetPort(u16),
+    /// Start or stop, depending on whether it's running
+    Toggle,
+    Refresh,
+}
+
+#[relm4::component(pub)]
+impl Component for MasterWindow {
+    type Init = ();
+    type Input = MasterMsg;
+    type Output = ();
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_title: Some("Private Master Server"),
+            set_default_size: (560, 360),
+            // Closing only hides, the master keeps running until stopped
+            set_hide_on_close: true,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::Label {
+                        set_text: "UDP port",
+                    },
+                    gtk::SpinButton::with_range(1.0, 65535.0, 1.0) {
+                        set_value: model.port as f64,
+                        #[watch]
+                        set_sensitive: model.running.is_none(),
+                        connect_value_changed[sender] => move |spin| {
+                            sender.input(MasterMsg::SetPort(spin.value() as u16));
+                        },
+                    },
+                    gtk::Button {
+                        #[watch]
+                        set_label: if model.running.is_some() { "Stop" } else { "Start" },
+                        connect_clicked => MasterMsg::Toggle,
+                    },
+                    gtk::Label {
+                        #[watch]
+                        set_text: &model.status(),
+                        set_hexpand: true,
+                        set_halign: gtk::Align::End,
+                    },
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_markup: &format!(
+                        "<small>Start dedicated servers with <tt>+set sv_master1 &lt;this host&gt;:{0}</tt> and set <tt>master_servers_cod1</tt> / <tt>master_servers_coduo</tt> to <tt>&lt;this host&gt;:{0}</tt> on the clients.</small>",
+                        model.port
+                    ),
+                    set_wrap: true,
+                    set_xalign: 0.0,
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                    #[name = "servers"]
+                    gtk::Grid {
+                        set_row_spacing: 4,
+                        set_column_spacing: 12,
+                    },
+                },
+            }
+        }
+    }
+
+    fn init(_: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let model = MasterWindow {
+            port: master_server::configured_port(),
+            running: None,
+            error: None,
+        };
+        let widgets = view_output!();
+
+        let input = sender.input_sender().clone();
+        glib::timeout_add_seconds_local(2, move || {
+            if input.send(MasterMsg::Refresh).is_ok() {
+                glib::ControlFlow::Continue
+            }
+            else {
+                glib::ControlFlow::Break
+            }
+        });
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            MasterMsg::SetPort(port) => self.port = port,
+            MasterMsg::Toggle => {
+                if let Some((stop, _)) = self.running.take() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                else {
+                    self.start();
+                }
+            }
+            MasterMsg::Refresh => (),
+        }
+        fill_servers(&widgets.servers, self.running.as_ref().map(|(_, servers)| servers));
+        self.update_view(widgets, sender);
+    }
+}
+
+impl MasterWindow {
+    fn start(&mut self)
+    {
+        match MasterServer::bind(self.port) {
+            Ok(server) => {
+                let stop = Arc::new(AtomicBool::new(false));
+                self.running = Some((stop.clone(), server.servers()));
+                self.error = None;
+                std::thread::spawn(move || {
+                    if let Err(e) = server.run(stop) {
+                        eprintln!("CoDLinux: Master server stopped: {e}");
+                    }
+                });
+            }
+            Err(e) => self.error = Some(format!("Can't listen on port {}: {}", self.port, e)),
+        }
+    }
+
+    fn status(&self) -> String
+    {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        match &self.running {
+            Some((_, servers)) => format!("Running, {} servers registered", servers.lock().unwrap().len()),
+            None => String::from("Stopped"),
+        }
+    }
+}
+
+impl Drop for MasterWindow {
+    fn drop(&mut self)
+    {
+        if let Some((stop, _)) = &self.running {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+fn fill_servers(grid: &gtk::Grid, servers: Option<&ServerList>)
+{
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+    for (col, title) in ["Server", "Game", "Map", "Players", "Address"].iter().enumerate() {
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!("<b>{title}</b>"));
+        label.set_xalign(0.0);
+        grid.attach(&label, col as i32, 0, 1, 1);
+    }
+    let Some(servers) = servers else { return; };
+    for (row, (addr, (info, _))) in servers.lock().unwrap().iter().enumerate() {
+        let game = master_server::server_protocol(info)
+            .and_then(master::family_for_protocol)
+            .map(|f| f.label)
+            .unwrap_or("?");
+        let cells = [
+            query::strip_colours(&info.hostname),
+            game.to_string(),
+            info.map.clone(),
+            format!("{}/{}", info.clients, info.max_clients),
+            addr.to_string(),
+        ];
+        for (col, text) in cells.iter().enumerate() {
+            let label = gtk::Label::new(Some(text));
+            label.set_xalign(0.0);
+            label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            grid.attach(&label, col as i32, row as i32 + 1, 1, 1);
+        }
+    }
+}
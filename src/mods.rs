@@ -0,0 +1,433 @@
+use relm4::{gtk, Component, ComponentController, ComponentParts, ComponentSender, Controller, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::gio;
+
+use std::{fs, io, path::{Path, PathBuf}, process::{Command, Stdio}};
+
+use crate::{downloads, pk3::{self, Inventory}, GameInfo};
+
+/// Disabled pk3s are moved here, inside their folder. The game only loads
+/// pk3s sitting directly in a folder.
+pub(crate) static DISABLED_DIR: &str = "codlinux_disabled";
+
+fn file_name(path: &Path) -> String
+{
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+pub(crate) fn is_disabled(pk3: &Path) -> bool
+{
+    pk3.parent().is_some_and(|p| file_name(p) == DISABLED_DIR)
+}
+
+/// The game's own folders, then every folder next to them with enabled or
+/// disabled pk3s.
+pub(crate) fn managed_folders(game: &GameInfo) -> Vec<PathBuf>
+{
+    let mut folders = pk3::game_folders(game);
+    let Some(dir) = game.path.parent() else { return folders; };
+    let mut mods: Vec<PathBuf> = fs::read_dir(dir).into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && !folders.contains(p))
+        .filter(|p| !pk3::pk3s_in(p).is_empty() || !pk3::pk3s_in(&p.join(DISABLED_DIR)).is_empty())
+        .collect();
+    mods.sort();
+    folders.extend(mods);
+    folders
+}
+
+/// Moves `pk3` into `aside` inside its folder, or back out of it with None.
+pub(crate) fn move_pk3(pk3: &Path, aside: Option<&str>) -> io::Result<PathBuf>
+{
+    let folder = pk3.parent().ok_or_else(|| io::Error::other("pk3 has no folder"))?;
+    let dest = match aside {
+        Some(aside) => folder.join(aside).join(pk3.file_name().unwrap_or_default()),
+        None => folder.parent().ok_or_else(|| io::Error::other("pk3 has no folder"))?.join(pk3.file_name().unwrap_or_default()),
+    };
+    if dest.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", dest.display())));
+    }
+    fs::create_dir_all(dest.parent().unwrap())?;
+    fs::rename(pk3, &dest)?;
+    println!("CoDLinux: Moved {} to {}", pk3.display(), dest.display());
+    Ok(dest)
+}
+
+/// Moves `pk3` in or out of its folder's `codlinux_disabled`.
+pub(crate) fn set_enabled(pk3: &Path, enabled: bool) -> io::Result<PathBuf>
+{
+    move_pk3(pk3, (!enabled).then_some(DISABLED_DIR))
+}
+
+/// Installs a pk3 into `target`, or the pk3s of a zip. Zips laid out as
+/// `<mod>/<file>.pk3` are extracted as they are, anything else has its
+/// pk3s put into `target`.
+pub(crate) fn install(game: &GameInfo, archive: &Path, target: &str) -> io::Result<String>
+{
+    let dir = game.path.parent().ok_or_else(|| io::Error::other("game has no folder"))?;
+    let is_pk3 = archive.extension().is_some_and(|e| e.eq_ignore_ascii_case("pk3"));
+    if is_pk3 {
+        let dest = dir.join(target).join(file_name(archive));
+        if dest.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", dest.display())));
+        }
+        fs::create_dir_all(dest.parent().unwrap())?;
+        fs::copy(archive, &dest)?;
+        downloads::record_user_installed(&[format!("{}/{}", target, file_name(archive))])?;
+        return Ok(format!("Installed {} into {}", file_name(archive), target));
+    }
+
+    let installed = extract_pk3s(archive, dir, target)?;
+    downloads::record_user_installed(&installed)?;
+    let mut folders: Vec<&str> = installed.iter().filter_map(|n| n.split('/').next()).collect();
+    folders.sort();
+    folders.dedup();
+    Ok(format!("Installed {} pk3s from {} into {}", installed.len(), file_name(archive), folders.join(", ")))
+}
+
+/// Extracts only the pk3s of a zip into the game folder `dir`, returns
+/// them as `<folder>/<file>.pk3`. Nothing is overwritten: when one of them
+/// exists already, nothing is extracted at all.
+fn extract_pk3s(archive: &Path, dir: &Path, target: &str) -> io::Result<Vec<String>>
+{
+    let entries = pk3::entries(archive)?;
+    let pk3s: Vec<&str> = entries.iter()
+        .map(|e| e.name.as_str())
+        .filter(|n| n.to_lowercase().ends_with(".pk3"))
+        .collect();
+    if pk3s.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has no pk3 files", file_name(archive))));
+    }
+    // `../x.pk3` or `/x.pk3` would land outside the game folder
+    let unsafe_path = |n: &str| n.contains('\\') || !Path::new(n).components().all(|c| matches!(c, std::path::Component::Normal(_)));
+    if let Some(name) = pk3s.iter().find(|n| unsafe_path(n)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has a pk3 outside its folders: {name}", file_name(archive))));
+    }
+
+    let nested = pk3s.iter().all(|n| n.matches('/').count() == 1);
+    let installed: Vec<String> = pk3s.iter()
+        .map(|n| if nested { n.to_string() } else { format!("{}/{}", target, n.rsplit('/').next().unwrap_or(n)) })
+        .collect();
+    if let Some(existing) = installed.iter().map(|n| dir.join(n)).find(|p| p.exists()) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", existing.display())));
+    }
+
+    // `-n` on top of the check above, unzip never asks or overwrites
+    let mut unzip = Command::new("unzip");
+    unzip.args(["-n", "-C"]).stdin(Stdio::null());
+    if nested {
+        unzip.arg(archive).arg("*/*.pk3").arg("-d").arg(dir);
+    }
+    else {
+        unzip.arg("-j").arg(archive).arg("*.pk3").arg("-d").arg(dir.join(target));
+    }
+    let output = unzip.output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => io::Error::new(e.kind(), "unzip is not installed, it is needed to install from zips"),
+        _ => io::Error::new(e.kind(), format!("Can't run unzip: {e}")),
+    })?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("unzip failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(installed)
+}
+
+/// Mod folders and pk3s of one game.
+pub(crate) struct ModManager {
+    game: GameInfo,
+    inventory: Inventory,
+    /// Folder names offered as install targets
+    targets: gtk::StringList,
+    status: String,
+    downloads: Option<Controller<downloads::DownloadsWindow>>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ModMsg {
+    Refresh,
+    /// Enables a disabled pk3, disables an enabled one
+    Toggle(PathBuf),
+    ChooseInstall,
+    Install(PathBuf),
+    Launch(String),
+    ShowDownloads,
+}
+
+#[derive(Debug)]
+pub(crate) enum ModOutput {
+    /// Game name and the fs_game to start it with
+    Launch(String, String),
+}
+
+#[relm4::component(pub(crate))]
+impl Component for ModManager {
+    type Init = GameInfo;
+    type Input = ModMsg;
+    type Output = ModOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_title: Some(&format!("Mods: {}", model.game.name)),
+            set_default_size: (640, 520),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                    #[name = "folders"]
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 4,
+                    },
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_text: &model.status,
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::Label {
+                        set_text: "Install into",
+                    },
+                    #[name = "target"]
+                    gtk::DropDown {
+                        set_model: Some(&model.targets),
+                        set_tooltip_text: Some("Where single pk3s go, zips with a mod folder inside keep theirs"),
+                    },
+                    gtk::Button {
+                        set_label: "Install zip or pk3…",
+                        connect_clicked => ModMsg::ChooseInstall,
+                    },
+                    gtk::Button {
+                        set_label: "Downloaded pk3s…",
+                        set_tooltip_text: Some("pk3s pulled from servers, with quarantine and delete"),
+                        set_hexpand: true,
+                        set_halign: gtk::Align::End,
+                        connect_clicked => ModMsg::ShowDownloads,
+                    },
+                    gtk::Button {
+                        set_icon_name: "view-refresh",
+                        set_tooltip_text: Some("Rescan"),
+                        connect_clicked => ModMsg::Refresh,
+                    },
+                },
+            }
+        }
+    }
+
+    fn init(game: Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let model = ModManager {
+            inventory: Inventory::default(),
+            targets: gtk::StringList::new(&[]),
+            game,
+            status: String::new(),
+            downloads: None,
+        };
+        let widgets = view_output!();
+        sender.input(ModMsg::Refresh);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::Input, sender: ComponentSender<Self>, root: &Self::Root)
+    {
+        match msg {
+            ModMsg::Refresh => (),
+            ModMsg::Toggle(pk3) => {
+                self.status = match set_enabled(&pk3, is_disabled(&pk3)) {
+                    Ok(dest) if is_disabled(&dest) => format!("Disabled {}", file_name(&pk3)),
+                    Ok(_) => format!("Enabled {}", file_name(&pk3)),
+                    Err(e) => format!("Can't move {}: {e}", file_name(&pk3)),
+                };
+            }
+            ModMsg::ChooseInstall => {
+                let filter = gtk::FileFilter::new();
+                filter.set_name(Some("Mods (zip, pk3)"));
+                filter.add_suffix("zip");
+                filter.add_suffix("pk3");
+                let dialog = gtk::FileDialog::builder()
+                    .title("Install a mod")
+                    .default_filter(&filter)
+                    .modal(true)
+                    .build();
+                let input = sender.input_sender().clone();
+                dialog.open(Some(root), gio::Cancellable::NONE, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        input.emit(ModMsg::Install(path));
+                    }
+                });
+                return;
+            }
+            ModMsg::Install(archive) => {
+                let target = self.targets.string(widgets.target.selected()).map(|s| s.to_string()).unwrap_or_else(|| String::from("main"));
+                self.status = install(&self.game, &archive, &target).unwrap_or_else(|e| format!("Install failed: {e}"));
+                println!("CoDLinux: {}", self.status);
+            }
+            ModMsg::Launch(fs_game) => {
+                sender.output(ModOutput::Launch(self.game.name.clone(), fs_game)).unwrap();
+                return;
+            }
+            ModMsg::ShowDownloads => {
+                match &self.downloads {
+                    Some(window) => window.emit(downloads::DownloadsMsg::Rescan),
+                    None => {
+                        let window = downloads::DownloadsWindow::builder().launch(self.game.clone()).detach();
+                        root.application().unwrap().add_window(window.widget());
+                        self.downloads = Some(window);
+                    }
+                }
+                self.downloads.as_ref().unwrap().widget().present();
+                return;
+            }
+        }
+
+        self.inventory = Inventory::scan(&self.game);
+        let folders = managed_folders(&self.game);
+        let selected = self.targets.string(widgets.target.selected());
+        let names: Vec<String> = folders.iter().map(|f| file_name(f)).collect();
+        let names_ref: Vec<&str> = names.iter().map(String::as_str).collect();
+        self.targets.splice(0, self.targets.n_items(), &names_ref);
+        if let Some(pos) = selected.and_then(|s| names.iter().position(|n| *n == s.as_str())) {
+            widgets.target.set_selected(pos as u32);
+        }
+        self.fill(&widgets.folders, &folders, &sender);
+        self.update_view(widgets, sender);
+    }
+}
+
+impl ModManager {
+    fn fill(&self, list: &gtk::Box, folders: &[PathBuf], sender: &ComponentSender<Self>)
+    {
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+        let conflicts = self.inventory.conflicts(&self.game);
+        let own = pk3::game_folders(&self.game);
+
+        for folder in folders {
+            let name = file_name(folder);
+            let header = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            header.set_margin_top(6);
+            let title = gtk::Label::new(None);
+            title.set_markup(&format!("<b>{}</b>", gtk::glib::markup_escape_text(&name)));
+            title.set_hexpand(true);
+            title.set_xalign(0.0);
+            header.append(&title);
+            if !own.contains(folder) {
+                let play = gtk::Button::with_label("Play");
+                play.set_tooltip_text(Some(&format!("Start {} with +set fs_game {}", self.game.name, name)));
+                let input = sender.input_sender().clone();
+                let fs_game = name.clone();
+                play.connect_clicked(move |_| input.emit(ModMsg::Launch(fs_game.clone())));
+                header.append(&play);
+            }
+            list.append(&header);
+
+            let enabled = pk3::pk3s_in(folder);
+            let disabled = pk3::pk3s_in(&folder.join(DISABLED_DIR));
+            for pk3 in enabled.iter().chain(disabled.iter()) {
+                list.append(&self.pk3_row(pk3, &conflicts, sender));
+            }
+        }
+    }
+
+    fn pk3_row(&self, pk3: &Path, conflicts: &[pk3::Conflict], sender: &ComponentSender<Self>) -> gtk::Box
+    {
+        let disabled = is_disabled(pk3);
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        row.set_margin_start(12);
+
+        let label = gtk::Label::new(Some(&file_name(pk3)));
+        label.set_xalign(0.0);
+        label.set_hexpand(true);
+        label.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
+        label.set_sensitive(!disabled);
+        row.append(&label);
+
+        if let Some(content) = self.inventory.pk3s.iter().find(|p| p.path == pk3) {
+            let details = gtk::Label::new(Some(&format!(
+                "{:.1} MB, {} maps",
+                content.size as f64 / (1024.0 * 1024.0),
+                content.maps.len(),
+            )));
+            row.append(&details);
+        }
+
+        let overrides: Vec<String> = conflicts.iter()
+            .filter(|c| c.winner == pk3 || c.overridden == pk3)
+            .map(|c| if c.winner == pk3 {
+                format!("Overrides {} files of {}", c.files.len(), file_name(&c.overridden))
+            } else {
+                format!("{} files overridden by {}", c.files.len(), file_name(&c.winner))
+            })
+            .collect();
+        if !overrides.is_empty() {
+            let warning = gtk::Image::from_icon_name("dialog-warning");
+            warning.set_tooltip_text(Some(&overrides.join("\n")));
+            row.append(&warning);
+        }
+
+        let toggle = gtk::Button::with_label(if disabled { "Enable" } else { "Disable" });
+        let input = sender.input_sender().clone();
+        let path = pk3.to_path_buf();
+        toggle.connect_clicked(move |_| input.emit(ModMsg::Toggle(path.clone())));
+        row.append(&toggle);
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_only_pk3s_and_never_overwrite()
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-mods-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let nested = dir.join("awe.zip");
+        pk3::write_test_pk3(&nested, &["awe/", "awe/awe.pk3", "awe/readme.txt", "install.txt"]);
+        let game_dir = dir.join("game");
+
+        assert_eq!(extract_pk3s(&nested, &game_dir, "main").unwrap(), ["awe/awe.pk3"]);
+        assert!(game_dir.join("awe").join("awe.pk3").is_file());
+        assert!(!game_dir.join("awe").join("readme.txt").exists() && !game_dir.join("install.txt").exists());
+        assert_eq!(extract_pk3s(&nested, &game_dir, "main").unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        // Loose pk3s go into the target, none of them if one is there already
+        let flat = dir.join("maps.zip");
+        pk3::write_test_pk3(&flat, &["zz_one.pk3", "maps/zz_two.PK3", "readme.txt"]);
+        fs::create_dir_all(game_dir.join("main")).unwrap();
+        fs::write(game_dir.join("main").join("zz_two.PK3"), b"mine").unwrap();
+        assert_eq!(extract_pk3s(&flat, &game_dir, "main").unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert!(!game_dir.join("main").join("zz_one.pk3").exists());
+        assert_eq!(fs::read(game_dir.join("main").join("zz_two.PK3")).unwrap(), b"mine");
+
+        fs::remove_file(game_dir.join("main").join("zz_two.PK3")).unwrap();
+        assert_eq!(extract_pk3s(&flat, &game_dir, "main").unwrap(), ["main/zz_one.pk3", "main/zz_two.PK3"]);
+        assert!(game_dir.join("main").join("zz_one.pk3").is_file() && game_dir.join("main").join("zz_two.PK3").is_file());
+        assert!(!game_dir.join("main").join("readme.txt").exists());
+
+        for name in ["../evil.pk3", "/tmp/evil.pk3", "awe/../../evil.pk3", "..\\evil.pk3"] {
+            let crafted = dir.join("crafted.zip");
+            pk3::write_test_pk3(&crafted, &[name]);
+            assert_eq!(extract_pk3s(&crafted, &game_dir, "main").unwrap_err().kind(), io::ErrorKind::InvalidData, "{name}");
+        }
+        assert!(!dir.join("evil.pk3").exists() && !game_dir.join("evil.pk3").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,430 @@
+use std::{collections::HashMap, fs::{self, File}, io::{self, Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+
+use crate::{master, GameInfo};
+
+/// One file inside a pk3, from the zip central directory.
+#[derive(Debug, Clone)]
+pub(crate) struct Pk3Entry {
+    pub name: String,
+    pub size: u64,
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16
+{
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32
+{
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn bad_zip(path: &Path, why: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), why))
+}
+
+/// Lists a pk3 (a zip) by reading its central directory, nothing is
+/// decompressed.
+pub(crate) fn entries(path: &Path) -> io::Result<Vec<Pk3Entry>>
+{
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    // The end of central directory record is in the last 64k + 22 bytes
+    let tail_len = len.min(65557);
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0u8; tail_len as usize];
+    file.read_exact(&mut tail)?;
+    let eocd = (0..tail.len().saturating_sub(21)).rev()
+        .find(|i| tail[*i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| bad_zip(path, "not a zip file"))?;
+    let count = u16_at(&tail, eocd + 10) as usize;
+    let dir_size = u32_at(&tail, eocd + 12) as usize;
+    let dir_offset = u32_at(&tail, eocd + 16) as u64;
+
+    file.seek(SeekFrom::Start(dir_offset))?;
+    let mut dir = vec![0u8; dir_size];
+    file.read_exact(&mut dir)?;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut at = 0;
+    while at + 46 <= dir.len() && dir[at..].starts_with(b"PK\x01\x02") {
+        let size = u32_at(&dir, at + 24) as u64;
+        let name_len = u16_at(&dir, at + 28) as usize;
+        let extra_len = u16_at(&dir, at + 30) as usize;
+        let comment_len = u16_at(&dir, at + 32) as usize;
+        let name = dir.get(at + 46..at + 46 + name_len).ok_or_else(|| bad_zip(path, "truncated central directory"))?;
+        entries.push(Pk3Entry { name: String::from_utf8_lossy(name).replace('\\', "/"), size });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Folders the game loads pk3s from, `main` plus `uo` for United Offensive.
+pub(crate) fn game_folders(game: &GameInfo) -> Vec<PathBuf>
+{
+    let Some(dir) = game.path.parent() else { return Vec::new(); };
+    let mut folders = vec![dir.join("main")];
+    if master::family_for_game(&game.name).is_some_and(|f| f.scheme == "coduo") {
+        folders.push(dir.join("uo"));
+    }
+    folders
+}
+
+/// Every pk3 directly inside `folder`, sorted like the game loads them.
+pub(crate) fn pk3s_in(folder: &Path) -> Vec<PathBuf>
+{
+    let mut pk3s: Vec<PathBuf> = fs::read_dir(folder).into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("pk3")))
+        .collect();
+    pk3s.sort();
+    pk3s
+}
+
+/// What one pk3 provides, by the names the game uses.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Pk3Content {
+    pub path: PathBuf,
+    /// `main`, `uo` or the mod folder it is in
+    pub folder: String,
+    pub files: usize,
+    pub size: u64,
+    /// `maps/mp/<map>.bsp`
+    pub maps: Vec<String>,
+    /// `<name>.arena`, the map list entries of the menus
+    pub arenas: Vec<String>,
+    /// `maps/mp/gametypes/<type>.gsc`
+    pub gametypes: Vec<String>,
+    /// `localizedstrings/<file>.str`
+    pub strings: Vec<String>,
+    /// `levelshots/<map>.jpg` and friends
+    pub levelshots: Vec<String>,
+    /// Every file, lowercased, for finding overrides
+    pub names: Vec<String>,
+}
+
+impl Pk3Content {
+    fn from_entries(path: &Path, folder: &str, entries: &[Pk3Entry]) -> Pk3Content
+    {
+        let mut content = Pk3Content {
+            path: path.to_path_buf(),
+            folder: folder.to_string(),
+            files: entries.len(),
+            size: entries.iter().map(|e| e.size).sum(),
+            ..Default::default()
+        };
+        for entry in entries {
+            let name = entry.name.to_lowercase();
+            if name.ends_with('/') { continue; }
+            content.names.push(name.clone());
+            let stem = name.rsplit('/').next().unwrap_or(&name).rsplit_once('.').map_or(name.as_str(), |(s, _)| s).to_string();
+            if let Some(map) = name.strip_prefix("maps/mp/").and_then(|n| n.strip_suffix(".bsp")) {
+                if !map.contains('/') {
+                    content.maps.push(stem);
+                }
+            }
+            else if let Some(gametype) = name.strip_prefix("maps/mp/gametypes/").and_then(|n| n.strip_suffix(".gsc")) {
+                // `_callbacksetup.gsc` and friends are helpers, not gametypes
+                if !gametype.starts_with('_') && !gametype.contains('/') {
+                    content.gametypes.push(stem);
+                }
+            }
+            else if name.ends_with(".arena") {
+                content.arenas.push(stem);
+            }
+            else if name.starts_with("localizedstrings/") && name.ends_with(".str") {
+                content.strings.push(stem);
+            }
+            else if name.starts_with("levelshots/") && [".jpg", ".tga", ".dds"].iter().any(|e| name.ends_with(e)) {
+                content.levelshots.push(stem);
+            }
+        }
+        content
+    }
+}
+
+/// Files of `overridden` that `winner` replaces, it loads later in the same
+/// folder or from a folder loaded after it.
+#[derive(Debug, Clone)]
+pub(crate) struct Conflict {
+    pub winner: PathBuf,
+    pub overridden: PathBuf,
+    pub files: Vec<String>,
+}
+
+/// Index of every pk3 in an install: the game's own folders first, then mods.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Inventory {
+    pub pk3s: Vec<Pk3Content>,
+    /// Archives that couldn't be read, with why
+    pub broken: Vec<(PathBuf, String)>,
+}
+
+fn folder_name(folder: &Path) -> String
+{
+    folder.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// Folders next to the game's own that hold pk3s. `main` and `uo` are
+/// never mods, a Call of Duty install with United Offensive on top has both.
+pub(crate) fn mod_folders(game: &GameInfo) -> Vec<PathBuf>
+{
+    let Some(dir) = game.path.parent() else { return Vec::new(); };
+    let not_a_mod = |p: &Path| ["main", "uo"].iter().any(|own| folder_name(p).eq_ignore_ascii_case(own));
+    let mut folders: Vec<PathBuf> = fs::read_dir(dir).into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && !not_a_mod(p) && !pk3s_in(p).is_empty())
+        .collect();
+    folders.sort();
+    folders
+}
+
+impl Inventory {
+    pub(crate) fn scan(game: &GameInfo) -> Inventory
+    {
+        let mut inventory = Inventory::default();
+        for folder in game_folders(game).into_iter().chain(mod_folders(game)) {
+            let name = folder_name(&folder);
+            for pk3 in pk3s_in(&folder) {
+                match entries(&pk3) {
+                    Ok(list) => inventory.pk3s.push(Pk3Content::from_entries(&pk3, &name, &list)),
+                    Err(e) => {
+                        eprintln!("CoDLinux: Skipping {}: {}", pk3.display(), e);
+                        inventory.broken.push((pk3, e.to_string()));
+                    }
+                }
+            }
+        }
+        inventory
+    }
+
+    /// Pk3s the game loads without `fs_game`, or with `fs_game` set to `mod_folder`.
+    pub(crate) fn loaded(&self, game: &GameInfo, mod_folder: Option<&str>) -> impl Iterator<Item = &Pk3Content>
+    {
+        let own: Vec<String> = game_folders(game).iter().map(|f| folder_name(f)).collect();
+        let mod_folder = mod_folder.map(str::to_lowercase);
+        self.pk3s.iter().filter(move |p| own.contains(&p.folder) || mod_folder.as_deref() == Some(&p.folder.to_lowercase()))
+    }
+
+    /// Folders holding pk3s, the game's own first.
+    pub(crate) fn folders(&self) -> Vec<&str>
+    {
+        let mut folders: Vec<&str> = Vec::new();
+        for pk3 in &self.pk3s {
+            if !folders.contains(&pk3.folder.as_str()) {
+                folders.push(&pk3.folder);
+            }
+        }
+        folders
+    }
+
+    /// Pk3s the game loads together that provide the same files: `main`,
+    /// then `uo`, then the `fs_game` folder, each in name order, and the
+    /// last one wins. Stock `pak*.pk3` patching each other is expected and
+    /// left out.
+    pub(crate) fn conflicts(&self, game: &GameInfo) -> Vec<Conflict>
+    {
+        let own: Vec<String> = game_folders(game).iter().map(|f| folder_name(f)).collect();
+        let stock = |p: &Pk3Content| own.contains(&p.folder)
+            && p.path.file_name().is_some_and(|n| n.to_string_lossy().to_lowercase().starts_with("pak"));
+        let mut sets: Vec<Option<&str>> = vec![None];
+        sets.extend(self.folders().into_iter().filter(|f| !own.iter().any(|o| o == f)).map(Some));
+
+        let mut conflicts: Vec<Conflict> = Vec::new();
+        for mod_folder in sets {
+            // File name to the pk3 that currently provides it
+            let mut providers: HashMap<&str, &Pk3Content> = HashMap::new();
+            for pk3 in self.loaded(game, mod_folder) {
+                for name in &pk3.names {
+                    let Some(previous) = providers.insert(name, pk3) else { continue; };
+                    if stock(previous) && stock(pk3) { continue; }
+                    // The game's own folders were already compared without a mod
+                    if mod_folder.is_some() && own.contains(&pk3.folder) { continue; }
+                    match conflicts.iter_mut().find(|c| c.winner == pk3.path && c.overridden == previous.path) {
+                        Some(conflict) => conflict.files.push(name.clone()),
+                        None => conflicts.push(Conflict {
+                            winner: pk3.path.clone(),
+                            overridden: previous.path.clone(),
+                            files: vec![name.clone()],
+                        }),
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Every pk3 providing `map`.
+    pub(crate) fn providers(&self, map: &str) -> Vec<&Pk3Content>
+    {
+        self.pk3s.iter().filter(|p| p.maps.iter().any(|m| m.eq_ignore_ascii_case(map))).collect()
+    }
+}
+
+fn sorted_unique<'a>(names: impl Iterator<Item = &'a String>) -> Vec<String>
+{
+    let mut names: Vec<String> = names.cloned().collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Multiplayer maps (`maps/mp/<map>.bsp`) and gametypes
+/// (`maps/mp/gametypes/<type>.gsc`) the game loads, with `mod_folder`'s
+/// when it runs a mod.
+pub(crate) fn maps_and_gametypes(game: &GameInfo, mod_folder: Option<&str>) -> (Vec<String>, Vec<String>)
+{
+    let inventory = Inventory::scan(game);
+    let maps = sorted_unique(inventory.loaded(game, mod_folder).flat_map(|p| &p.maps));
+    let gametypes = sorted_unique(inventory.loaded(game, mod_folder).flat_map(|p| &p.gametypes));
+    (maps, gametypes)
+}
+
+/// Writes a zip of empty, stored files, enough for `entries` and `unzip`.
+#[cfg(test)]
+pub(crate) fn write_test_pk3(path: &Path, names: &[&str])
+{
+    let mut data = Vec::new();
+    let mut directory = Vec::new();
+    for name in names {
+        let offset = data.len() as u32;
+        data.extend_from_slice(b"PK\x03\x04\x0a\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data.extend_from_slice(name.as_bytes());
+
+        directory.extend_from_slice(b"PK\x01\x02\x14\x00\x0a\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let count = (names.len() as u16).to_le_bytes();
+    let mut end = b"PK\x05\x06\x00\x00\x00\x00".to_vec();
+    end.extend_from_slice(&count);
+    end.extend_from_slice(&count);
+    end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    end.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    end.extend_from_slice(&[0; 2]);
+
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, [data, directory, end].concat()).unwrap();
+}
+
+/// A game called `name` with its executable in `dir`.
+#[cfg(test)]
+pub(crate) fn test_game(dir: &Path, name: &str) -> GameInfo
+{
+    GameInfo {
+        name: name.to_string(),
+        version: String::new(),
+        path: dir.join("CoDMP.exe"),
+        wineprefix: String::new(),
+        envars: String::new(),
+        args: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-pk3-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn read_central_directory()
+    {
+        let dir = test_dir("entries");
+        let path = dir.join("main").join("zz_maps.pk3");
+        write_test_pk3(&path, &["maps/mp/mp_test.bsp", "maps/mp/gametypes/ctf.gsc", "maps/mp/gametypes/_callbacksetup.gsc", "Levelshots/MP_Test.jpg", "sound/"]);
+        let list = entries(&path).unwrap();
+        assert_eq!(list.len(), 5);
+        assert_eq!(list[3].name, "Levelshots/MP_Test.jpg");
+
+        let content = Pk3Content::from_entries(&path, "main", &list);
+        assert_eq!(content.maps, ["mp_test"]);
+        assert_eq!(content.gametypes, ["ctf"]);
+        assert_eq!(content.levelshots, ["mp_test"]);
+        assert_eq!(content.names.len(), 4);
+
+        fs::write(dir.join("broken.pk3"), b"not a zip").unwrap();
+        assert_eq!(entries(&dir.join("broken.pk3")).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn own_folders_are_not_mods()
+    {
+        let dir = test_dir("mods");
+        write_test_pk3(&dir.join("main").join("pak0.pk3"), &["maps/mp/mp_harbor.bsp"]);
+        write_test_pk3(&dir.join("uo").join("pakuo00.pk3"), &["maps/mp/mp_arnhem.bsp"]);
+        write_test_pk3(&dir.join("awe").join("awe.pk3"), &["maps/mp/gametypes/ctf.gsc"]);
+        fs::create_dir_all(dir.join("empty")).unwrap();
+
+        // Call of Duty next to United Offensive doesn't take `uo` for a mod
+        let cod = test_game(&dir, "Call of Duty");
+        assert_eq!(game_folders(&cod), [dir.join("main")]);
+        assert_eq!(mod_folders(&cod), [dir.join("awe")]);
+        let inventory = Inventory::scan(&cod);
+        assert_eq!(inventory.folders(), ["main", "awe"]);
+        let maps: Vec<&String> = inventory.loaded(&cod, None).flat_map(|p| &p.maps).collect();
+        assert_eq!(maps, ["mp_harbor"]);
+
+        let uo = test_game(&dir, "United Offensive");
+        assert_eq!(game_folders(&uo), [dir.join("main"), dir.join("uo")]);
+        assert_eq!(mod_folders(&uo), [dir.join("awe")]);
+        let (maps, gametypes) = maps_and_gametypes(&uo, Some("AWE"));
+        assert_eq!(maps, ["mp_arnhem", "mp_harbor"]);
+        assert_eq!(gametypes, ["ctf"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn conflicts_of_folders_loaded_together()
+    {
+        let dir = test_dir("conflicts");
+        write_test_pk3(&dir.join("main").join("pak0.pk3"), &["ui/menu.menu", "maps/mp/mp_harbor.bsp"]);
+        write_test_pk3(&dir.join("main").join("pak1.pk3"), &["ui/menu.menu"]);
+        write_test_pk3(&dir.join("main").join("zz_hud.pk3"), &["ui/hud.menu"]);
+        write_test_pk3(&dir.join("uo").join("pakuo00.pk3"), &["ui/menu.menu"]);
+        write_test_pk3(&dir.join("awe").join("awe.pk3"), &["ui/hud.menu", "ui/menu.menu", "awe.cfg"]);
+        write_test_pk3(&dir.join("awe").join("awe_fix.pk3"), &["awe.cfg"]);
+        write_test_pk3(&dir.join("other").join("other.pk3"), &["awe.cfg"]);
+        let file = |folder: &str, name: &str| dir.join(folder).join(name);
+
+        let uo = test_game(&dir, "United Offensive");
+        let conflicts = Inventory::scan(&uo).conflicts(&uo);
+        let files = |winner: PathBuf, overridden: PathBuf| conflicts.iter()
+            .find(|c| c.winner == winner && c.overridden == overridden)
+            .map(|c| c.files.clone());
+        // Stock paks patching each other, across main and uo too, are fine
+        assert_eq!(files(file("main", "pak1.pk3"), file("main", "pak0.pk3")), None);
+        assert_eq!(files(file("uo", "pakuo00.pk3"), file("main", "pak1.pk3")), None);
+        // A mod overrides the last provider in the game's own folders
+        assert_eq!(files(file("awe", "awe.pk3"), file("main", "zz_hud.pk3")).unwrap(), ["ui/hud.menu"]);
+        assert_eq!(files(file("awe", "awe.pk3"), file("uo", "pakuo00.pk3")).unwrap(), ["ui/menu.menu"]);
+        assert_eq!(files(file("awe", "awe_fix.pk3"), file("awe", "awe.pk3")).unwrap(), ["awe.cfg"]);
+        // Mods are never loaded together, so awe and other don't conflict
+        assert_eq!(conflicts.len(), 3);
+
+        // Call of Duty doesn't load `uo`, awe overrides its own pak instead
+        let cod = test_game(&dir, "Call of Duty");
+        let conflicts = Inventory::scan(&cod).conflicts(&cod);
+        assert!(conflicts.iter().any(|c| c.winner == file("awe", "awe.pk3") && c.overridden == file("main", "pak1.pk3")));
+        assert_eq!(conflicts.len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
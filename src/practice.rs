@@ -0,0 +1,165 @@
+use relm4::{gtk, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::glib;
+
+use std::{net::{SocketAddr, UdpSocket}, process::Child, thread, time::{Duration, Instant}};
+
+use crate::{hosting::{self, HostedServer}, launch::{self, LaunchPlan}, pk3, query, util, GameInfo};
+
+/// How long the server gets to load the map and answer `getinfo`.
+static STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A local dedicated server, stopped when dropped.
+#[derive(Debug)]
+pub(crate) struct PracticeServer {
+    pub port: u16,
+    /// Only None once it's been stopped
+    child: Option<Child>,
+}
+
+impl PracticeServer {
+    /// Stops the server, the handle finishes once it's gone.
+    pub(crate) fn stop(mut self) -> Option<thread::JoinHandle<()>>
+    {
+        let child = self.child.take()?;
+        println!("CoDLinux: Stopping practice server on port {}", self.port);
+        Some(hosting::stop(child))
+    }
+}
+
+impl Drop for PracticeServer {
+    fn drop(&mut self)
+    {
+        if let Some(child) = self.child.take() {
+            println!("CoDLinux: Stopping practice server on port {}", self.port);
+            hosting::stop(child);
+        }
+    }
+}
+
+/// First port from 28970 up that nothing listens on, clear of the hosted servers.
+fn free_port() -> u16
+{
+    let hosted = hosting::load_hosted();
+    (28970..).find(|p| {
+        !hosted.iter().any(|s| s.port == *p) && UdpSocket::bind(("0.0.0.0", *p)).is_ok()
+    }).unwrap()
+}
+
+/// LAN only (`dedicated 1`) so it never shows up on the master server.
+fn plan(game: &GameInfo, port: u16, map: &str, gametype: &str) -> Option<LaunchPlan>
+{
+    let mut plan = launch::resolve(game);
+    plan.executable = hosting::dedicated_executable(game)?;
+    plan.args = format!(
+        "+set dedicated 1 +set net_port {port} +set sv_hostname Practice +set g_gametype {} +map {}",
+        launch::shell_quote(gametype),
+        launch::shell_quote(map),
+    );
+    Some(plan)
+}
+
+/// Starts a server on `map` and waits until it answers `getinfo`. Blocks,
+/// call it off the main loop.
+pub(crate) fn start(game: &GameInfo, map: &str, gametype: &str) -> Result<PracticeServer, String>
+{
+    let port = free_port();
+    let plan = plan(game, port, map, gametype)
+        .ok_or_else(|| format!("No dedicated executable next to {}", game.path.display()))?;
+    let server = HostedServer {
+        name: String::from("practice"),
+        game: game.name.clone(),
+        port,
+        config: String::new(),
+        headless: false,
+        args: String::new(),
+    };
+    let child = hosting::start(&server, &plan).map_err(|e| format!("Failed to start the practice server: {e}"))?;
+    let mut practice = PracticeServer { port, child: Some(child) };
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while Instant::now() < deadline {
+        if let Some(Ok(Some(status))) = practice.child.as_mut().map(|c| c.try_wait()) {
+            return Err(format!("The practice server exited ({status}), see {}", hosting::log_file(&server).display()));
+        }
+        if let Ok(Some(info)) = query::query_info(addr, Duration::from_secs(1)) {
+            println!("CoDLinux: Practice server up on port {port}, map {}", info.map);
+            return Ok(practice);
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    // Dropping `practice` stops it
+    Err(format!("The practice server didn't answer within {} seconds", STARTUP_TIMEOUT.as_secs()))
+}
+
+/// Map and gametype picker for `game`, `on_start` gets the choice.
+pub(crate) fn show_window(app: &gtk::Application, game: &GameInfo, on_start: impl Fn(String, String) + 'static)
+{
+    let (maps, gametypes) = pk3::maps_and_gametypes(game, None);
+    if maps.is_empty() {
+        util::notify(&format!("No multiplayer maps found for {}", game.name), 5000, false).unwrap();
+        return;
+    }
+    let gametypes = if gametypes.is_empty() {
+        ["dm", "tdm", "sd", "re", "bel", "hq"].iter().map(|g| g.to_string()).collect()
+    } else { gametypes };
+
+    let map_names: Vec<&str> = maps.iter().map(String::as_str).collect();
+    let gametype_names: Vec<&str> = gametypes.iter().map(String::as_str).collect();
+    let last_map = util::load_setting("practice_map").unwrap_or_default();
+    let last_gametype = util::load_setting("practice_gametype").unwrap_or_default();
+
+    relm4::view! {
+        window = gtk::Window {
+            set_title: Some(&format!("Practice: {}", game.name)),
+            set_modal: true,
+            set_resizable: false,
+
+            gtk::Grid {
+                set_row_spacing: 6,
+                set_column_spacing: 12,
+                set_margin_all: 12,
+
+                attach[0, 0, 1, 1] = &gtk::Label {
+                    set_markup: "<b>Map</b>",
+                    set_halign: gtk::Align::Start,
+                },
+                #[name = "map_list"]
+                attach[1, 0, 1, 1] = &gtk::DropDown::from_strings(&map_names) {
+                    set_hexpand: true,
+                    set_enable_search: true,
+                    set_selected: maps.iter().position(|m| *m == last_map).unwrap_or(0) as u32,
+                },
+                attach[0, 1, 1, 1] = &gtk::Label {
+                    set_markup: "<b>Gametype</b>",
+                    set_halign: gtk::Align::Start,
+                },
+                #[name = "gametype_list"]
+                attach[1, 1, 1, 1] = &gtk::DropDown::from_strings(&gametype_names) {
+                    set_selected: gametypes.iter().position(|g| *g == last_gametype).unwrap_or(0) as u32,
+                },
+                #[name = "start_button"]
+                attach[1, 2, 1, 1] = &gtk::Button {
+                    set_label: "Start",
+                    set_halign: gtk::Align::End,
+                },
+            }
+        }
+    }
+
+    start_button.connect_clicked(gtk::glib::clone!(
+        #[weak] window,
+        move |_| {
+            let map = maps[map_list.selected() as usize].clone();
+            let gametype = gametypes[gametype_list.selected() as usize].clone();
+            util::save_setting("practice_map", &map).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save setting: {e}"));
+            util::save_setting("practice_gametype", &gametype).unwrap_or_else(|e| eprintln!("CoDLinux: Failed to save setting: {e}"));
+            window.close();
+            on_start(map, gametype);
+        }
+    ));
+
+    app.add_window(&window);
+    window.set_visible(true);
+}
@@ -0,0 +1,432 @@
+use std::{io, collections::BTreeMap, net::{SocketAddr, ToSocketAddrs, UdpSocket}, time::{Duration, Instant}};
+
+/// Every connectionless (out of band) Quake 3 packet starts with this.
+pub(crate) static OOB: &[u8] = b"\xff\xff\xff\xff";
+
+pub(crate) static DEFAULT_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Builds an out of band packet: `\xff\xff\xff\xff<command>`.
+pub(crate) fn oob_packet(command: &str) -> Vec<u8>
+{
+    let mut packet = OOB.to_vec();
+    packet.extend_from_slice(command.as_bytes());
+    packet
+}
+
+/// Splits an out of band packet into its command word and the rest.
+/// `infoResponse\n\a\b` gives `("infoResponse", "\a\b")`.
+pub(crate) fn parse_oob(packet: &[u8]) -> Option<(String, &[u8])>
+{
+    let body = packet.strip_prefix(OOB)?;
+    let end = body.iter()
+        .position(|b| *b == b'\n' || *b == b' ' || *b == b'\\' || *b == 0)
+        .unwrap_or(body.len());
+    let command = String::from_utf8_lossy(&body[..end]).to_string();
+    let rest = match body.get(end) {
+        Some(b'\n') | Some(b' ') => &body[end + 1..],
+        _ => &body[end..],
+    };
+    Some((command, rest))
+}
+
+/// Parses `\key\value\key\value` into a map.
+pub(crate) fn parse_info_string(info: &str) -> BTreeMap<String, String>
+{
+    let info = info.trim_end_matches(['\n', '\0']);
+    let mut parts = info.strip_prefix('\\').unwrap_or(info).split('\\');
+    let mut map = BTreeMap::new();
+    while let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+        if !key.is_empty() {
+            map.insert(key.to_string(), value.to_string());
+        }
+    }
+    map
+}
+
+/// Resolves `host[:port]`, defaulting to the game port.
+pub(crate) fn resolve(address: &str) -> io::Result<SocketAddr>
+{
+    let address = address.trim();
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let with_port = match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => address.to_string(),
+        // Bare or bracketed IPv6 without a port
+        _ if address.contains(':') => format!("[{}]:{}", address.trim_matches(['[', ']']), crate::uri::DEFAULT_PORT),
+        _ => format!("{}:{}", address, crate::uri::DEFAULT_PORT),
+    };
+    with_port.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {address}")))
+}
+
+/// What `getinfo` tells about a server, plus the measured ping.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerInfo {
+    pub addr: SocketAddr,
+    pub hostname: String,
+    pub map: String,
+    pub gametype: String,
+    pub clients: u32,
+    pub max_clients: u32,
+    pub ping: u32,
+    pub password: bool,
+    pub fs_game: String,
+    pub info: BTreeMap<String, String>,
+}
+
+impl ServerInfo {
+    pub(crate) fn from_info(addr: SocketAddr, ping: u32, info: BTreeMap<String, String>) -> ServerInfo
+    {
+        let get = |keys: &[&str]| keys.iter().find_map(|k| info.get(*k)).cloned().unwrap_or_default();
+        ServerInfo {
+            addr,
+            hostname: get(&["hostname", "sv_hostname"]),
+            map: get(&["mapname"]),
+            gametype: get(&["gametype", "g_gametype"]),
+            clients: get(&["clients"]).parse().unwrap_or(0),
+            max_clients: get(&["sv_maxclients"]).parse().unwrap_or(0),
+            ping,
+            password: matches!(get(&["pswrd", "g_needpass", "needpass"]).as_str(), "1"),
+            fs_game: get(&["fs_game", "game"]),
+            info,
+        }
+    }
+
+    pub(crate) fn is_full(&self) -> bool
+    {
+        self.max_clients > 0 && self.clients >= self.max_clients
+    }
+}
+
+/// Sends `getinfo` to every address and collects the answers that come
+/// back within `timeout`. Servers that don't answer are left out.
+pub(crate) fn query_infos(addrs: &[SocketAddr], timeout: Duration) -> io::Result<Vec<ServerInfo>>
+{
+    let replies = query_all(addrs, "getinfo codlinux", "infoResponse", timeout)?;
+    Ok(replies.into_iter()
+        .map(|(addr, ping, body)| ServerInfo::from_info(addr, ping, parse_info_string(&body)))
+        .collect())
+}
+
+pub(crate) fn query_info(addr: SocketAddr, timeout: Duration) -> io::Result<Option<ServerInfo>>
+{
+    Ok(query_infos(&[addr], timeout)?.into_iter().next())
+}
+
+/// Sends `command` to every address and waits for `response` packets.
+/// Returns the sender, round trip time in ms and the text after the command word.
+pub(crate) fn query_all(addrs: &[SocketAddr], command: &str, response: &str, timeout: Duration) -> io::Result<Vec<(SocketAddr, u32, String)>>
+{
+    let socket = bind_for(addrs)?;
+    let packet = oob_packet(command);
+    let mut sent_at: BTreeMap<SocketAddr, Instant> = BTreeMap::new();
+    for addr in addrs {
+        if let Err(e) = socket.send_to(&packet, addr) {
+            eprintln!("CoDLinux: Failed to query {}: {}", addr, e);
+            continue;
+        }
+        sent_at.insert(*addr, Instant::now());
+    }
+
+    let mut replies = Vec::new();
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 65536];
+    while !sent_at.is_empty() {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else { break; };
+        socket.set_read_timeout(Some(left.max(Duration::from_millis(1))))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        // A dual stack socket sees IPv4 servers as `::ffff:a.b.c.d`
+        let from = SocketAddr::new(from.ip().to_canonical(), from.port());
+        let Some(start) = sent_at.remove(&from) else { continue; };
+        match parse_oob(&buffer[..len]) {
+            Some((cmd, body)) if cmd == response => {
+                let ping = start.elapsed().as_millis() as u32;
+                replies.push((from, ping, String::from_utf8_lossy(body).to_string()));
+            }
+            _ => {
+                // Not what we asked for, keep waiting for this server
+                sent_at.insert(from, start);
+            }
+        }
+    }
+    Ok(replies)
+}
+
+/// Binds an unconnected socket of the right family for `addrs`.
+pub(crate) fn bind_for(addrs: &[SocketAddr]) -> io::Result<UdpSocket>
+{
+    if addrs.iter().any(|a| a.is_ipv6()) {
+        UdpSocket::bind("[::]:0")
+    }
+    else {
+        UdpSocket::bind("0.0.0.0:0")
+    }
+}
+
+/// Removes Quake colour codes (`^1`, `^7`...) from a name.
+pub(crate) fn strip_colours(s: &str) -> String
+{
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '^' && chars.peek().is_some_and(|n| *n != '^') {
+            chars.next();
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// One line of the player list in `statusResponse`.
+#[derive(Debug, Clone)]
+pub(crate) struct Player {
+    pub score: i32,
+    pub ping: u32,
+    pub name: String,
+}
+
+/// What `getstatus` tells about a server: every serverinfo cvar and the players.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerStatus {
+    pub addr: SocketAddr,
+    pub ping: u32,
+    pub cvars: BTreeMap<String, String>,
+    pub players: Vec<Player>,
+}
+
+impl ServerStatus {
+    pub(crate) fn cvar(&self, key: &str) -> &str
+    {
+        self.cvars.get(key).map(|v| v.as_str()).unwrap_or_default()
+    }
+}
+
+/// Parses the body of `statusResponse`: the cvar block on the first line,
+/// then one `score ping "name"` line per player.
+pub(crate) fn parse_status(body: &str) -> (BTreeMap<String, String>, Vec<Player>)
+{
+    let mut lines = body.lines();
+    let cvars = lines.next().map(parse_info_string).unwrap_or_default();
+    let players = lines.filter_map(parse_player).collect();
+    (cvars, players)
+}
+
+pub(crate) fn parse_player(line: &str) -> Option<Player>
+{
+    let mut parts = line.trim().splitn(3, ' ');
+    let score = parts.next()?.parse().ok()?;
+    let ping = parts.next()?.parse().ok()?;
+    let name = parts.next()?.trim();
+    let name = name.strip_prefix('"').and_then(|n| n.strip_suffix('"')).unwrap_or(name);
+    Some(Player { score, ping, name: name.to_string() })
+}
+
+pub(crate) fn query_statuses(addrs: &[SocketAddr], timeout: Duration) -> io::Result<Vec<ServerStatus>>
+{
+    let replies = query_all(addrs, "getstatus", "statusResponse", timeout)?;
+    Ok(replies.into_iter()
+        .map(|(addr, ping, body)| {
+            let (cvars, players) = parse_status(&body);
+            ServerStatus { addr, ping, cvars, players }
+        })
+        .collect())
+}
+
+pub(crate) fn query_status(addr: SocketAddr, timeout: Duration) -> io::Result<Option<ServerStatus>>
+{
+    Ok(query_statuses(&[addr], timeout)?.into_iter().next())
+}
+
+/// Quake colour codes `^0`..`^7`, higher digits wrap around like the game does.
+/// `^7` (white) is left to the theme's text colour so it stays readable.
+static COLOURS: [Option<&str>; 8] = [
+    Some("#000000"), Some("#ff0000"), Some("#00c000"), Some("#e0c000"),
+    Some("#0000ff"), Some("#00c0c0"), Some("#ff00ff"), None,
+];
+
+/// Turns a name with `^n` colour codes into Pango markup.
+pub(crate) fn colour_markup(s: &str) -> String
+{
+    let mut out = String::new();
+    let mut open = false;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '^' && chars.peek().is_some_and(|n| *n != '^') {
+            let code = chars.next().unwrap();
+            if open {
+                out.push_str("</span>");
+                open = false;
+            }
+            if let Some(colour) = COLOURS[(code as u32).wrapping_sub('0' as u32) as usize & 7] {
+                out.push_str(&format!("<span foreground=\"{colour}\">"));
+                open = true;
+            }
+            continue;
+        }
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    if open {
+        out.push_str("</span>");
+    }
+    out
+}
+
+/// A UDP server on 127.0.0.1 answering each packet with what `reply` makes
+/// of it. It stops after 5 seconds without packets.
+#[cfg(test)]
+pub(crate) fn fake_server(reply: impl Fn(&[u8]) -> Vec<Vec<u8>> + Send + 'static) -> SocketAddr
+{
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let addr = socket.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 65536];
+        while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+            for packet in reply(&buffer[..len]) {
+                socket.send_to(&packet, from).unwrap();
+            }
+        }
+    });
+    addr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers getinfo and getstatus like a CoD server on mp_harbor.
+    fn fake_game_server() -> SocketAddr
+    {
+        fake_server(|packet| match parse_oob(packet) {
+            Some((cmd, _)) if cmd == "getinfo" => vec![oob_packet(
+                "infoResponse\n\\hostname\\^1Red ^7Server\\mapname\\mp_harbor\\gametype\\sd\\clients\\3\\sv_maxclients\\16\\fs_game\\awe",
+            )],
+            Some((cmd, _)) if cmd == "getstatus" => vec![oob_packet(
+                "statusResponse\n\\sv_hostname\\Red\\mapname\\mp_harbor\n5 48 \"^2Some One\"\n0 999 \"bot\"\n",
+            )],
+            _ => Vec::new(),
+        })
+    }
+
+    /// Takes packets and never answers.
+    fn silent_server() -> SocketAddr
+    {
+        fake_server(|_| Vec::new())
+    }
+
+    #[test]
+    fn oob_packets()
+    {
+        assert_eq!(oob_packet("getinfo x"), b"\xff\xff\xff\xffgetinfo x");
+        let (cmd, rest) = parse_oob(b"\xff\xff\xff\xffinfoResponse\n\\a\\b").unwrap();
+        assert_eq!(cmd, "infoResponse");
+        assert_eq!(rest, b"\\a\\b");
+        let (cmd, rest) = parse_oob(b"\xff\xff\xff\xffgetserversResponse\\EOT").unwrap();
+        assert_eq!(cmd, "getserversResponse");
+        assert_eq!(rest, b"\\EOT");
+        assert!(parse_oob(b"getinfo").is_none());
+    }
+
+    #[test]
+    fn info_strings()
+    {
+        let info = parse_info_string("\\a\\1\\\\skipped\\b\\\\dangling\n");
+        assert_eq!(info.len(), 2);
+        assert_eq!(info["a"], "1");
+        assert_eq!(info["b"], "");
+        assert!(parse_info_string("").is_empty());
+    }
+
+    #[test]
+    fn getinfo_from_a_local_server()
+    {
+        let addr = fake_game_server();
+        let info = query_info(addr, Duration::from_secs(2)).unwrap().unwrap();
+        assert_eq!(info.addr, addr);
+        assert_eq!(info.hostname, "^1Red ^7Server");
+        assert_eq!(info.map, "mp_harbor");
+        assert_eq!(info.gametype, "sd");
+        assert_eq!((info.clients, info.max_clients), (3, 16));
+        assert_eq!(info.fs_game, "awe");
+        assert!(!info.password);
+        assert!(!info.is_full());
+    }
+
+    #[test]
+    fn getstatus_from_a_local_server()
+    {
+        let addr = fake_game_server();
+        let status = query_status(addr, Duration::from_secs(2)).unwrap().unwrap();
+        assert_eq!(status.cvar("mapname"), "mp_harbor");
+        assert_eq!(status.cvar("missing"), "");
+        assert_eq!(status.players.len(), 2);
+        assert_eq!(status.players[0].name, "^2Some One");
+        assert_eq!((status.players[0].score, status.players[0].ping), (5, 48));
+    }
+
+    #[test]
+    fn silent_servers_time_out()
+    {
+        let addr = silent_server();
+        let started = Instant::now();
+        assert!(query_info(addr, Duration::from_millis(300)).unwrap().is_none());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // The answering one still comes back
+        let infos = query_infos(&[addr, fake_game_server()], Duration::from_millis(500)).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].map, "mp_harbor");
+    }
+
+    #[test]
+    fn ipv4_replies_on_a_dual_stack_socket()
+    {
+        // Not every sandbox has IPv6
+        let Ok(v6) = UdpSocket::bind("[::1]:0") else { return; };
+        let v6 = v6.local_addr().unwrap();
+        let infos = query_infos(&[v6, fake_game_server()], Duration::from_millis(500)).unwrap();
+        assert_eq!(infos.len(), 1);
+        assert!(infos[0].addr.is_ipv4());
+    }
+
+    #[test]
+    fn status_bodies()
+    {
+        let (cvars, players) = parse_status("\\sv_hostname\\Red\\g_gametype\\tdm\n12 50 \"^1Red ^7Guy\"\n-3 999 \"quoted \"name\"\"\nnot a player\n0 0 unquoted name\n");
+        assert_eq!(cvars["g_gametype"], "tdm");
+        assert_eq!(players.len(), 3);
+        assert_eq!((players[0].score, players[0].ping, players[0].name.as_str()), (12, 50, "^1Red ^7Guy"));
+        assert_eq!((players[1].score, players[1].name.as_str()), (-3, "quoted \"name\""));
+        assert_eq!(players[2].name, "unquoted name");
+
+        let (cvars, players) = parse_status("");
+        assert!(cvars.is_empty() && players.is_empty());
+        assert!(parse_player("5 48").is_none());
+        assert!(parse_player("x 48 \"a\"").is_none());
+    }
+
+    #[test]
+    fn colours()
+    {
+        assert_eq!(strip_colours("^1Red^7Guy^^"), "RedGuy^^");
+        assert_eq!(colour_markup("plain"), "plain");
+        assert_eq!(colour_markup("^1Red^7 <Guy>"), "<span foreground=\"#ff0000\">Red</span> &lt;Guy&gt;");
+        // ^9 wraps to ^1, a trailing ^ stays
+        assert_eq!(colour_markup("^9a^"), "<span foreground=\"#ff0000\">a^</span>");
+        assert_eq!(colour_markup("^2&'"), "<span foreground=\"#00c000\">&amp;&#39;</span>");
+    }
+}
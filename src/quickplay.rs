@@ -0,0 +1,208 @@
+use std::{collections::HashSet, net::SocketAddr};
+
+use crate::{master, pk3::Inventory, query::{self, ServerInfo}, servers, util, GameInfo};
+
+/// Multiplayer maps that ship with Call of Duty 1.5.
+static COD_MAPS: [&str; 16] = [
+    "mp_bocage", "mp_brecourt", "mp_carentan", "mp_chateau", "mp_dawnville", "mp_depot",
+    "mp_harbor", "mp_hurtgen", "mp_neuville", "mp_pavlov", "mp_powcamp", "mp_railyard",
+    "mp_rocket", "mp_ship", "mp_stalingrad", "mp_tigertown",
+];
+
+/// Multiplayer maps United Offensive adds. It loads `main` too, so the
+/// Call of Duty maps are there as well.
+static UO_MAPS: [&str; 18] = [
+    "mp_arnhem", "mp_berlin", "mp_cassino", "mp_foy", "mp_italy", "mp_kharkov",
+    "mp_kursk", "mp_peaks", "mp_ponyri", "mp_rhinevalley", "mp_sicily",
+    "mp_uo_carentan", "mp_uo_dawnville", "mp_uo_depot", "mp_uo_harbor", "mp_uo_hurtgen",
+    "mp_uo_powcamp", "mp_uo_stanjel",
+];
+
+/// What Quick Play is allowed to pick, from `quickplay_*` in codlinux.cfg.
+#[derive(Debug, Clone)]
+pub(crate) struct Filters {
+    /// Only this gametype (`dm`, `tdm`, `sd`...), any if empty
+    pub gametype: String,
+    /// Only servers running this fs_game, any if empty
+    pub fs_game: String,
+    pub allow_password: bool,
+    pub max_ping: u32,
+}
+
+impl Filters {
+    pub(crate) fn load() -> Filters
+    {
+        let setting = |key: &str| util::load_setting(key).unwrap_or_default().trim().to_string();
+        Filters {
+            gametype: setting("quickplay_gametype"),
+            fs_game: setting("quickplay_fs_game"),
+            allow_password: setting("quickplay_allow_password") == "1",
+            max_ping: setting("quickplay_max_ping").parse().unwrap_or(150),
+        }
+    }
+
+    pub(crate) fn accepts(&self, server: &ServerInfo) -> bool
+    {
+        if server.is_full() || server.ping > self.max_ping {
+            return false;
+        }
+        if server.password && !self.allow_password {
+            return false;
+        }
+        if !self.gametype.is_empty() && !server.gametype.eq_ignore_ascii_case(&self.gametype) {
+            return false;
+        }
+        if !self.fs_game.is_empty() && !server.fs_game.eq_ignore_ascii_case(&self.fs_game) {
+            return false;
+        }
+        true
+    }
+}
+
+/// True if `map` ships with `game`, for installs whose pk3s can't be read.
+pub(crate) fn has_stock_map(game: &GameInfo, map: &str) -> bool
+{
+    let map = map.to_lowercase();
+    let uo = master::family_for_game(&game.name).is_some_and(|f| f.scheme == "coduo");
+    COD_MAPS.contains(&map.as_str()) || (uo && UO_MAPS.contains(&map.as_str()))
+}
+
+/// Higher is better. Populated servers with a few free slots and a low
+/// ping win; a map we'd have to download costs a lot.
+pub(crate) fn score(server: &ServerInfo, has_map: bool) -> i64
+{
+    let free = server.max_clients.saturating_sub(server.clients) as i64;
+    let mut score = server.clients as i64 * 10;
+    score += free.min(4) * 5;
+    score -= server.ping as i64 / 5;
+    if !has_map {
+        score -= 100;
+    }
+    score
+}
+
+pub(crate) fn pick_best(servers: Vec<ServerInfo>, filters: &Filters, has_map: impl Fn(&str) -> bool) -> Option<ServerInfo>
+{
+    servers.into_iter()
+        .filter(|s| filters.accepts(s))
+        .max_by_key(|s| score(s, has_map(&s.map)))
+}
+
+/// Every server we know for `game`: the master list plus favourites and history.
+pub(crate) fn known_servers(game: &GameInfo) -> Vec<SocketAddr>
+{
+    let Some(family) = master::family_for_game(&game.name) else {
+        return Vec::new();
+    };
+    let mut addrs = master::query_masters(&family.masters(), family.protocol, query::DEFAULT_TIMEOUT);
+
+    let ours = |scheme: &str| master::family_for_scheme(scheme).is_some_and(|f| f.scheme == family.scheme);
+
+    let favourites = servers::load_favourites().into_iter().map(|f| f.server);
+    let history = servers::load_history().into_iter().map(|h| h.server);
+    for server in favourites.chain(history).filter(|s| ours(&s.scheme)) {
+        if let Ok(addr) = query::resolve(&server.address()) && !addrs.contains(&addr) {
+            addrs.push(addr);
+        }
+    }
+    addrs
+}
+
+/// Maps the game loads without a mod, from its pk3s. Falls back to the
+/// stock list when none could be read.
+pub(crate) fn installed_maps(game: &GameInfo, inventory: &Inventory) -> impl Fn(&str) -> bool + use<>
+{
+    let maps: HashSet<String> = inventory.loaded(game, None).flat_map(|p| p.maps.iter().cloned()).collect();
+    let game = game.clone();
+    move |map| {
+        if maps.is_empty() {
+            has_stock_map(&game, map)
+        }
+        else {
+            maps.contains(&map.to_lowercase())
+        }
+    }
+}
+
+/// Queries everything known for `game` and picks the best server. Blocking.
+pub(crate) fn find_server(game: &GameInfo) -> Result<ServerInfo, String>
+{
+    let filters = Filters::load();
+    let addrs = known_servers(game);
+    if addrs.is_empty() {
+        return Err(format!("No servers known for {}", game.name));
+    }
+    let has_map = installed_maps(game, &Inventory::scan(game));
+    let infos = query::query_infos(&addrs, query::DEFAULT_TIMEOUT).map_err(|e| e.to_string())?;
+    let answered = infos.len();
+    pick_best(infos, &filters, has_map)
+        .ok_or_else(|| format!("None of the {answered} servers that answered match the Quick Play filters"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pk3;
+
+    fn server(port: u16, map: &str, clients: u32, ping: u32) -> ServerInfo
+    {
+        let mut info = query::parse_info_string(&format!("\\mapname\\{map}\\gametype\\tdm\\clients\\{clients}\\sv_maxclients\\16"));
+        info.insert(String::from("hostname"), format!("server {port}"));
+        ServerInfo::from_info(SocketAddr::from(([127, 0, 0, 1], port)), ping, info)
+    }
+
+    fn filters() -> Filters
+    {
+        Filters { gametype: String::new(), fs_game: String::new(), allow_password: false, max_ping: 150 }
+    }
+
+    #[test]
+    fn stock_maps_per_game()
+    {
+        let dir = std::env::temp_dir();
+        let cod = pk3::test_game(&dir, "Call of Duty");
+        let uo = pk3::test_game(&dir, "United Offensive");
+        assert!(has_stock_map(&cod, "MP_Harbor") && has_stock_map(&uo, "mp_harbor"));
+        assert!(!has_stock_map(&cod, "mp_arnhem") && has_stock_map(&uo, "mp_arnhem"));
+        assert!(!has_stock_map(&uo, "mp_custom"));
+    }
+
+    #[test]
+    fn installed_maps_win_over_the_stock_list()
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-quickplay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let game = pk3::test_game(&dir, "Call of Duty");
+
+        // Nothing readable installed: the stock list decides
+        let has_map = installed_maps(&game, &Inventory::scan(&game));
+        assert!(has_map("mp_harbor") && !has_map("mp_custom"));
+
+        pk3::write_test_pk3(&dir.join("main").join("pak0.pk3"), &["maps/mp/mp_harbor.bsp"]);
+        pk3::write_test_pk3(&dir.join("main").join("zz_custom.pk3"), &["maps/mp/mp_custom.bsp"]);
+        pk3::write_test_pk3(&dir.join("awe").join("awe.pk3"), &["maps/mp/mp_modmap.bsp"]);
+        let has_map = installed_maps(&game, &Inventory::scan(&game));
+        assert!(has_map("MP_CUSTOM") && has_map("mp_harbor"));
+        assert!(!has_map("mp_carentan") && !has_map("mp_modmap"));
+
+        // A fuller server on a map we don't have loses to one we can load
+        let servers = vec![server(1, "mp_carentan", 12, 40), server(2, "mp_custom", 8, 40)];
+        assert_eq!(pick_best(servers, &filters(), &has_map).unwrap().map, "mp_custom");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filters_and_scores()
+    {
+        let full = server(1, "mp_harbor", 16, 20);
+        let far = server(2, "mp_harbor", 4, 400);
+        assert!(!filters().accepts(&full) && !filters().accepts(&far));
+        let mut sd_only = filters();
+        sd_only.gametype = String::from("SD");
+        assert!(!sd_only.accepts(&server(3, "mp_harbor", 4, 20)));
+
+        assert!(score(&server(4, "mp_harbor", 10, 50), true) > score(&server(5, "mp_harbor", 2, 50), true));
+        assert!(score(&server(6, "mp_harbor", 2, 50), true) > score(&server(7, "mp_custom", 10, 50), false));
+        assert!(pick_best(vec![full, far], &filters(), |_| true).is_none());
+    }
+}
@@ -0,0 +1,404 @@
+use relm4::{gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::glib;
+
+use std::{io, net::{SocketAddr, UdpSocket}, time::{Duration, Instant}};
+
+use crate::{query, uri::ServerUri};
+
+/// Long answers (`status` on a full server) come in several packets, stop
+/// listening once the server has been quiet this long.
+static QUIET_TIME: Duration = Duration::from_millis(400);
+static RCON_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// `rcon <password> <command>`
+pub(crate) fn rcon_packet(password: &str, command: &str) -> Vec<u8>
+{
+    query::oob_packet(&format!("rcon {password} {command}"))
+}
+
+/// Text of a `print` packet, None for anything else.
+pub(crate) fn parse_print(packet: &[u8]) -> Option<String>
+{
+    let (command, body) = query::parse_oob(packet)?;
+    (command == "print").then(|| String::from_utf8_lossy(body).trim_end_matches('\0').to_string())
+}
+
+/// The server takes everything up to the first space as the password and
+/// doesn't agree across versions on quotes, so neither can be sent.
+pub(crate) fn valid_password(password: &str) -> bool
+{
+    !password.is_empty() && !password.contains(|c: char| c.is_whitespace() || c == '"')
+}
+
+/// Sends one rcon command and collects everything the server prints back.
+/// Works against anything that answers with `print` packets, a local UDP
+/// stand-in included.
+pub(crate) fn send(addr: SocketAddr, password: &str, command: &str) -> io::Result<String>
+{
+    if !valid_password(password) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "rcon passwords can't contain spaces or quotes"));
+    }
+    let socket = query::bind_for(&[addr])?;
+    socket.send_to(&rcon_packet(password, command), addr)?;
+    collect_prints(&socket, addr, RCON_TIMEOUT)
+}
+
+fn collect_prints(socket: &UdpSocket, addr: SocketAddr, timeout: Duration) -> io::Result<String>
+{
+    let deadline = Instant::now() + timeout;
+    let mut output = String::new();
+    let mut answered = false;
+    let mut buffer = [0u8; 16384];
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let wait = if answered { left.min(QUIET_TIME) } else { left };
+        socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(r) => r,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        };
+        if from != addr { continue; }
+        if let Some(text) = parse_print(&buffer[..len]) {
+            output.push_str(&text);
+            answered = true;
+        }
+    }
+    if !answered {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer from the server"));
+    }
+    Ok(output)
+}
+
+/// Console for one favourite server.
+pub(crate) struct RconConsole {
+    /// `ServerEntry::id` of the favourite
+    id: u64,
+    label: String,
+    server: ServerUri,
+    password: String,
+    remember: bool,
+    /// What the favourite currently stores
+    saved: Option<String>,
+    /// Commands sent, oldest first
+    history: Vec<String>,
+    /// Position while browsing `history` with Up/Down
+    history_pos: Option<usize>,
+    busy: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum RconMsg {
+    SetPassword(String),
+    SetRemember(bool),
+    Send(String),
+    /// Puts text in the command entry
+    Prefill(String),
+    HistoryUp,
+    HistoryDown,
+}
+
+#[derive(Debug)]
+pub(crate) enum RconOutput {
+    /// Favourite id and the rcon password to store with it, None to forget it
+    SavePassword(u64, Option<String>),
+}
+
+#[derive(Debug)]
+pub(crate) enum RconCmd {
+    Reply(String, Result<String, String>),
+}
+
+#[relm4::component(pub(crate))]
+impl Component for RconConsole {
+    /// Favourite id, label, server and saved rcon password
+    type Init = (u64, String, ServerUri, Option<String>);
+    type Input = RconMsg;
+    type Output = RconOutput;
+    type CommandOutput = RconCmd;
+
+    view! {
+        gtk::Window {
+            set_title: Some(&format!("RCON: {}", model.label)),
+            set_default_size: (640, 420),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::Label {
+                        set_markup: "<b>Password</b>",
+                    },
+                    gtk::PasswordEntry {
+                        set_text: &model.password,
+                        set_show_peek_icon: true,
+                        set_hexpand: true,
+                        connect_changed[sender] => move |entry| {
+                            sender.input(RconMsg::SetPassword(entry.text().to_string()));
+                        },
+                    },
+                    gtk::CheckButton {
+                        set_label: Some("Remember"),
+                        set_active: model.remember,
+                        connect_toggled[sender] => move |btn| {
+                            sender.input(RconMsg::SetRemember(btn.is_active()));
+                        },
+                    },
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_policy: (gtk::PolicyType::Automatic, gtk::PolicyType::Automatic),
+
+                    #[name = "output"]
+                    gtk::TextView {
+                        set_editable: false,
+                        set_cursor_visible: false,
+                        set_monospace: true,
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::Button {
+                        set_label: "status",
+                        connect_clicked => RconMsg::Send(String::from("status")),
+                    },
+                    gtk::Button {
+                        set_label: "map_rotate",
+                        connect_clicked => RconMsg::Send(String::from("map_rotate")),
+                    },
+                    gtk::Button {
+                        set_label: "kick",
+                        set_tooltip_text: Some("Kick a client number from `status`"),
+                        connect_clicked => RconMsg::Prefill(String::from("clientkick ")),
+                    },
+                    gtk::Spinner {
+                        #[watch]
+                        set_spinning: model.busy,
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    #[name = "command"]
+                    gtk::Entry {
+                        set_hexpand: true,
+                        set_placeholder_text: Some("Command"),
+                        connect_activate[sender] => move |entry| {
+                            sender.input(RconMsg::Send(entry.text().to_string()));
+                        },
+                        add_controller = gtk::EventControllerKey {
+                            connect_key_pressed[sender] => move |_, key, _, _| {
+                                match key {
+                                    gtk::gdk::Key::Up => sender.input(RconMsg::HistoryUp),
+                                    gtk::gdk::Key::Down => sender.input(RconMsg::HistoryDown),
+                                    _ => return glib::Propagation::Proceed,
+                                }
+                                glib::Propagation::Stop
+                            },
+                        },
+                    },
+                    gtk::Button {
+                        set_label: "Send",
+                        connect_clicked[sender, command] => move |_| {
+                            sender.input(RconMsg::Send(command.text().to_string()));
+                        },
+                    },
+                },
+            }
+        }
+    }
+
+    fn init((id, label, server, password): Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let model = RconConsole {
+            id,
+            label,
+            server,
+            remember: password.is_some(),
+            password: password.clone().unwrap_or_default(),
+            saved: password,
+            history: Vec::new(),
+            history_pos: None,
+            busy: false,
+        };
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            RconMsg::SetPassword(password) => self.password = password,
+            RconMsg::SetRemember(remember) => {
+                self.remember = remember;
+                if !remember && self.saved.take().is_some() {
+                    sender.output(RconOutput::SavePassword(self.id, None)).unwrap();
+                }
+            }
+            RconMsg::Send(command) => {
+                let command = command.trim().to_string();
+                if command.is_empty() || self.busy { return; }
+                if self.password.is_empty() {
+                    append(&widgets.output, "Enter the rcon password first\n");
+                    return;
+                }
+                if !valid_password(&self.password) {
+                    append(&widgets.output, "rcon passwords can't contain spaces or quotes\n");
+                    return;
+                }
+                if self.history.last() != Some(&command) {
+                    self.history.push(command.clone());
+                }
+                self.history_pos = None;
+                widgets.command.set_text("");
+                append(&widgets.output, &format!("] {command}\n"));
+
+                self.busy = true;
+                let address = self.server.address();
+                let password = self.password.clone();
+                sender.spawn_oneshot_command(move || {
+                    let reply = query::resolve(&address)
+                        .and_then(|addr| send(addr, &password, &command))
+                        .map_err(|e| e.to_string());
+                    RconCmd::Reply(command, reply)
+                });
+            }
+            RconMsg::Prefill(text) => {
+                widgets.command.set_text(&text);
+                widgets.command.grab_focus();
+                widgets.command.set_position(-1);
+            }
+            RconMsg::HistoryUp => {
+                if self.history.is_empty() { return; }
+                let pos = self.history_pos.map_or(self.history.len() - 1, |p| p.saturating_sub(1));
+                self.history_pos = Some(pos);
+                widgets.command.set_text(&self.history[pos]);
+                widgets.command.set_position(-1);
+            }
+            RconMsg::HistoryDown => {
+                let Some(pos) = self.history_pos else { return; };
+                if pos + 1 < self.history.len() {
+                    self.history_pos = Some(pos + 1);
+                    widgets.command.set_text(&self.history[pos + 1]);
+                    widgets.command.set_position(-1);
+                }
+                else {
+                    self.history_pos = None;
+                    widgets.command.set_text("");
+                }
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+
+    fn update_cmd_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::CommandOutput, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            RconCmd::Reply(command, reply) => {
+                self.busy = false;
+                match reply {
+                    Ok(text) => {
+                        append(&widgets.output, &query::strip_colours(&text));
+                        if !text.ends_with('\n') {
+                            append(&widgets.output, "\n");
+                        }
+                        // Only keep passwords the server accepted
+                        let password = Some(self.password.clone());
+                        if self.remember && !text.starts_with("Bad rconpassword") && self.saved != password {
+                            self.saved = password.clone();
+                            sender.output(RconOutput::SavePassword(self.id, password)).unwrap();
+                        }
+                    }
+                    Err(e) => append(&widgets.output, &format!("{command}: {e}\n")),
+                }
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+}
+
+fn append(view: &gtk::TextView, text: &str)
+{
+    let buffer = view.buffer();
+    let mut end = buffer.end_iter();
+    buffer.insert(&mut end, text);
+    view.scroll_to_iter(&mut buffer.end_iter(), 0.0, false, 0.0, 1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers `rcon secret <command>` like a server, `status` in two packets.
+    fn fake_rcon_server() -> SocketAddr
+    {
+        query::fake_server(|packet| {
+            let Some((cmd, rest)) = query::parse_oob(packet) else { return Vec::new(); };
+            if cmd != "rcon" {
+                return Vec::new();
+            }
+            let rest = String::from_utf8_lossy(rest);
+            match rest.trim().split_once(' ') {
+                Some(("secret", "status")) => vec![
+                    query::oob_packet("print\nmap: mp_harbor\nnum score ping name\n"),
+                    query::oob_packet("print\n  0     5   48 Some One\n"),
+                ],
+                Some(("secret", command)) => vec![query::oob_packet(&format!("print\n{command}\n"))],
+                _ => vec![query::oob_packet("print\nBad rconpassword.\n")],
+            }
+        })
+    }
+
+    #[test]
+    fn packets()
+    {
+        assert_eq!(rcon_packet("secret", "status"), query::oob_packet("rcon secret status"));
+        assert_eq!(parse_print(&query::oob_packet("print\nhello\n\0")).as_deref(), Some("hello\n"));
+        assert_eq!(parse_print(&query::oob_packet("infoResponse\n")), None);
+    }
+
+    #[test]
+    fn passwords_with_spaces_or_quotes_are_refused()
+    {
+        assert!(valid_password("s3cr3t!"));
+        assert!(!valid_password(""));
+        assert!(!valid_password("two words"));
+        assert!(!valid_password("quo\"te"));
+
+        let server = fake_rcon_server();
+        let err = send(server, "two words", "status").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn replies_from_a_local_server()
+    {
+        let server = fake_rcon_server();
+        // Both packets of a long answer are joined
+        assert_eq!(send(server, "secret", "status").unwrap(), "map: mp_harbor\nnum score ping name\n  0     5   48 Some One\n");
+        assert_eq!(send(server, "secret", "map_rotate").unwrap(), "map_rotate\n");
+        assert_eq!(send(server, "wrong", "status").unwrap(), "Bad rconpassword.\n");
+    }
+
+    #[test]
+    fn silent_servers_time_out()
+    {
+        let server = query::fake_server(|_| Vec::new());
+        let socket = query::bind_for(&[server]).unwrap();
+        socket.send_to(&rcon_packet("secret", "status"), server).unwrap();
+        let err = collect_prints(&socket, server, Duration::from_millis(300)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{env, fs, io, collections::BTreeMap, path::{Path, PathBuf}};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{handlers, util, GameInfo};
+
+/// One codlinux copy and the games found next to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Install {
+    pub path: PathBuf,
+    pub executable: PathBuf,
+    pub games: Vec<RegisteredGame>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RegisteredGame {
+    pub name: String,
+    pub version: String,
+}
+
+/// Every install this user has started, shared by all codlinux copies so
+/// links end up in the right folder no matter which copy registered last.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub(crate) struct Registry {
+    pub installs: Vec<Install>,
+    /// scheme -> install folder that opens its links, picked by the user
+    /// or given to the first install able to
+    #[serde(default)]
+    pub preferred: BTreeMap<String, PathBuf>,
+    /// Install whose binary `codlinux-links.desktop` runs
+    #[serde(default)]
+    pub dispatcher: Option<PathBuf>,
+}
+
+impl Install {
+    pub(crate) fn handles(&self, scheme: &str) -> bool
+    {
+        handlers::candidates(scheme).iter()
+            .any(|name| self.games.iter().any(|g| g.name == *name))
+    }
+
+    fn is_stale(&self) -> bool
+    {
+        !self.path.is_dir() || !self.executable.exists()
+    }
+}
+
+pub(crate) fn data_dir() -> PathBuf
+{
+    match env::var("XDG_DATA_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("codlinux"),
+        _ => Path::new(&env::var("HOME").unwrap()).join(".local/share/codlinux"),
+    }
+}
+
+fn registry_file() -> PathBuf
+{
+    data_dir().join("installs.json")
+}
+
+impl Registry {
+    pub(crate) fn load() -> io::Result<Registry>
+    {
+        let path = registry_file();
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        let contents = fs::read_to_string(&path)?;
+        Ok(serde_json::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("CoDLinux: Ignoring broken {:?}: {}", path, e);
+            Registry::default()
+        }))
+    }
+
+    /// Written to a temporary file first, so a copy starting at the same
+    /// time never reads half a file.
+    pub(crate) fn save(&self) -> io::Result<()>
+    {
+        fs::create_dir_all(data_dir())?;
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        let tmp = registry_file().with_extension(format!("json.{}", std::process::id()));
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, registry_file())
+    }
+
+    /// Drops installs whose folder or binary is gone.
+    pub(crate) fn prune(&mut self)
+    {
+        self.installs.retain(|install| {
+            if install.is_stale() {
+                println!("CoDLinux: Forgetting vanished install {:?}", install.path);
+            }
+            !install.is_stale()
+        });
+        let installs = &self.installs;
+        self.preferred.retain(|_, path| installs.iter().any(|i| &i.path == path));
+        if self.dispatcher.as_ref().is_some_and(|path| !installs.iter().any(|i| i.path == *path)) {
+            self.dispatcher = None;
+        }
+    }
+
+    /// Gives every scheme without an install the first registered install
+    /// that can handle it, and the dispatcher role to `here` if nobody has
+    /// it. Returns true if anything changed.
+    pub(crate) fn assign(&mut self, here: &Path) -> bool
+    {
+        let mut changed = false;
+        for scheme in handlers::SCHEMES {
+            if self.preferred.contains_key(scheme) { continue; }
+            if let Some(install) = self.installs.iter().find(|i| i.handles(scheme)) {
+                println!("CoDLinux: {scheme}:// links now open with {:?}", install.path);
+                self.preferred.insert(scheme.to_string(), install.path.clone());
+                changed = true;
+            }
+        }
+        if self.dispatcher.is_none() && self.installs.iter().any(|i| i.path == here) {
+            self.dispatcher = Some(here.to_path_buf());
+            changed = true;
+        }
+        changed
+    }
+
+    /// The install the link desktop entry runs, if it still exists.
+    pub(crate) fn dispatcher(&self) -> Option<&Install>
+    {
+        let path = self.dispatcher.as_ref()?;
+        self.installs.iter().find(|i| &i.path == path && !i.is_stale())
+    }
+
+    pub(crate) fn upsert(&mut self, path: &Path, executable: &Path, games: &[GameInfo])
+    {
+        let install = Install {
+            path: path.to_path_buf(),
+            executable: executable.to_path_buf(),
+            games: games.iter()
+                .map(|g| RegisteredGame { name: g.name.clone(), version: g.version.clone() })
+                .collect(),
+            last_seen: Utc::now(),
+        };
+        match self.installs.iter_mut().find(|i| i.path == path) {
+            Some(existing) => *existing = install,
+            None => self.installs.push(install),
+        }
+    }
+
+    /// Install that opens links of `scheme`, see `assign`.
+    pub(crate) fn install_for(&self, scheme: &str) -> Option<&Install>
+    {
+        let path = self.preferred.get(scheme)?;
+        self.installs.iter().find(|i| &i.path == path && i.handles(scheme))
+    }
+
+    pub(crate) fn any_handles(&self, scheme: &str) -> bool
+    {
+        self.installs.iter().any(|i| i.handles(scheme))
+    }
+}
+
+/// Records this install and cleans out stale ones. Called on every start.
+pub(crate) fn update(games: &[GameInfo]) -> io::Result<Registry>
+{
+    let mut registry = Registry::load()?;
+    let here = util::my_exe_path()?;
+    registry.prune();
+    // An install that lost all its games gives its schemes up
+    registry.upsert(&here, &util::my_exe()?, games);
+    let installs = registry.installs.clone();
+    registry.preferred.retain(|scheme, path| installs.iter().any(|i| &i.path == path && i.handles(scheme)));
+    registry.assign(&here);
+    registry.save()?;
+    Ok(registry)
+}
+
+/// Makes this install open every scheme it can handle, and the one the
+/// link desktop entry runs.
+pub(crate) fn prefer_this_install() -> io::Result<()>
+{
+    let mut registry = Registry::load()?;
+    let here = util::my_exe_path()?;
+    let schemes: Vec<&str> = match registry.installs.iter().find(|i| i.path == here) {
+        Some(install) => handlers::SCHEMES.iter().copied().filter(|s| install.handles(s)).collect(),
+        None => Vec::new(),
+    };
+    for scheme in schemes {
+        registry.preferred.insert(scheme.to_string(), here.clone());
+    }
+    registry.dispatcher = Some(here);
+    registry.save()
+}
+
+/// Where a link passed to the dispatcher should go.
+pub(crate) enum Dispatch {
+    Here,
+    Elsewhere(PathBuf),
+    Nowhere,
+}
+
+pub(crate) fn dispatch(scheme: &str) -> io::Result<Dispatch>
+{
+    let mut registry = Registry::load()?;
+    let here = util::my_exe_path()?;
+    let before = registry.installs.len();
+    registry.prune();
+    if registry.assign(&here) || registry.installs.len() != before {
+        registry.save()?;
+    }
+
+    Ok(match registry.install_for(scheme) {
+        Some(install) if install.path == here => Dispatch::Here,
+        Some(install) => Dispatch::Elsewhere(install.executable.clone()),
+        None => Dispatch::Nowhere,
+    })
+}
+
+/// Replaces this process with the codlinux at `executable`, passing `args`
+/// on. Only returns if that fails.
+pub(crate) fn hand_off(executable: &Path, args: &[String]) -> io::Error
+{
+    use std::os::unix::process::CommandExt;
+
+    println!("CoDLinux: Handing link over to {:?}", executable);
+    let mut cmd = std::process::Command::new(executable);
+    cmd.args(args);
+    if let Some(dir) = executable.parent() {
+        cmd.current_dir(dir);
+    }
+    cmd.exec()
+}
+
+/// Application id of the install at `path`. Each install is its own single
+/// instance, a link the dispatcher routed here must not end up in another
+/// install that happens to be running.
+pub(crate) fn application_id(path: &Path) -> String
+{
+    let digest = format!("{:x}", md5::compute(path.to_string_lossy().as_bytes()));
+    format!("wolfpack.kazam.codlinux.i{}", &digest[..12])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn application_id_per_install()
+    {
+        let a = application_id(Path::new("/games/cod"));
+        assert_eq!(a, application_id(Path::new("/games/cod")));
+        assert_ne!(a, application_id(Path::new("/games/uo")));
+        // A valid D-Bus name: no element starts with a digit, only [A-Za-z0-9_-]
+        assert!(a.split('.').all(|e| !e.is_empty() && !e.starts_with(|c: char| c.is_ascii_digit())));
+        assert!(a.chars().all(|c| c.is_ascii_alphanumeric() || c == '.'));
+    }
+}
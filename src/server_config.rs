@@ -0,0 +1,541 @@
+use relm4::{gtk, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::{gdk, glib};
+
+use std::{cell::RefCell, fs, io, path::{Path, PathBuf}, rc::Rc};
+
+/// Cvars the editor shows as a form, with their labels.
+pub(crate) static COMMON_CVARS: [(&str, &str); 6] = [
+    ("sv_hostname", "Hostname"),
+    ("sv_maxclients", "Max clients"),
+    ("g_gametype", "Gametype"),
+    ("g_password", "Join password"),
+    ("rcon_password", "RCON password"),
+    ("sv_privatePassword", "Private slot password"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    /// `set`/`seta`/`sets key value`, `raw` is written back untouched
+    /// unless the value changes
+    Set { command: String, key: String, value: String, raw: String },
+    /// Comments, blank lines and any other command
+    Other(String),
+}
+
+/// A server config that keeps everything it doesn't understand.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ServerConfig {
+    lines: Vec<Line>,
+}
+
+fn parse_line(raw: &str) -> Line
+{
+    let trimmed = raw.trim();
+    let Some((command, rest)) = trimmed.split_once(char::is_whitespace) else {
+        return Line::Other(raw.to_string());
+    };
+    let command = command.to_lowercase();
+    if !matches!(command.as_str(), "set" | "seta" | "sets") {
+        return Line::Other(raw.to_string());
+    }
+    let rest = rest.trim_start();
+    let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let value = value.trim();
+    let value = if let Some(quoted) = value.strip_prefix('"') {
+        quoted.split_once('"').map(|(v, _)| v).unwrap_or(quoted)
+    }
+    else {
+        // Unquoted values end at a trailing comment
+        value.split("//").next().unwrap_or_default().trim()
+    };
+    Line::Set { command, key: key.to_string(), value: value.to_string(), raw: raw.to_string() }
+}
+
+impl ServerConfig {
+    pub(crate) fn parse(text: &str) -> ServerConfig
+    {
+        ServerConfig { lines: text.lines().map(parse_line).collect() }
+    }
+
+    pub(crate) fn load(path: &Path) -> io::Result<ServerConfig>
+    {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(ServerConfig::parse(&text)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ServerConfig::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> io::Result<()>
+    {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    pub(crate) fn to_text(&self) -> String
+    {
+        let mut text: String = self.lines.iter()
+            .map(|line| match line {
+                Line::Set { raw, .. } | Line::Other(raw) => raw.as_str(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        text.push('\n');
+        text
+    }
+
+    /// Last value set for `key`, like the game would see it.
+    pub(crate) fn get(&self, key: &str) -> Option<&str>
+    {
+        self.lines.iter().rev().find_map(|line| match line {
+            Line::Set { key: k, value, .. } if k.eq_ignore_ascii_case(key) => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Changes the last line setting `key`, or appends a `seta` line. The
+    /// value is written as given, quoted if the line was quoted before or
+    /// the value needs it.
+    pub(crate) fn set(&mut self, key: &str, value: &str)
+    {
+        if self.get(key) == Some(value) {
+            return;
+        }
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            Line::Set { command, key: k, value: v, raw } if k.eq_ignore_ascii_case(key) => Some((command, k, v, raw)),
+            _ => None,
+        });
+        match existing {
+            Some((command, k, v, raw)) => {
+                let quoted = was_quoted(raw) || needs_quotes(value);
+                *raw = format!("{} {} {}", command, k, quote(value, quoted));
+                *v = value.to_string();
+            }
+            None => self.lines.push(Line::Set {
+                command: String::from("seta"),
+                key: key.to_string(),
+                raw: format!("seta {} {}", key, quote(value, true)),
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    /// `sv_maprotation` as (gametype, map) pairs.
+    pub(crate) fn rotation(&self) -> Vec<(String, String)>
+    {
+        parse_rotation(self.get("sv_maprotation").unwrap_or_default(), self.get("g_gametype").unwrap_or("dm"))
+    }
+
+    /// Rewrites `sv_maprotation`, unless it already holds this rotation
+    /// in some other spelling.
+    pub(crate) fn set_rotation(&mut self, rotation: &[(String, String)])
+    {
+        if self.rotation() == rotation {
+            return;
+        }
+        self.set("sv_maprotation", &format_rotation(rotation));
+    }
+}
+
+/// True if the value of a `set` line is in quotes.
+fn was_quoted(raw: &str) -> bool
+{
+    let mut words = raw.trim().splitn(3, char::is_whitespace);
+    words.nth(2).is_some_and(|value| value.trim_start().starts_with('"'))
+}
+
+fn needs_quotes(value: &str) -> bool
+{
+    value.is_empty() || value.contains(char::is_whitespace) || value.contains("//") || value.contains(';')
+}
+
+fn quote(value: &str, quoted: bool) -> String
+{
+    if quoted { format!("\"{value}\"") } else { value.to_string() }
+}
+
+/// `gametype tdm map mp_harbor map mp_carentan gametype sd map mp_depot`,
+/// maps before the first `gametype` use `default_gametype`.
+pub(crate) fn parse_rotation(rotation: &str, default_gametype: &str) -> Vec<(String, String)>
+{
+    let mut gametype = default_gametype.to_string();
+    let mut words = rotation.split_whitespace();
+    let mut entries = Vec::new();
+    while let Some(word) = words.next() {
+        match (word.to_lowercase().as_str(), words.next()) {
+            ("gametype", Some(g)) => gametype = g.to_string(),
+            ("map", Some(m)) => entries.push((gametype.clone(), m.to_string())),
+            _ => (),
+        }
+    }
+    entries
+}
+
+/// Inverse of `parse_rotation`. The first entry always names its gametype,
+/// the server would use whatever `g_gametype` it's running otherwise, after
+/// that `gametype` is only written when it changes.
+pub(crate) fn format_rotation(rotation: &[(String, String)]) -> String
+{
+    let mut words = Vec::new();
+    let mut current: Option<&str> = None;
+    for (gametype, map) in rotation {
+        if current != Some(gametype.as_str()) {
+            words.push(format!("gametype {gametype}"));
+            current = Some(gametype);
+        }
+        words.push(format!("map {map}"));
+    }
+    words.join(" ")
+}
+
+/// Redraws the rotation list, set once the list exists.
+type Rebuild = Rc<RefCell<Option<Box<dyn Fn()>>>>;
+
+/// Editor for `path`. `maps` and `gametypes` come from the installed pk3s.
+pub(crate) fn show_editor_window(app: &gtk::Application, path: PathBuf, maps: Vec<String>, gametypes: Vec<String>)
+{
+    let config = match ServerConfig::load(&path) {
+        Ok(config) => Rc::new(RefCell::new(config)),
+        Err(e) => {
+            eprintln!("CoDLinux: Can't read {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let rotation = Rc::new(RefCell::new(config.borrow().rotation()));
+    let gametypes = if gametypes.is_empty() {
+        ["dm", "tdm", "sd", "re", "bel", "hq"].iter().map(|g| g.to_string()).collect()
+    } else { gametypes };
+
+    relm4::view! {
+        window = gtk::Window {
+            set_title: Some(&format!("Server config: {}", path.display())),
+            set_default_size: (720, 560),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                #[name = "form"]
+                gtk::Grid {
+                    set_row_spacing: 6,
+                    set_column_spacing: 12,
+                },
+
+                gtk::Label {
+                    set_markup: "<b>Map rotation</b>  <small>drag maps from the left, drag rows to reorder</small>",
+                    set_halign: gtk::Align::Start,
+                },
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 12,
+                    set_vexpand: true,
+
+                    gtk::ScrolledWindow {
+                        set_width_request: 200,
+                        set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                        #[name = "available"]
+                        gtk::ListBox {
+                            set_selection_mode: gtk::SelectionMode::None,
+                        },
+                    },
+                    gtk::ScrolledWindow {
+                        set_hexpand: true,
+                        set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+
+                        #[name = "rotation_list"]
+                        gtk::ListBox {
+                            set_selection_mode: gtk::SelectionMode::None,
+                            set_placeholder: Some(&gtk::Label::new(Some("Drop maps here"))),
+                        },
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+                    set_halign: gtk::Align::End,
+
+                    #[name = "save_status"]
+                    gtk::Label {},
+                    #[name = "save_button"]
+                    gtk::Button {
+                        set_label: "Save",
+                    },
+                },
+            }
+        }
+    }
+
+    // Common cvars
+    let mut entries: Vec<(&'static str, gtk::Entry)> = Vec::new();
+    for (row, (key, label)) in COMMON_CVARS.iter().enumerate() {
+        let name = gtk::Label::new(None);
+        name.set_markup(&format!("<b>{label}</b>"));
+        name.set_xalign(0.0);
+        let entry = gtk::Entry::new();
+        entry.set_hexpand(true);
+        entry.set_text(config.borrow().get(key).unwrap_or_default());
+        entry.set_tooltip_text(Some(key));
+        form.attach(&name, 0, row as i32, 1, 1);
+        form.attach(&entry, 1, row as i32, 1, 1);
+        entries.push((key, entry));
+    }
+
+    // Maps to drag from
+    for map in &maps {
+        let label = gtk::Label::new(Some(map));
+        label.set_xalign(0.0);
+        label.set_margin_all(4);
+        let source = gtk::DragSource::new();
+        source.set_actions(gdk::DragAction::COPY);
+        source.set_content(Some(&gdk::ContentProvider::for_value(&format!("map:{map}").to_value())));
+        label.add_controller(source);
+        available.append(&label);
+    }
+    if maps.is_empty() {
+        available.append(&gtk::Label::new(Some("No maps found in the game's pk3s")));
+    }
+
+    let rebuild: Rebuild = Rc::new(RefCell::new(None));
+    *rebuild.borrow_mut() = Some(Box::new(glib::clone!(
+        #[strong] rotation,
+        #[weak] rotation_list,
+        #[strong] gametypes,
+        #[weak] rebuild,
+        move || {
+            while let Some(child) = rotation_list.first_child() {
+                rotation_list.remove(&child);
+            }
+            for (i, (gametype, map)) in rotation.borrow().iter().enumerate() {
+                let row = gametype_row(i, gametype, map, &gametypes, &rotation, &rebuild);
+                rotation_list.append(&row);
+            }
+        }
+    )));
+
+    // Drags carry `map:<name>` from the left or `row:<index>` within the rotation
+    let target = gtk::DropTarget::new(String::static_type(), gdk::DragAction::COPY | gdk::DragAction::MOVE);
+    target.connect_drop(glib::clone!(
+        #[strong] rotation,
+        #[weak] rotation_list,
+        #[strong] rebuild,
+        #[strong] gametypes,
+        #[upgrade_or] false,
+        move |_, value, _x, y| {
+            let Ok(payload) = value.get::<String>() else { return false; };
+            let len = rotation.borrow().len();
+            let at = rotation_list.row_at_y(y as i32).map(|r| r.index() as usize).unwrap_or(len).min(len);
+            {
+                let mut rotation = rotation.borrow_mut();
+                if let Some(map) = payload.strip_prefix("map:") {
+                    let gametype = at.checked_sub(1).and_then(|i| rotation.get(i)).map(|(g, _)| g.clone())
+                        .unwrap_or_else(|| gametypes[0].clone());
+                    rotation.insert(at, (gametype, map.to_string()));
+                }
+                else if let Some(from) = payload.strip_prefix("row:").and_then(|i| i.parse::<usize>().ok()) {
+                    if from >= rotation.len() { return false; }
+                    let entry = rotation.remove(from);
+                    let at = if at > from { at - 1 } else { at };
+                    let len = rotation.len();
+                    rotation.insert(at.min(len), entry);
+                }
+            }
+            if let Some(rebuild) = rebuild.borrow().as_ref() {
+                rebuild();
+            }
+            true
+        }
+    ));
+    rotation_list.add_controller(target);
+
+    if let Some(rebuild) = rebuild.borrow().as_ref() {
+        rebuild();
+    }
+
+    save_button.connect_clicked(glib::clone!(
+        #[strong] config,
+        #[strong] rotation,
+        #[strong] save_status,
+        move |_| {
+            let mut config = config.borrow_mut();
+            for (key, entry) in &entries {
+                let value = entry.text();
+                // Don't add lines for empty fields that were never set
+                if !value.is_empty() || config.get(key).is_some() {
+                    config.set(key, value.trim());
+                }
+            }
+            config.set_rotation(&rotation.borrow());
+            match config.save(&path) {
+                Ok(()) => save_status.set_text("Saved"),
+                Err(e) => save_status.set_text(&format!("Failed to save: {e}")),
+            }
+        }
+    ));
+
+    app.add_window(&window);
+    window.set_visible(true);
+}
+
+/// One rotation entry: drag handle, gametype, map and a remove button.
+fn gametype_row(
+    index: usize,
+    gametype: &str,
+    map: &str,
+    gametypes: &[String],
+    rotation: &Rc<RefCell<Vec<(String, String)>>>,
+    rebuild: &Rebuild,
+) -> gtk::Box
+{
+    let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    row.set_margin_all(2);
+
+    let handle = gtk::Image::from_icon_name("list-drag-handle-symbolic");
+    let source = gtk::DragSource::new();
+    source.set_actions(gdk::DragAction::MOVE);
+    source.set_content(Some(&gdk::ContentProvider::for_value(&format!("row:{index}").to_value())));
+    handle.add_controller(source);
+    row.append(&handle);
+
+    let names: Vec<&str> = gametypes.iter().map(|g| g.as_str()).collect();
+    let dropdown = gtk::DropDown::from_strings(&names);
+    match gametypes.iter().position(|g| g.eq_ignore_ascii_case(gametype)) {
+        Some(selected) => dropdown.set_selected(selected as u32),
+        None => {
+            // Keep gametypes we don't know about selectable
+            let list = dropdown.model().and_downcast::<gtk::StringList>().unwrap();
+            list.append(gametype);
+            dropdown.set_selected(list.n_items() - 1);
+        }
+    }
+    dropdown.connect_selected_notify(glib::clone!(
+        #[strong] rotation,
+        move |dd| {
+            let Some(name) = dd.selected_item().and_downcast::<gtk::StringObject>() else { return; };
+            if let Some(entry) = rotation.borrow_mut().get_mut(index) {
+                entry.0 = name.string().to_string();
+            }
+        }
+    ));
+    row.append(&dropdown);
+
+    let label = gtk::Label::new(Some(map));
+    label.set_hexpand(true);
+    label.set_xalign(0.0);
+    row.append(&label);
+
+    let remove = gtk::Button::from_icon_name("list-remove");
+    remove.connect_clicked(glib::clone!(
+        #[strong] rotation,
+        #[strong] rebuild,
+        move |_| {
+            if index < rotation.borrow().len() {
+                rotation.borrow_mut().remove(index);
+            }
+            if let Some(rebuild) = rebuild.borrow().as_ref() {
+                rebuild();
+            }
+        }
+    ));
+    row.append(&remove);
+
+    row
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation(entries: &[(&str, &str)]) -> Vec<(String, String)>
+    {
+        entries.iter().map(|(g, m)| (g.to_string(), m.to_string())).collect()
+    }
+
+    #[test]
+    fn rotation_round_trip()
+    {
+        let text = "gametype tdm map mp_harbor map mp_carentan gametype sd map mp_depot";
+        let parsed = parse_rotation(text, "dm");
+        assert_eq!(parsed, rotation(&[("tdm", "mp_harbor"), ("tdm", "mp_carentan"), ("sd", "mp_depot")]));
+        assert_eq!(format_rotation(&parsed), text);
+
+        // Maps before any gametype use the default, and get it written out
+        let parsed = parse_rotation("map mp_harbor GAMETYPE sd map mp_depot", "dm");
+        assert_eq!(parsed, rotation(&[("dm", "mp_harbor"), ("sd", "mp_depot")]));
+        assert_eq!(format_rotation(&parsed), "gametype dm map mp_harbor gametype sd map mp_depot");
+        assert_eq!(parse_rotation(&format_rotation(&parsed), "tdm"), parsed);
+
+        assert_eq!(format_rotation(&[]), "");
+        assert!(parse_rotation("map", "dm").is_empty());
+    }
+
+    #[test]
+    fn set_lines()
+    {
+        let mut config = ServerConfig::parse("// My server\nset sv_hostname \"^1Red\" // name\nseta g_gametype sd\nexec maps.cfg");
+        assert_eq!(config.get("SV_HOSTNAME"), Some("^1Red"));
+        assert_eq!(config.get("g_gametype"), Some("sd"));
+        assert_eq!(config.get("exec"), None);
+
+        // Unchanged values leave the line alone, comment included
+        config.set("sv_hostname", "^1Red");
+        config.set("sv_hostname", "^2Green Server");
+        config.set("g_gametype", "tdm");
+        config.set("g_password", "");
+        config.set("rcon_password", "say\"hi");
+        assert_eq!(config.to_text(), "// My server\nset sv_hostname \"^2Green Server\"\nseta g_gametype tdm\nexec maps.cfg\nseta g_password \"\"\nseta rcon_password \"say\"hi\"\n");
+        assert_eq!(config.get("rcon_password"), Some("say\"hi"));
+
+        // The last of several lines wins and is the one changed
+        let mut config = ServerConfig::parse("set g_gametype dm\nset g_gametype sd");
+        config.set("g_gametype", "hq");
+        assert_eq!(config.to_text(), "set g_gametype dm\nset g_gametype hq\n");
+    }
+
+    #[test]
+    fn rotation_is_only_rewritten_when_it_changes()
+    {
+        let text = "set g_gametype sd\nset sv_maprotation \"map mp_harbor  map mp_depot\"\n";
+        let mut config = ServerConfig::parse(text);
+        let current = config.rotation();
+        assert_eq!(current, rotation(&[("sd", "mp_harbor"), ("sd", "mp_depot")]));
+        config.set_rotation(&current);
+        assert_eq!(config.to_text(), text);
+
+        config.set_rotation(&rotation(&[("sd", "mp_depot"), ("tdm", "mp_harbor")]));
+        assert_eq!(config.get("sv_maprotation"), Some("gametype sd map mp_depot gametype tdm map mp_harbor"));
+    }
+
+    #[test]
+    fn load_set_save()
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-server-config-{}", std::process::id()));
+        let path = dir.join("main").join("server.cfg");
+        let _ = fs::remove_dir_all(&dir);
+
+        // A missing config is empty and gets created on save
+        let mut config = ServerConfig::load(&path).unwrap();
+        assert_eq!(config.get("sv_hostname"), None);
+        config.set("sv_hostname", "Test");
+        config.save(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "seta sv_hostname \"Test\"\n");
+
+        fs::write(&path, "// keep me\nset sv_maxclients 16\nset sv_hostname Old\n").unwrap();
+        let mut config = ServerConfig::load(&path).unwrap();
+        config.set("sv_maxclients", "20");
+        config.set_rotation(&rotation(&[("dm", "mp_harbor")]));
+        config.save(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "// keep me\nset sv_maxclients 20\nset sv_hostname Old\nseta sv_maprotation \"gametype dm map mp_harbor\"\n",
+        );
+        assert_eq!(ServerConfig::load(&path).unwrap().rotation(), rotation(&[("dm", "mp_harbor")]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use relm4::{
+    factory::{DynamicIndex, FactoryComponent, FactorySender}, gtk, RelmWidgetExt
+};
+use gtk::prelude::*;
+
+use std::{fs, io, path::PathBuf, sync::atomic::{AtomicU64, Ordering}};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{uri::ServerUri, util};
+
+/// How many joins `history.json` keeps.
+static HISTORY_LEN: usize = 100;
+/// How many of them the main window shows.
+pub(crate) static RECENT_SHOWN: usize = 10;
+
+/// One connection, recorded whenever a game is launched with `+connect`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    pub time: DateTime<Utc>,
+    pub game: String,
+    /// Without the password, that only lives in favourites
+    pub server: ServerUri,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Favourite {
+    pub label: String,
+    pub server: ServerUri,
+    /// For servers we administrate, see `rcon`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rcon_password: Option<String>,
+}
+
+fn conf_file(name: &str) -> PathBuf
+{
+    util::my_exe_path().unwrap().join("codlinux_conf").join(name)
+}
+
+fn load_json<T: for<'de> Deserialize<'de> + Default>(name: &str) -> T
+{
+    let path = conf_file(name);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return T::default();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        eprintln!("CoDLinux: Ignoring broken {:?}: {}", path, e);
+        T::default()
+    })
+}
+
+fn save_json<T: Serialize>(name: &str, value: &T) -> io::Result<()>
+{
+    let json = serde_json::to_string_pretty(value).map_err(io::Error::other)?;
+    fs::write(conf_file(name), json)
+}
+
+/// Newest first.
+pub(crate) fn load_history() -> Vec<HistoryEntry>
+{
+    load_json("history.json")
+}
+
+pub(crate) fn record_join(game: &str, server: &ServerUri) -> io::Result<HistoryEntry>
+{
+    let mut server = server.clone();
+    server.password = None;
+    let entry = HistoryEntry { time: Utc::now(), game: game.to_string(), server };
+
+    let mut history = load_history();
+    history.insert(0, entry.clone());
+    history.truncate(HISTORY_LEN);
+    save_json("history.json", &history)?;
+    Ok(entry)
+}
+
+pub(crate) fn load_favourites() -> Vec<Favourite>
+{
+    load_json("favourites.json")
+}
+
+pub(crate) fn save_favourites(favourites: &[Favourite]) -> io::Result<()>
+{
+    save_json("favourites.json", &favourites)
+}
+
+/// Link to share, the password is never included.
+pub(crate) fn share_link(server: &ServerUri) -> String
+{
+    let mut server = server.clone();
+    server.password = None;
+    server.to_link()
+}
+
+static NEXT_ENTRY_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A row in the "Servers" section, either a favourite or a recent join.
+#[derive(Debug, Clone)]
+pub struct ServerEntry {
+    /// Stays the same while the row is edited or moved, unlike its index
+    pub id: u64,
+    pub favourite: bool,
+    pub label: String,
+    pub detail: String,
+    pub server: ServerUri,
+    pub rcon_password: Option<String>,
+}
+
+impl ServerEntry {
+    pub(crate) fn from_favourite(favourite: Favourite) -> ServerEntry
+    {
+        ServerEntry {
+            id: NEXT_ENTRY_ID.fetch_add(1, Ordering::Relaxed),
+            favourite: true,
+            detail: favourite.server.address(),
+            label: favourite.label,
+            server: favourite.server,
+            rcon_password: favourite.rcon_password,
+        }
+    }
+
+    pub(crate) fn from_history(entry: HistoryEntry) -> ServerEntry
+    {
+        ServerEntry {
+            id: NEXT_ENTRY_ID.fetch_add(1, Ordering::Relaxed),
+            favourite: false,
+            label: entry.server.address(),
+            detail: format!("{} • {}", entry.game, entry.time.with_timezone(&Local).format("%Y-%m-%d %H:%M")),
+            server: entry.server,
+            rcon_password: None,
+        }
+    }
+
+    pub(crate) fn to_favourite(&self) -> Favourite
+    {
+        Favourite { label: self.label.clone(), server: self.server.clone(), rcon_password: self.rcon_password.clone() }
+    }
+}
+
+#[derive(Debug)]
+pub enum EntryOutput {
+    Connect(DynamicIndex),
+    CopyLink(DynamicIndex),
+    /// Star a recent server
+    AddFavourite(DynamicIndex),
+    Remove(DynamicIndex),
+    /// New label and password of a favourite
+    Edited(DynamicIndex, String, String),
+    Rcon(DynamicIndex),
+    Details(DynamicIndex),
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for ServerEntry {
+    type Init = ServerEntry;
+    type Input = ();
+    type Output = EntryOutput;
+    type CommandOutput = ();
+    type ParentWidget = gtk::Box;
+
+    view! {
+        root = gtk::Box {
+            set_orientation: gtk::Orientation::Horizontal,
+            set_spacing: 6,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_hexpand: true,
+
+                gtk::Label {
+                    set_text: &self.label,
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: gtk::pango::EllipsizeMode::End,
+                },
+                gtk::Label {
+                    set_markup: &format!("<small>{}</small>", gtk::glib::markup_escape_text(&self.detail)),
+                    set_halign: gtk::Align::Start,
+                },
+            },
+
+            gtk::Button {
+                set_icon_name: "media-playback-start",
+                set_tooltip_text: Some("Connect"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(EntryOutput::Connect(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "edit-copy",
+                set_tooltip_text: Some(&format!("Copy {}:// link", self.server.scheme)),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(EntryOutput::CopyLink(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_icon_name: "dialog-information",
+                set_tooltip_text: Some("Details"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(EntryOutput::Details(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_visible: !self.favourite,
+                set_icon_name: "starred",
+                set_tooltip_text: Some("Add to favourites"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(EntryOutput::AddFavourite(index.clone())).unwrap();
+                },
+            },
+            gtk::Button {
+                set_visible: self.favourite,
+                set_icon_name: "utilities-terminal",
+                set_tooltip_text: Some("RCON console"),
+                connect_clicked[sender, index] => move |_| {
+                    sender.output(EntryOutput::Rcon(index.clone())).unwrap();
+                },
+            },
+            gtk::MenuButton {
+                set_visible: self.favourite,
+                set_icon_name: "document-edit",
+                set_direction: gtk::ArrowType::Right,
+
+                #[wrap(Some)]
+                set_popover: popover = &gtk::Popover {
+                    set_position: gtk::PositionType::Right,
+                    set_autohide: true,
+
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 6,
+
+                        gtk::Grid {
+                            set_row_spacing: 6,
+                            set_column_spacing: 12,
+                            set_margin_all: 12,
+
+                            attach[0, 0, 1, 1] = &gtk::Label {
+                                set_markup: "<b>Label</b>",
+                                set_halign: gtk::Align::Start,
+                            },
+                            #[name = "label_entry"]
+                            attach[1, 0, 1, 1] = &gtk::Entry {
+                                set_text: &self.label,
+                                set_hexpand: true,
+                            },
+
+                            attach[0, 1, 1, 1] = &gtk::Label {
+                                set_markup: "<b>Password</b>",
+                                set_halign: gtk::Align::Start,
+                            },
+                            #[name = "password_entry"]
+                            attach[1, 1, 1, 1] = &gtk::PasswordEntry {
+                                set_text: self.server.password.as_deref().unwrap_or_default(),
+                                set_show_peek_icon: true,
+                                set_hexpand: true,
+                            },
+                        },
+
+                        gtk::Button {
+                            set_label: "Save",
+                            connect_clicked[sender, index, label_entry, password_entry, popover] => move |_| {
+                                let label = label_entry.text().trim().to_string();
+                                let password = password_entry.text().to_string();
+                                sender.output(EntryOutput::Edited(index.clone(), label, password)).unwrap();
+
+                                popover.popdown();
+                            }
+                        },
+                        gtk::Button {
+                            set_label: "Remove",
+                            connect_clicked[sender, index, popover] => move |_| {
+                                popover.popdown();
+                                sender.output(EntryOutput::Remove(index.clone())).unwrap();
+                            }
+                        },
+                    },
+                }
+            },
+        }
+    }
+
+    fn init_model(entry: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> ServerEntry
+    {
+        entry
+    }
+}
@@ -0,0 +1,140 @@
+use relm4::gtk::glib;
+
+use std::{net::SocketAddr, time::{Duration, Instant}};
+
+use crate::{query::{self, ServerInfo}, uri::ServerUri, util, GameInfo};
+
+/// A join parked until the full server it targets has a free slot.
+pub(crate) struct SlotWait {
+    /// Already carries the connect args
+    pub game: GameInfo,
+    pub server: ServerUri,
+    pub addr: SocketAddr,
+    /// Last answer from the server, for the status line
+    pub last: ServerInfo,
+    /// The launcher window was hidden when the link came in
+    pub hidden: bool,
+    pub polling: bool,
+    started: Instant,
+    timeout: Duration,
+    timer: Option<glib::SourceId>,
+}
+
+impl SlotWait {
+    /// Starts polling, `poll` is called every `slot_poll_interval` seconds
+    /// until it returns false or the wait is cancelled.
+    pub(crate) fn start(game: GameInfo, server: ServerUri, info: ServerInfo, hidden: bool, poll: impl Fn() -> bool + 'static) -> SlotWait
+    {
+        let interval = util::load_setting("slot_poll_interval").unwrap_or_default().parse::<u32>().unwrap_or(5).max(1);
+        let minutes = util::load_setting("slot_wait_timeout").unwrap_or_default().parse::<u64>().unwrap_or(10);
+        let timer = glib::timeout_add_seconds_local(interval, move || {
+            if poll() { glib::ControlFlow::Continue } else { glib::ControlFlow::Break }
+        });
+
+        SlotWait {
+            game,
+            server,
+            addr: info.addr,
+            last: info,
+            hidden,
+            polling: false,
+            started: Instant::now(),
+            timeout: Duration::from_secs(minutes * 60),
+            timer: Some(timer),
+        }
+    }
+
+    pub(crate) fn expired(&self) -> bool
+    {
+        self.started.elapsed() >= self.timeout
+    }
+
+    /// Shown in the main window while waiting.
+    pub(crate) fn status(&self) -> String
+    {
+        let left = self.timeout.saturating_sub(self.started.elapsed()).as_secs();
+        format!("Waiting for a slot on {} ({}/{}), {}:{:02} left",
+            query::strip_colours(&self.last.hostname), self.last.clients, self.last.max_clients, left / 60, left % 60)
+    }
+}
+
+/// Dropping the wait stops polling.
+impl Drop for SlotWait {
+    fn drop(&mut self)
+    {
+        if let Some(timer) = self.timer.take() {
+            timer.remove();
+        }
+    }
+}
+
+/// Current info for the server behind `server`, None if it can't be
+/// resolved or doesn't answer. Blocking.
+pub(crate) fn check(server: &ServerUri) -> Option<ServerInfo>
+{
+    let addr = query::resolve(&server.address()).ok()?;
+    let mut info = query::query_info(addr, query::DEFAULT_TIMEOUT).unwrap_or_else(|e| {
+        eprintln!("CoDLinux: getinfo {addr} failed: {e}");
+        None
+    })?;
+    complete(&mut info);
+    Some(info)
+}
+
+/// Not every server puts fs_game in getinfo, getstatus has all serverinfo
+/// cvars. Those getinfo left out are added to `info.info`, pak lists included.
+/// An empty fs_game in getinfo is an answer too, no mod.
+pub(crate) fn complete(info: &mut ServerInfo)
+{
+    if info.info.contains_key("fs_game") || info.info.contains_key("game") { return; }
+    let Ok(Some(status)) = query::query_status(info.addr, query::DEFAULT_TIMEOUT) else { return; };
+    info.fs_game = status.cvar("fs_game").to_string();
+    for (key, value) in status.cvars {
+        info.info.entry(key).or_insert(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+    /// Answers getinfo with `info` and counts the getstatus it gets.
+    fn server(info: &'static str) -> (ServerUri, Arc<AtomicUsize>)
+    {
+        let statuses = Arc::new(AtomicUsize::new(0));
+        let counted = statuses.clone();
+        let addr = query::fake_server(move |packet| match query::parse_oob(packet) {
+            Some((cmd, _)) if cmd == "getinfo" => vec![query::oob_packet(&format!("infoResponse\n{info}"))],
+            Some((cmd, _)) if cmd == "getstatus" => {
+                counted.fetch_add(1, Ordering::Relaxed);
+                vec![query::oob_packet("statusResponse\n\\fs_game\\awe\\sv_referencedPakNames\\main/pak0 awe/awe\\mapname\\other\n")]
+            }
+            _ => Vec::new(),
+        });
+        let uri = crate::uri::parse(&format!("cod1://{addr}")).unwrap();
+        (uri, statuses)
+    }
+
+    #[test]
+    fn getstatus_only_without_an_fs_game_key()
+    {
+        // An empty fs_game means no mod, nothing more to ask
+        let (uri, statuses) = server("\\mapname\\mp_harbor\\fs_game\\");
+        let info = check(&uri).unwrap();
+        assert_eq!(info.fs_game, "");
+        assert_eq!(statuses.load(Ordering::Relaxed), 0);
+
+        let (uri, statuses) = server("\\mapname\\mp_harbor\\fs_game\\pam");
+        assert_eq!(check(&uri).unwrap().fs_game, "pam");
+        assert_eq!(statuses.load(Ordering::Relaxed), 0);
+
+        // Missing cvars come from getstatus, getinfo's own win
+        let (uri, statuses) = server("\\mapname\\mp_harbor");
+        let info = check(&uri).unwrap();
+        assert_eq!(statuses.load(Ordering::Relaxed), 1);
+        assert_eq!(info.fs_game, "awe");
+        assert_eq!(info.info["sv_referencedPakNames"], "main/pak0 awe/awe");
+        assert_eq!(info.info["mapname"], "mp_harbor");
+    }
+}
@@ -0,0 +1,129 @@
+use relm4::{gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::glib;
+
+/// Countdown shown before the remembered game is launched, so it can
+/// still be skipped without editing `codlinux.cfg`.
+pub struct Splash {
+    game: String,
+    remaining: u32,
+    timer: Option<glib::SourceId>,
+}
+
+#[derive(Debug)]
+pub enum SplashMsg {
+    Tick,
+    LaunchNow,
+    OpenLauncher,
+}
+
+#[derive(Debug)]
+pub enum SplashOutput {
+    Launch,
+    OpenLauncher,
+}
+
+#[relm4::component(pub)]
+impl Component for Splash {
+    type Init = (String, u32);
+    type Input = SplashMsg;
+    type Output = SplashOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_title: Some("CoDLinux"),
+            set_default_size: (300, 120),
+            set_resizable: false,
+            connect_close_request[sender] => move |_| {
+                sender.input(SplashMsg::OpenLauncher);
+                glib::Propagation::Stop
+            },
+            // Reading the seat's modifiers at start doesn't work on Wayland,
+            // so Shift is watched while the countdown runs
+            add_controller = gtk::EventControllerKey {
+                connect_key_pressed[sender] => move |_, key, _, _| {
+                    if matches!(key, gtk::gdk::Key::Shift_L | gtk::gdk::Key::Shift_R) {
+                        sender.input(SplashMsg::OpenLauncher);
+                    }
+                    glib::Propagation::Proceed
+                },
+                connect_modifiers[sender] => move |_, state| {
+                    if state.contains(gtk::gdk::ModifierType::SHIFT_MASK) {
+                        sender.input(SplashMsg::OpenLauncher);
+                    }
+                    glib::Propagation::Proceed
+                },
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 8,
+                set_margin_all: 12,
+
+                gtk::Image {
+                    set_icon_name: Some("codlinux"),
+                    set_icon_size: gtk::IconSize::Large,
+                },
+                gtk::Label {
+                    #[watch]
+                    set_markup: &format!("Launching <b>{}</b> in {}...", model.game, model.remaining),
+                },
+                gtk::Label {
+                    set_markup: "<small>Press Shift or pass --menu at start to skip this.</small>",
+                },
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 8,
+                    set_halign: gtk::Align::Center,
+
+                    gtk::Button {
+                        set_label: "Open launcher instead",
+                        connect_clicked => SplashMsg::OpenLauncher,
+                    },
+                    gtk::Button {
+                        set_label: "Launch now",
+                        connect_clicked => SplashMsg::LaunchNow,
+                    },
+                },
+            }
+        }
+    }
+
+    fn init((game, delay): Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let input = sender.input_sender().clone();
+        let timer = glib::timeout_add_seconds_local(1, move || {
+            if input.send(SplashMsg::Tick).is_ok() {
+                glib::ControlFlow::Continue
+            }
+            else {
+                glib::ControlFlow::Break
+            }
+        });
+
+        let model = Splash { game, remaining: delay, timer: Some(timer) };
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, root: &Self::Root)
+    {
+        let output = match msg {
+            SplashMsg::Tick => {
+                self.remaining = self.remaining.saturating_sub(1);
+                if self.remaining > 0 { return; }
+                SplashOutput::Launch
+            }
+            SplashMsg::LaunchNow => SplashOutput::Launch,
+            SplashMsg::OpenLauncher => SplashOutput::OpenLauncher,
+        };
+
+        // Only answer once, the window is going away anyway
+        let Some(timer) = self.timer.take() else { return; };
+        timer.remove();
+        root.set_visible(false);
+        sender.output(output).unwrap();
+    }
+}
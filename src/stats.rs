@@ -0,0 +1,499 @@
+use std::{collections::BTreeMap, fmt::Write as _, fs::File, io::{self, Read, Seek, SeekFrom}, path::{Path, PathBuf}};
+
+use serde::Serialize;
+
+use crate::query;
+
+/// One line of `games_mp.log` that matters for statistics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// `InitGame: \g_gametype\dm\mapname\mp_harbor...`
+    InitGame { map: String, gametype: String },
+    ShutdownGame,
+    /// `J;guid;num;name`
+    Join { name: String },
+    /// `Q;guid;num;name`
+    Quit { name: String },
+    /// `K;` and `D;` share their fields, an empty or `-1` attacker is the world
+    Kill(Hit),
+    Damage(Hit),
+    /// `say;` and `sayteam;`
+    Say { name: String, text: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Hit {
+    pub victim: String,
+    pub victim_team: String,
+    pub attacker: Option<String>,
+    pub attacker_team: String,
+    pub weapon: String,
+    pub damage: u32,
+    pub means: String,
+    pub location: String,
+}
+
+impl Hit {
+    pub(crate) fn is_headshot(&self) -> bool
+    {
+        self.location == "head" || self.means == "MOD_HEAD_SHOT"
+    }
+
+    pub(crate) fn is_suicide(&self) -> bool
+    {
+        self.attacker.as_ref().is_none_or(|a| *a == self.victim)
+    }
+
+    /// Teams are empty in deathmatch, where nobody has teammates.
+    pub(crate) fn is_teamkill(&self) -> bool
+    {
+        !self.is_suicide() && !self.victim_team.is_empty() && self.victim_team == self.attacker_team
+    }
+}
+
+/// `  12:34` at the start of every line, in seconds since the server started.
+fn parse_time(time: &str) -> Option<u64>
+{
+    let (minutes, seconds) = time.split_once(':')?;
+    Some(minutes.parse::<u64>().ok()? * 60 + seconds.parse::<u64>().ok()?)
+}
+
+/// Names are keyed without colour codes, `^1Bob` and `Bob` are one player.
+fn player_name(name: &str) -> String
+{
+    query::strip_colours(name).trim().to_string()
+}
+
+fn parse_hit(fields: &[&str]) -> Option<Hit>
+{
+    // guid;num;team;name;guid;num;team;name;weapon;damage;mod;location
+    if fields.len() < 12 { return None; }
+    let attacker = player_name(fields[7]);
+    let world = attacker.is_empty() || fields[5] == "-1";
+    Some(Hit {
+        victim: player_name(fields[3]),
+        victim_team: fields[2].to_string(),
+        attacker: (!world).then_some(attacker),
+        attacker_team: fields[6].to_string(),
+        weapon: fields[8].to_string(),
+        damage: fields[9].parse().unwrap_or(0),
+        means: fields[10].to_string(),
+        location: fields[11].to_string(),
+    })
+}
+
+/// Time and event of one log line, None for anything not tracked.
+pub(crate) fn parse_line(line: &str) -> Option<(u64, Event)>
+{
+    let (time, rest) = line.trim_start().split_once(' ')?;
+    let time = parse_time(time)?;
+    let rest = rest.trim_end_matches(['\r', '\n']);
+
+    if let Some(info) = rest.strip_prefix("InitGame:") {
+        let info = query::parse_info_string(info.trim());
+        let get = |key: &str| info.get(key).cloned().unwrap_or_default();
+        return Some((time, Event::InitGame { map: get("mapname"), gametype: get("g_gametype") }));
+    }
+    if rest.starts_with("ShutdownGame:") {
+        return Some((time, Event::ShutdownGame));
+    }
+
+    let fields: Vec<&str> = rest.split(';').collect();
+    let event = match fields[0] {
+        "J" if fields.len() >= 4 => Event::Join { name: player_name(fields[3]) },
+        "Q" if fields.len() >= 4 => Event::Quit { name: player_name(fields[3]) },
+        "K" => Event::Kill(parse_hit(&fields[1..])?),
+        "D" => Event::Damage(parse_hit(&fields[1..])?),
+        // The text may contain `;` itself
+        "say" | "sayteam" if fields.len() >= 5 => Event::Say {
+            name: player_name(fields[3]),
+            text: query::strip_colours(&fields[4..].join(";")).trim_start_matches('\u{15}').to_string(),
+        },
+        _ => return None,
+    };
+    Some((time, event))
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub suicides: u32,
+    pub teamkills: u32,
+    pub headshots: u32,
+    pub damage: u64,
+    /// Seconds connected
+    pub playtime: u64,
+    pub messages: u32,
+    /// Kills per weapon
+    pub weapons: BTreeMap<String, u32>,
+}
+
+impl PlayerStats {
+    pub(crate) fn ratio(&self) -> f32
+    {
+        self.kills as f32 / self.deaths.max(1) as f32
+    }
+
+    pub(crate) fn best_weapon(&self) -> Option<&str>
+    {
+        self.weapons.iter().max_by_key(|(_, kills)| **kills).map(|(weapon, _)| weapon.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct MapStats {
+    /// Times the map was loaded
+    pub games: u32,
+    pub kills: u32,
+    pub headshots: u32,
+    /// Seconds the map was running
+    pub playtime: u64,
+}
+
+/// Kills and deaths in the game being played.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Score {
+    pub kills: i32,
+    pub deaths: u32,
+}
+
+/// Statistics built from a `games_mp.log`, fed one line at a time so a
+/// running server's log can be followed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct Stats {
+    pub players: BTreeMap<String, PlayerStats>,
+    pub maps: BTreeMap<String, MapStats>,
+    /// Map and gametype being played
+    #[serde(skip)]
+    pub current: Option<(String, String)>,
+    /// Scores in the current game
+    #[serde(skip)]
+    pub scoreboard: BTreeMap<String, Score>,
+    #[serde(skip)]
+    game_started: Option<u64>,
+    /// Connected players and when they joined
+    #[serde(skip)]
+    online: BTreeMap<String, u64>,
+    #[serde(skip)]
+    last_time: u64,
+}
+
+impl Stats {
+    pub(crate) fn feed(&mut self, line: &str)
+    {
+        let Some((time, event)) = parse_line(line) else { return; };
+        // The clock starts over when the server restarts
+        if time < self.last_time {
+            self.end_game(self.last_time);
+            self.current = None;
+        }
+        self.last_time = time;
+
+        match event {
+            Event::InitGame { map, gametype } => {
+                self.end_game(time);
+                self.maps.entry(map.clone()).or_default().games += 1;
+                self.current = Some((map, gametype));
+                self.scoreboard.clear();
+                self.game_started = Some(time);
+            }
+            Event::ShutdownGame => self.end_game(time),
+            Event::Join { name } => {
+                self.online.entry(name.clone()).or_insert(time);
+                self.scoreboard.entry(name.clone()).or_default();
+                self.players.entry(name).or_default();
+            }
+            Event::Quit { name } => {
+                if let Some(joined) = self.online.remove(&name) {
+                    self.players.entry(name.clone()).or_default().playtime += time - joined;
+                }
+                self.scoreboard.remove(&name);
+            }
+            Event::Kill(hit) => {
+                self.seen(&hit.victim, time);
+                self.players.entry(hit.victim.clone()).or_default().deaths += 1;
+                self.scoreboard.entry(hit.victim.clone()).or_default().deaths += 1;
+                if let Some((map, _)) = &self.current {
+                    let map = self.maps.entry(map.clone()).or_default();
+                    map.kills += 1;
+                    map.headshots += hit.is_headshot() as u32;
+                }
+                if hit.is_suicide() {
+                    self.players.entry(hit.victim.clone()).or_default().suicides += 1;
+                    self.scoreboard.entry(hit.victim).or_default().kills -= 1;
+                    return;
+                }
+                let attacker = hit.attacker.clone().unwrap();
+                self.seen(&attacker, time);
+                if hit.is_teamkill() {
+                    self.players.entry(attacker.clone()).or_default().teamkills += 1;
+                    self.scoreboard.entry(attacker).or_default().kills -= 1;
+                    return;
+                }
+                let player = self.players.entry(attacker.clone()).or_default();
+                player.kills += 1;
+                player.headshots += hit.is_headshot() as u32;
+                *player.weapons.entry(hit.weapon).or_default() += 1;
+                self.scoreboard.entry(attacker).or_default().kills += 1;
+            }
+            Event::Damage(hit) => {
+                if let Some(attacker) = hit.attacker.as_ref().filter(|_| !hit.is_suicide()) {
+                    self.seen(attacker, time);
+                    self.players.entry(attacker.clone()).or_default().damage += hit.damage as u64;
+                }
+            }
+            Event::Say { name, .. } => {
+                self.seen(&name, time);
+                self.players.entry(name).or_default().messages += 1;
+            }
+        }
+    }
+
+    /// Logs that start mid-game have players without a `J;` line.
+    fn seen(&mut self, name: &str, time: u64)
+    {
+        if !self.online.contains_key(name) {
+            self.online.insert(name.to_string(), time);
+        }
+    }
+
+    /// Everyone is dropped at the end of a game, they join again on the next map.
+    fn end_game(&mut self, time: u64)
+    {
+        for (name, joined) in std::mem::take(&mut self.online) {
+            self.players.entry(name).or_default().playtime += time.saturating_sub(joined);
+        }
+        if let (Some(started), Some((map, _))) = (self.game_started.take(), &self.current) {
+            self.maps.entry(map.clone()).or_default().playtime += time.saturating_sub(started);
+        }
+    }
+
+    /// Totals with the game in progress counted up to the last line.
+    pub(crate) fn totals(&self) -> Stats
+    {
+        let mut totals = self.clone();
+        totals.end_game(self.last_time);
+        totals
+    }
+
+    pub(crate) fn players_csv(&self) -> String
+    {
+        let mut csv = String::from("player,kills,deaths,suicides,teamkills,headshots,damage,playtime_seconds,messages,best_weapon\n");
+        for (name, p) in &self.players {
+            let _ = writeln!(
+                csv, "{},{},{},{},{},{},{},{},{},{}",
+                csv_field(name), p.kills, p.deaths, p.suicides, p.teamkills, p.headshots, p.damage, p.playtime, p.messages,
+                csv_field(p.best_weapon().unwrap_or("")),
+            );
+        }
+        csv
+    }
+
+    pub(crate) fn maps_csv(&self) -> String
+    {
+        let mut csv = String::from("map,games,kills,headshots,playtime_seconds\n");
+        for (name, m) in &self.maps {
+            let _ = writeln!(csv, "{},{},{},{},{}", csv_field(name), m.games, m.kills, m.headshots, m.playtime);
+        }
+        csv
+    }
+}
+
+fn csv_field(s: &str) -> String
+{
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+    else {
+        s.to_string()
+    }
+}
+
+/// `1h 05m`, `12m 30s`
+pub(crate) fn format_playtime(seconds: u64) -> String
+{
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+    }
+    else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+/// Reads what was appended to a log since the last call.
+#[derive(Debug)]
+pub(crate) struct LogTail {
+    pub path: PathBuf,
+    offset: u64,
+    /// Last line, until its newline is written. Kept as bytes so a
+    /// character split between two reads isn't mangled.
+    partial: Vec<u8>,
+}
+
+impl LogTail {
+    pub(crate) fn new(path: &Path) -> LogTail
+    {
+        LogTail { path: path.to_path_buf(), offset: 0, partial: Vec::new() }
+    }
+
+    /// New complete lines, from the start again if the log was truncated.
+    pub(crate) fn read_lines(&mut self) -> io::Result<Vec<String>>
+    {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let read = file.read_to_end(&mut self.partial)?;
+        self.offset += read as u64;
+
+        let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') else { return Ok(Vec::new()); };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        Ok(String::from_utf8_lossy(&complete).lines().map(str::to_string).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(line: &str) -> Hit
+    {
+        match parse_line(line) {
+            Some((_, Event::Kill(hit))) => hit,
+            other => panic!("not a kill: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn lines()
+    {
+        assert_eq!(
+            parse_line("  0:00 InitGame: \\g_gametype\\sd\\mapname\\mp_harbor"),
+            Some((0, Event::InitGame { map: String::from("mp_harbor"), gametype: String::from("sd") })),
+        );
+        assert_eq!(parse_line("123:45 ShutdownGame:"), Some((7425, Event::ShutdownGame)));
+        assert_eq!(parse_line("  1:02 J;1234;3;^1Bob^7"), Some((62, Event::Join { name: String::from("Bob") })));
+        assert_eq!(parse_line("  1:02 Q;1234;3;Bob\r\n"), Some((62, Event::Quit { name: String::from("Bob") })));
+        assert_eq!(
+            parse_line("  1:05 say;1234;3;Bob;\u{15}gg; wp ^2all"),
+            Some((65, Event::Say { name: String::from("Bob"), text: String::from("gg; wp all") })),
+        );
+        assert_eq!(parse_line("  1:06 Weapon;1234;3;Bob;kar98k_mp"), None);
+        assert_eq!(parse_line("  1:06 K;1;2;axis"), None);
+        assert_eq!(parse_line("garbage"), None);
+        assert_eq!(parse_line("x:yz J;1;2;Bob"), None);
+    }
+
+    #[test]
+    fn kill_lines()
+    {
+        let kill = hit("  0:10 K;1;0;axis;^1Bob;2;1;allies;Alice;kar98k_mp;135;MOD_HEAD_SHOT;head");
+        assert_eq!(kill.victim, "Bob");
+        assert_eq!(kill.attacker.as_deref(), Some("Alice"));
+        assert_eq!((kill.weapon.as_str(), kill.damage), ("kar98k_mp", 135));
+        assert!(kill.is_headshot() && !kill.is_suicide() && !kill.is_teamkill());
+
+        // The world has no name and number -1
+        let world = hit("  0:20 K;2;1;allies;Alice;;-1;world;;none;100;MOD_FALLING;none");
+        assert_eq!(world.attacker, None);
+        assert!(world.is_suicide() && !world.is_teamkill());
+
+        let suicide = hit("  0:30 K;1;0;axis;Bob;1;0;axis;Bob;mk1britishfrag_mp;200;MOD_GRENADE_SPLASH;none");
+        assert!(suicide.is_suicide() && !suicide.is_teamkill());
+
+        let teamkill = hit("  0:41 K;3;2;allies;Carl;2;1;allies;Alice;kar98k_mp;100;MOD_RIFLE_BULLET;torso_upper");
+        assert!(teamkill.is_teamkill());
+
+        // Nobody is on a team in deathmatch
+        let dm = hit("  0:41 K;3;2;;Carl;2;1;;Alice;kar98k_mp;100;MOD_RIFLE_BULLET;torso_upper");
+        assert!(!dm.is_teamkill());
+    }
+
+    #[test]
+    fn feed_a_game()
+    {
+        let log = [
+            "  0:00 InitGame: \\g_gametype\\tdm\\mapname\\mp_harbor",
+            "  0:05 J;1;0;^1Bob",
+            "  0:06 J;2;1;Alice",
+            "  0:10 K;1;0;axis;^1Bob;2;1;allies;Alice;kar98k_mp;135;MOD_HEAD_SHOT;head",
+            "  0:20 K;2;1;allies;Alice;;-1;world;;none;100;MOD_FALLING;none",
+            "  0:30 K;1;0;axis;Bob;1;0;axis;Bob;mk1britishfrag_mp;200;MOD_GRENADE_SPLASH;none",
+            "  0:40 J;3;2;Carl",
+            "  0:41 K;3;2;allies;Carl;2;1;allies;Alice;kar98k_mp;100;MOD_RIFLE_BULLET;torso_upper",
+            "  0:45 Q;3;2;Carl",
+            "  0:50 say;2;1;Alice;gg; wp",
+        ];
+        let mut stats = Stats::default();
+        for line in log {
+            stats.feed(line);
+        }
+        assert_eq!(stats.current, Some((String::from("mp_harbor"), String::from("tdm"))));
+        let score = |name: &str| stats.scoreboard.get(name).map(|s| (s.kills, s.deaths));
+        assert_eq!(score("Alice"), Some((-1, 1)));
+        assert_eq!(score("Bob"), Some((-1, 2)));
+        assert_eq!(score("Carl"), None);
+
+        // The server restarted: the clock went back, the game so far is over
+        stats.feed("  0:02 InitGame: \\g_gametype\\dm\\mapname\\mp_depot");
+        assert_eq!(stats.current, Some((String::from("mp_depot"), String::from("dm"))));
+        assert!(stats.scoreboard.is_empty());
+
+        let bob = &stats.players["Bob"];
+        assert_eq!((bob.kills, bob.deaths, bob.suicides, bob.playtime), (0, 2, 1, 45));
+        let alice = &stats.players["Alice"];
+        assert_eq!((alice.kills, alice.deaths, alice.suicides, alice.teamkills, alice.headshots), (1, 1, 1, 1, 1));
+        assert_eq!((alice.messages, alice.playtime, alice.best_weapon()), (1, 44, Some("kar98k_mp")));
+        let carl = &stats.players["Carl"];
+        assert_eq!((carl.deaths, carl.playtime), (1, 5));
+
+        let harbor = &stats.maps["mp_harbor"];
+        assert_eq!((harbor.games, harbor.kills, harbor.headshots, harbor.playtime), (1, 4, 1, 50));
+        assert_eq!(stats.maps["mp_depot"].games, 1);
+
+        // The game in progress counts up to the last line
+        stats.feed("  0:12 J;1;0;Bob");
+        stats.feed("  1:12 ShutdownGame:");
+        assert_eq!(stats.players["Bob"].playtime, 105);
+        assert_eq!(stats.maps["mp_depot"].playtime, 70);
+    }
+
+    #[test]
+    fn tail_a_log()
+    {
+        let dir = std::env::temp_dir().join(format!("codlinux-stats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("games_mp.log");
+        let append = |bytes: &[u8]| {
+            use std::io::Write;
+            std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap().write_all(bytes).unwrap();
+        };
+        std::fs::write(&path, "").unwrap();
+        let mut tail = LogTail::new(&path);
+        assert!(tail.read_lines().unwrap().is_empty());
+
+        // The last line only counts once its newline is there
+        append(b"  0:00 J;1;0;Bob\n  0:01 J;2;1;Ali");
+        assert_eq!(tail.read_lines().unwrap(), ["  0:00 J;1;0;Bob"]);
+        append(b"ce\n");
+        assert_eq!(tail.read_lines().unwrap(), ["  0:01 J;2;1;Alice"]);
+        assert!(tail.read_lines().unwrap().is_empty());
+
+        // A character split between two writes comes out whole
+        append(b"  0:02 say;1;0;Bob;caf\xc3");
+        assert!(tail.read_lines().unwrap().is_empty());
+        append(b"\xa9\n");
+        assert_eq!(tail.read_lines().unwrap(), ["  0:02 say;1;0;Bob;caf\u{e9}"]);
+
+        // A truncated log is read from the start again
+        std::fs::write(&path, "  0:00 J;3;2;Carl\n").unwrap();
+        assert_eq!(tail.read_lines().unwrap(), ["  0:00 J;3;2;Carl"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+use relm4::{gtk, Component, ComponentParts, ComponentSender, RelmWidgetExt};
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+use std::{fs, path::PathBuf};
+
+use crate::stats::{self, LogTail, Stats};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Statistics and live scoreboard of a hosted server, following its `games_mp.log`.
+pub struct StatsWindow {
+    name: String,
+    stats: Stats,
+    /// None until the existing log has been read
+    tail: Option<LogTail>,
+    log: PathBuf,
+    error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum StatsMsg {
+    /// Reads what the server logged since the last time
+    Tail,
+    ChooseExport(ExportFormat),
+    Export(PathBuf, ExportFormat),
+}
+
+#[derive(Debug)]
+pub enum StatsCmd {
+    /// The log read so far, in the background since it can be large
+    Loaded(Stats, LogTail),
+}
+
+#[relm4::component(pub)]
+impl Component for StatsWindow {
+    /// Server name and its log
+    type Init = (String, PathBuf);
+    type Input = StatsMsg;
+    type Output = ();
+    type CommandOutput = StatsCmd;
+
+    view! {
+        gtk::Window {
+            set_title: Some(&format!("Statistics: {}", model.name)),
+            set_default_size: (720, 480),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_spacing: 6,
+                set_margin_all: 12,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    gtk::Label {
+                        #[watch]
+                        set_text: &model.status(),
+                        set_hexpand: true,
+                        set_xalign: 0.0,
+                        set_ellipsize: gtk::pango::EllipsizeMode::Middle,
+                    },
+                    gtk::Spinner {
+                        #[watch]
+                        set_spinning: model.tail.is_none() && model.error.is_none(),
+                    },
+                    gtk::Button {
+                        set_label: "Export CSV",
+                        #[watch]
+                        set_sensitive: model.tail.is_some(),
+                        connect_clicked => StatsMsg::ChooseExport(ExportFormat::Csv),
+                    },
+                    gtk::Button {
+                        set_label: "Export JSON",
+                        #[watch]
+                        set_sensitive: model.tail.is_some(),
+                        connect_clicked => StatsMsg::ChooseExport(ExportFormat::Json),
+                    },
+                },
+
+                gtk::Notebook {
+                    set_vexpand: true,
+
+                    append_page[Some(&gtk::Label::new(Some("Scoreboard")))] = &gtk::ScrolledWindow {
+                        set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+                        #[name = "scoreboard"]
+                        gtk::Grid {
+                            set_row_spacing: 4,
+                            set_column_spacing: 12,
+                            set_margin_all: 6,
+                        },
+                    },
+                    append_page[Some(&gtk::Label::new(Some("Players")))] = &gtk::ScrolledWindow {
+                        set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+                        #[name = "players"]
+                        gtk::Grid {
+                            set_row_spacing: 4,
+                            set_column_spacing: 12,
+                            set_margin_all: 6,
+                        },
+                    },
+                    append_page[Some(&gtk::Label::new(Some("Maps")))] = &gtk::ScrolledWindow {
+                        set_policy: (gtk::PolicyType::Never, gtk::PolicyType::Automatic),
+                        #[name = "maps"]
+                        gtk::Grid {
+                            set_row_spacing: 4,
+                            set_column_spacing: 12,
+                            set_margin_all: 6,
+                        },
+                    },
+                },
+            }
+        }
+    }
+
+    fn init((name, log): Self::Init, root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self>
+    {
+        let model = StatsWindow {
+            name,
+            stats: Stats::default(),
+            tail: None,
+            log: log.clone(),
+            error: None,
+        };
+        let widgets = view_output!();
+
+        sender.spawn_oneshot_command(move || {
+            let mut tail = LogTail::new(&log);
+            let mut stats = Stats::default();
+            match tail.read_lines() {
+                Ok(lines) => lines.iter().for_each(|line| stats.feed(line)),
+                Err(e) => eprintln!("CoDLinux: Can't read {}: {e}", log.display()),
+            }
+            StatsCmd::Loaded(stats, tail)
+        });
+
+        let input = sender.input_sender().clone();
+        glib::timeout_add_seconds_local(2, move || {
+            if input.send(StatsMsg::Tail).is_ok() {
+                glib::ControlFlow::Continue
+            }
+            else {
+                glib::ControlFlow::Break
+            }
+        });
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::Input, sender: ComponentSender<Self>, root: &Self::Root)
+    {
+        match msg {
+            StatsMsg::Tail => {
+                // Hidden windows stop following the log
+                if !root.is_visible() { return; }
+                let Some(tail) = &mut self.tail else { return; };
+                match tail.read_lines() {
+                    Ok(lines) if lines.is_empty() => return,
+                    Ok(lines) => {
+                        lines.iter().for_each(|line| self.stats.feed(line));
+                        self.error = None;
+                    }
+                    Err(e) => self.error = Some(format!("Can't read {}: {e}", self.log.display())),
+                }
+            }
+            StatsMsg::ChooseExport(format) => {
+                let extension = match format { ExportFormat::Csv => "csv", ExportFormat::Json => "json" };
+                let dialog = gtk::FileDialog::builder()
+                    .title("Export statistics")
+                    .initial_name(format!("{}-stats.{}", self.name, extension))
+                    .modal(true)
+                    .build();
+                let input = sender.input_sender().clone();
+                dialog.save(Some(root), gio::Cancellable::NONE, move |result| {
+                    if let Some(path) = result.ok().and_then(|file| file.path()) {
+                        input.emit(StatsMsg::Export(path, format));
+                    }
+                });
+                return;
+            }
+            StatsMsg::Export(path, format) => {
+                let totals = self.stats.totals();
+                let result = match format {
+                    // Players go to the chosen file, maps next to it
+                    ExportFormat::Csv => fs::write(&path, totals.players_csv())
+                        .and_then(|_| fs::write(path.with_extension("maps.csv"), totals.maps_csv())),
+                    ExportFormat::Json => serde_json::to_string_pretty(&totals)
+                        .map_err(std::io::Error::other)
+                        .and_then(|json| fs::write(&path, json)),
+                };
+                self.error = result.err().map(|e| format!("Export failed: {e}"));
+            }
+        }
+        fill(widgets, &self.stats);
+        self.update_view(widgets, sender);
+    }
+
+    fn update_cmd_with_view(&mut self, widgets: &mut Self::Widgets, msg: Self::CommandOutput, sender: ComponentSender<Self>, _root: &Self::Root)
+    {
+        match msg {
+            StatsCmd::Loaded(stats, tail) => {
+                self.stats = stats;
+                self.tail = Some(tail);
+            }
+        }
+        fill(widgets, &self.stats);
+        self.update_view(widgets, sender);
+    }
+}
+
+impl StatsWindow {
+    fn status(&self) -> String
+    {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        if self.tail.is_none() {
+            return format!("Reading {}", self.log.display());
+        }
+        match &self.stats.current {
+            Some((map, gametype)) => format!("Playing {map} ({gametype}), {} players seen", self.stats.players.len()),
+            None => format!("{} players seen in {}", self.stats.players.len(), self.log.display()),
+        }
+    }
+}
+
+fn fill(widgets: &StatsWindowWidgets, stats: &Stats)
+{
+    let mut scores: Vec<_> = stats.scoreboard.iter().collect();
+    scores.sort_by_key(|(_, s)| (-s.kills, s.deaths));
+    fill_grid(&widgets.scoreboard, &["Player", "Kills", "Deaths"], scores.iter().map(|(name, s)| {
+        vec![name.to_string(), s.kills.to_string(), s.deaths.to_string()]
+    }));
+
+    let totals = stats.totals();
+    let mut players: Vec<_> = totals.players.iter().collect();
+    players.sort_by_key(|(_, p)| std::cmp::Reverse(p.kills));
+    fill_grid(
+        &widgets.players,
+        &["Player", "Kills", "Deaths", "K/D", "Headshots", "Suicides", "Teamkills", "Playtime", "Best weapon"],
+        players.iter().map(|(name, p)| vec![
+            name.to_string(),
+            p.kills.to_string(),
+            p.deaths.to_string(),
+            format!("{:.2}", p.ratio()),
+            p.headshots.to_string(),
+            p.suicides.to_string(),
+            p.teamkills.to_string(),
+            stats::format_playtime(p.playtime),
+            p.best_weapon().unwrap_or("").to_string(),
+        ]),
+    );
+
+    let mut maps: Vec<_> = totals.maps.iter().collect();
+    maps.sort_by_key(|(_, m)| std::cmp::Reverse(m.playtime));
+    fill_grid(&widgets.maps, &["Map", "Games", "Kills", "Headshots", "Playtime"], maps.iter().map(|(name, m)| vec![
+        name.to_string(),
+        m.games.to_string(),
+        m.kills.to_string(),
+        m.headshots.to_string(),
+        stats::format_playtime(m.playtime),
+    ]));
+}
+
+pub(crate) fn fill_grid(grid: &gtk::Grid, titles: &[&str], rows: impl Iterator<Item = Vec<String>>)
+{
+    while let Some(child) = grid.first_child() {
+        grid.remove(&child);
+    }
+    for (col, title) in titles.iter().enumerate() {
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!("<b>{title}</b>"));
+        label.set_xalign(0.0);
+        grid.attach(&label, col as i32, 0, 1, 1);
+    }
+    for (row, cells) in rows.enumerate() {
+        for (col, text) in cells.iter().enumerate() {
+            let label = gtk::Label::new(Some(text));
+            label.set_xalign(0.0);
+            label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+            grid.attach(&label, col as i32, row as i32 + 1, 1, 1);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{env, fs, io, os::unix::process::CommandExt, path::{Path, PathBuf}, process::Command};
+
+use crate::{hosting::{self, HostedServer}, launch::LaunchPlan, util, GameInfo};
+
+/// `codlinux-server-<name>.service`, see `HostedServer::file_name`.
+pub(crate) fn unit_name(server: &HostedServer) -> String
+{
+    format!("codlinux-server-{}.service", server.file_name())
+}
+
+fn unit_dir() -> PathBuf
+{
+    let config = env::var("XDG_CONFIG_HOME").ok().filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var("HOME").unwrap_or_default()).join(".config"));
+    config.join("systemd").join("user")
+}
+
+pub(crate) fn unit_path(server: &HostedServer) -> PathBuf
+{
+    unit_dir().join(unit_name(server))
+}
+
+pub(crate) fn is_installed(server: &HostedServer) -> bool
+{
+    unit_path(server).exists()
+}
+
+/// Quotes one ExecStart word, systemd also expands `%` specifiers.
+fn systemd_quote(s: &str) -> String
+{
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%"))
+}
+
+/// The unit for `server`. It runs `codlinux server run <name>`, which
+/// resolves the command from the current settings on every start. The
+/// command as resolved now is only recorded in a comment.
+pub(crate) fn unit_file(executable: &Path, server: &HostedServer, plan: &LaunchPlan) -> String
+{
+    let mut unit = String::new();
+    unit.push_str("[Unit]\n");
+    unit.push_str(&format!("Description=CoD dedicated server {} (port {})\n", server.name, server.port));
+    unit.push_str("After=network-online.target\n\n");
+
+    unit.push_str("[Service]\n");
+    unit.push_str("# `codlinux server run` resolves the command again on every start, when written it was:\n");
+    unit.push_str(&format!("# Runner: {}\n", plan.runner));
+    unit.push_str(&format!("# Command: {}\n", plan.to_shell().replace('\n', " ")));
+    unit.push_str(&format!("Environment={}\n", systemd_quote(&format!("WINEPREFIX={}", plan.wine_prefix))));
+    for (key, value) in &plan.env {
+        unit.push_str(&format!("Environment={}\n", systemd_quote(&format!("{key}={value}"))));
+    }
+    unit.push_str(&format!("WorkingDirectory={}\n", systemd_quote(&plan.working_dir.to_string_lossy())));
+    unit.push_str(&format!(
+        "ExecStart={} server run {}\n",
+        systemd_quote(&executable.to_string_lossy()),
+        systemd_quote(&server.name),
+    ));
+    unit.push_str("Restart=on-failure\n");
+    unit.push_str("RestartSec=10\n\n");
+
+    unit.push_str("[Install]\n");
+    unit.push_str("WantedBy=default.target\n");
+    unit
+}
+
+fn systemctl(args: &[&str]) -> io::Result<()>
+{
+    let status = Command::new("systemctl").arg("--user").args(args).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("systemctl --user {} failed", args.join(" "))));
+    }
+    Ok(())
+}
+
+/// Writes the unit, then enables and starts it.
+pub(crate) fn enable(server: &HostedServer, plan: &LaunchPlan) -> io::Result<()>
+{
+    let path = unit_path(server);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, unit_file(&util::my_exe()?, server, plan))?;
+    println!("CoDLinux: Wrote {}", path.display());
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", &unit_name(server)])
+}
+
+/// Stops and disables the unit and removes it.
+pub(crate) fn disable(server: &HostedServer) -> io::Result<()>
+{
+    systemctl(&["disable", "--now", &unit_name(server)])?;
+    fs::remove_file(unit_path(server))?;
+    systemctl(&["daemon-reload"])
+}
+
+/// `active`, `inactive`, `failed`... as `systemctl is-active` reports it.
+pub(crate) fn status(server: &HostedServer) -> String
+{
+    match Command::new("systemctl").args(["--user", "is-active", &unit_name(server)]).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        Err(e) => format!("unknown ({e})"),
+    }
+}
+
+fn find(name: &str) -> io::Result<HostedServer>
+{
+    hosting::load_hosted().into_iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No hosted server named `{name}`")))
+}
+
+fn plan_for(server: &HostedServer, games: &[GameInfo]) -> io::Result<LaunchPlan>
+{
+    let game = games.iter().find(|g| g.name == server.game)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not installed here", server.game)))?;
+    let game = crate::load_game_settings(game.clone())?;
+    hosting::plan(&game, server)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No dedicated executable next to {}", game.path.display())))
+}
+
+/// `codlinux server <list|run|enable|disable|status> [name]`
+pub(crate) fn run_cli(args: &[String], games: &[GameInfo]) -> io::Result<()>
+{
+    let command = args.first().map(|a| a.as_str()).unwrap_or("list");
+    if command == "list" {
+        for server in hosting::load_hosted() {
+            println!("{}\t{}\tport {}\t{}", server.name, server.game, server.port, status(&server));
+        }
+        return Ok(());
+    }
+
+    let name = args.get(1).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Usage: codlinux server {command} <name>")))?;
+    let server = find(name)?;
+    match command {
+        // Used by the unit: replace ourselves with the server's shell so
+        // systemd tracks it and the journal gets its output
+        "run" => {
+            let plan = plan_for(&server, games)?;
+            println!("CoDLinux: {}", plan.to_shell());
+            Err(Command::new("bash").arg("-c").arg(plan.to_shell()).exec())
+        }
+        "enable" => enable(&server, &plan_for(&server, games)?),
+        "disable" => disable(&server),
+        "status" => {
+            let _ = Command::new("systemctl").args(["--user", "status", "--no-pager", &unit_name(&server)]).status()?;
+            Ok(())
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown server command `{command}`"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(name: &str) -> HostedServer
+    {
+        HostedServer {
+            name: name.to_string(),
+            game: String::from("Call of Duty"),
+            port: 28960,
+            config: String::from("server.cfg"),
+            headless: true,
+            args: String::new(),
+        }
+    }
+
+    #[test]
+    fn unit_names_stay_apart()
+    {
+        assert_eq!(unit_name(&server("my-server")), "codlinux-server-my-server.service");
+        let spaced = unit_name(&server("my server"));
+        assert!(spaced.starts_with("codlinux-server-my-server-") && spaced.len() == "codlinux-server-my-server-.service".len() + 8);
+        assert_ne!(spaced, unit_name(&server("my/server")));
+        assert!(!hosting::log_file(&server("../../x")).to_string_lossy().contains(".."));
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::launch::shell_quote;
+
+pub(crate) static DEFAULT_PORT: u16 = 28960;
+
+/// A parsed `iw1x://` / `t1x://` link.
+///
+/// Grammar: `scheme://host[:port][/][?key=value&...]` where host is a
+/// hostname, an IPv4 address or a bracketed IPv6 address. Keys and values
+/// are percent-decoded. Known keys are `password`, `fs_game` and `name`,
+/// anything else is ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ServerUri {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+    pub password: Option<String>,
+    pub fs_game: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UriError {
+    NotALink,
+    MissingHost,
+    BadHost(String),
+    BadPort(String),
+    BadEscape(String),
+}
+
+impl fmt::Display for UriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self {
+            UriError::NotALink => write!(f, "not a server link"),
+            UriError::MissingHost => write!(f, "the link has no server address"),
+            UriError::BadHost(h) => write!(f, "invalid server address `{h}`"),
+            UriError::BadPort(p) => write!(f, "invalid port `{p}`"),
+            UriError::BadEscape(s) => write!(f, "invalid percent-encoding in `{s}`"),
+        }
+    }
+}
+
+impl ServerUri {
+    /// `host:port`, with brackets around IPv6 addresses.
+    pub(crate) fn address(&self) -> String
+    {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        }
+        else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Game arguments for this link. `fs_game` goes first, the game
+    /// needs it before anything else is loaded.
+    pub(crate) fn to_args(&self) -> Vec<String>
+    {
+        let mut args = Vec::new();
+        if let Some(fs_game) = &self.fs_game {
+            args.push(format!("+set fs_game {}", shell_quote(fs_game)));
+        }
+        if let Some(name) = &self.name {
+            args.push(format!("+set name {}", shell_quote(name)));
+        }
+        if let Some(password) = &self.password {
+            args.push(format!("+password {}", shell_quote(password)));
+        }
+        args.push(format!("+connect {}", shell_quote(&self.address())));
+        args
+    }
+
+    /// Builds the link back, the inverse of `parse`.
+    pub(crate) fn to_link(&self) -> String
+    {
+        let mut link = format!("{}://{}", self.scheme, self.address());
+        let mut query = Vec::new();
+        for (key, value) in [("password", &self.password), ("fs_game", &self.fs_game), ("name", &self.name)] {
+            if let Some(value) = value {
+                query.push(format!("{key}={}", percent_encode(value)));
+            }
+        }
+        if !query.is_empty() {
+            link.push('?');
+            link.push_str(&query.join("&"));
+        }
+        link
+    }
+}
+
+pub(crate) fn is_link(arg: &str) -> bool
+{
+    arg.split_once("://").is_some_and(|(scheme, _)| {
+        !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric())
+    })
+}
+
+pub(crate) fn parse(link: &str) -> Result<ServerUri, UriError>
+{
+    let (scheme, rest) = link.trim().split_once("://").ok_or(UriError::NotALink)?;
+    if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(UriError::NotALink);
+    }
+
+    let (authority, query) = match rest.split_once('?') {
+        Some((a, q)) => (a, q),
+        None => (rest, ""),
+    };
+    let authority = authority.trim_end_matches('/');
+    if authority.is_empty() {
+        return Err(UriError::MissingHost);
+    }
+
+    let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        let (host, after) = bracketed.split_once(']').ok_or_else(|| UriError::BadHost(authority.to_string()))?;
+        if host.is_empty() || !host.chars().all(|c| c.is_ascii_hexdigit() || c == ':' || c == '.') {
+            return Err(UriError::BadHost(host.to_string()));
+        }
+        let port = match after {
+            "" => None,
+            p => Some(p.strip_prefix(':').ok_or_else(|| UriError::BadPort(p.to_string()))?),
+        };
+        (host.to_string(), port)
+    }
+    else {
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((h, p)) => (h, Some(p)),
+            None => (authority, None),
+        };
+        let host = percent_decode(host)?;
+        if host.is_empty() || !host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-') {
+            return Err(UriError::BadHost(host));
+        }
+        (host, port)
+    };
+
+    let port = match port {
+        None | Some("") => DEFAULT_PORT,
+        Some(p) => match p.parse::<u16>() {
+            Ok(0) | Err(_) => return Err(UriError::BadPort(p.to_string())),
+            Ok(p) => p,
+        },
+    };
+
+    let mut uri = ServerUri {
+        scheme: scheme.to_lowercase(),
+        host,
+        port,
+        password: None,
+        fs_game: None,
+        name: None,
+    };
+
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let key = percent_decode(key)?;
+        let value = percent_decode(value)?;
+        if value.is_empty() { continue; }
+        match key.as_str() {
+            "password" => uri.password = Some(value),
+            "fs_game" => uri.fs_game = Some(value),
+            "name" => uri.name = Some(value),
+            _ => println!("CoDLinux: Ignoring unknown link option `{key}`"),
+        }
+    }
+
+    Ok(uri)
+}
+
+/// Decodes `%XX` escapes. `+` stays a `+`, passwords can contain it.
+pub(crate) fn percent_decode(s: &str) -> Result<String, UriError>
+{
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3).ok_or_else(|| UriError::BadEscape(s.to_string()))?;
+                let byte = u8::from_str_radix(hex, 16).map_err(|_| UriError::BadEscape(s.to_string()))?;
+                out.push(byte);
+                i += 3;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).map_err(|_| UriError::BadEscape(s.to_string()))
+}
+
+pub(crate) fn percent_encode(s: &str) -> String
+{
+    let mut out = String::new();
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        }
+        else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_options()
+    {
+        let uri = parse("iw1x://1.2.3.4:28961/?password=a+b%26c&fs_game=awe&name=Some%20One").unwrap();
+        assert_eq!(uri.scheme, "iw1x");
+        assert_eq!(uri.host, "1.2.3.4");
+        assert_eq!(uri.port, 28961);
+        assert_eq!(uri.password.as_deref(), Some("a+b&c"));
+        assert_eq!(uri.fs_game.as_deref(), Some("awe"));
+        assert_eq!(uri.name.as_deref(), Some("Some One"));
+        assert_eq!(parse(&uri.to_link()).unwrap(), uri);
+    }
+
+    #[test]
+    fn default_port_and_ignored_options()
+    {
+        let uri = parse("T1X://cod.example.org?password=&color=red").unwrap();
+        assert_eq!(uri.scheme, "t1x");
+        assert_eq!(uri.host, "cod.example.org");
+        assert_eq!(uri.port, DEFAULT_PORT);
+        assert_eq!(uri.password, None);
+        assert_eq!(uri.to_args(), ["+connect cod.example.org:28960"]);
+    }
+
+    #[test]
+    fn bad_ports()
+    {
+        assert_eq!(parse("iw1x://1.2.3.4:0"), Err(UriError::BadPort(String::from("0"))));
+        assert_eq!(parse("iw1x://1.2.3.4:70000"), Err(UriError::BadPort(String::from("70000"))));
+        assert_eq!(parse("iw1x://1.2.3.4:port"), Err(UriError::BadPort(String::from("port"))));
+        assert_eq!(parse("iw1x://[::1]x"), Err(UriError::BadPort(String::from("x"))));
+    }
+
+    #[test]
+    fn bracketed_ipv6()
+    {
+        let uri = parse("iw1x://[::1]:28962?password=it's").unwrap();
+        assert_eq!(uri.host, "::1");
+        assert_eq!(uri.port, 28962);
+        assert_eq!(uri.address(), "[::1]:28962");
+        // Unquoted, bash would take the brackets for a glob
+        assert_eq!(uri.to_args(), [r"+password 'it'\''s'", "+connect '[::1]:28962'"]);
+        assert_eq!(parse("iw1x://[fe80::1").unwrap_err(), UriError::BadHost(String::from("[fe80::1")));
+        assert_eq!(parse("iw1x://[zz::1]").unwrap_err(), UriError::BadHost(String::from("zz::1")));
+    }
+
+    #[test]
+    fn schemes()
+    {
+        // Any scheme parses, which game it belongs to is decided by the caller
+        assert_eq!(parse("foo://1.2.3.4").unwrap().scheme, "foo");
+        assert_eq!(parse("1.2.3.4:28960"), Err(UriError::NotALink));
+        assert_eq!(parse("i-w://1.2.3.4"), Err(UriError::NotALink));
+        assert_eq!(parse("iw1x://"), Err(UriError::MissingHost));
+        assert!(is_link("coduo://1.2.3.4"));
+        assert!(!is_link("+set"));
+    }
+
+    #[test]
+    fn percent_decoding()
+    {
+        assert_eq!(percent_decode("a+b").unwrap(), "a+b");
+        assert_eq!(percent_decode("%41%2b%20").unwrap(), "A+ ");
+        assert_eq!(percent_decode("%C3%A9").unwrap(), "é");
+        assert_eq!(percent_decode("50%"), Err(UriError::BadEscape(String::from("50%"))));
+        assert_eq!(percent_decode("%zz"), Err(UriError::BadEscape(String::from("%zz"))));
+        assert_eq!(percent_decode("%FF"), Err(UriError::BadEscape(String::from("%FF"))));
+        assert_eq!(percent_encode("a+b &"), "a%2Bb%20%26");
+    }
+}
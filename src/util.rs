@@ -4,11 +4,13 @@ static COD1_1_SUM: &str = "753fbcabd0fdda7f7dad3dbb29c3c008";
 static COD1_5_SUM: &str = "4bdf293d8e6fb32208d1b0942a1ba6bc";
 static COD1_5_1_SUM: &str = "928dd08dc169bd85fdd12d2db28def70";
 
+pub(crate) static LINK_DESKTOP_FILE: &str = "codlinux-links.desktop";
+
 static STATUS_OK: &str      = "[   \x1b[1;92m OK \x1b[0m   ]";
 static STATUS_FAILED: &str  = "[ \x1b[1;91m FAILED \x1b[0m ]";
 
 use std::sync::OnceLock;
-//use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
 
 static RESOLUTION: OnceLock<(u32, u32)> = OnceLock::new();
 static REFRESH_RATE: OnceLock<f32> = OnceLock::new();
@@ -16,6 +18,9 @@ pub(crate) static DISPLAY_OUTPUT: OnceLock<String> = OnceLock::new();
 
 //pub(crate) static GAME_RUNNING: AtomicBool = AtomicBool::new(false);
 
+/// Process group of the running game, 0 while none runs
+static GAME_GROUP: AtomicU32 = AtomicU32::new(0);
+
 use std::{io, env, fs};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -47,11 +52,18 @@ pub(crate) fn get_exes() -> io::Result<Vec<PathBuf>>
     Ok(executables)
 }
 
-pub(crate) fn my_exe_path() -> io::Result<PathBuf>
+pub(crate) fn my_exe() -> io::Result<PathBuf>
 {
     // Get the current executable path
     let exe_path = env::current_exe().unwrap();
     let resolved_path = fs::read_link(&exe_path).unwrap_or(exe_path); // Resolve symbolic link if it exists
+
+    Ok(resolved_path)
+}
+
+pub(crate) fn my_exe_path() -> io::Result<PathBuf>
+{
+    let resolved_path = my_exe()?;
     let exe_dir = resolved_path.parent().unwrap();
 
     Ok(exe_dir.to_path_buf())
@@ -94,9 +106,19 @@ pub(crate) fn name_version_info(executable: &Path) -> Result<Vec<(String, String
     Ok(result)
 }
 
+pub(crate) fn desktop_app_name(uo: bool) -> &'static str
+{
+    if uo { "CoDLinux (uo)" } else { "CoDLinux" }
+}
+
+pub(crate) fn desktop_file_name(uo: bool) -> String
+{
+    format!("{}.desktop", desktop_app_name(uo).replace(" ", "_"))
+}
+
 pub(crate) fn create_desktop_file(uo: &bool, executable_path: &str) -> std::io::Result<()>
 {
-    let app_name = if *uo { "CoDLinux (uo)" } else { "CoDLinux" };
+    let app_name = desktop_app_name(*uo);
     let desktop_file_content = format!(
         "[Desktop Entry]
 Type=Application
@@ -109,7 +131,6 @@ Terminal=false
 Categories=Game;
 StartupNotify=false
 Keywords=cod;gaming;wine;
-MimeType=x-scheme-handler/iw1x;x-scheme-handler/t1x;
 ",
         app_name,
         app_name,
@@ -117,7 +138,7 @@ MimeType=x-scheme-handler/iw1x;x-scheme-handler/t1x;
         workdir = Path::new(executable_path).parent().unwrap().to_string_lossy().to_string()
     );
 
-    let desktop_file_name = format!("{}.desktop", app_name.replace(" ", "_"));
+    let desktop_file_name = desktop_file_name(*uo);
     let desktop_file_path = std::path::Path::new(&std::env::var("HOME").unwrap())
         .join(".local/share/applications")
         .join(desktop_file_name);
@@ -127,6 +148,37 @@ MimeType=x-scheme-handler/iw1x;x-scheme-handler/t1x;
     Ok(())
 }
 
+/// Hidden desktop entry that receives every link and hands it to the
+/// dispatcher, which picks the install from the registry.
+pub(crate) fn create_link_desktop_file(executable: &Path) -> std::io::Result<()>
+{
+    let mime_types: String = crate::handlers::SCHEMES.iter()
+        .map(|scheme| format!("x-scheme-handler/{scheme};"))
+        .collect();
+    let desktop_file_content = format!(
+        "[Desktop Entry]
+Type=Application
+Name=CoDLinux Links
+Exec=\"{executable}\" --dispatch %u
+Icon=codlinux
+Terminal=false
+NoDisplay=true
+StartupNotify=false
+MimeType={mime_types}
+",
+        executable = executable.to_string_lossy(),
+        mime_types = mime_types,
+    );
+
+    let desktop_file_path = std::path::Path::new(&std::env::var("HOME").unwrap())
+        .join(".local/share/applications")
+        .join(LINK_DESKTOP_FILE);
+
+    fs::write(desktop_file_path, desktop_file_content)?;
+
+    Ok(())
+}
+
 pub(crate) fn extract_icon() -> std::io::Result<()>
 {
     let icon_file = Path::new(&env::var("HOME").unwrap()).join(".local/share/icons/codlinux.png");
@@ -138,13 +190,8 @@ pub(crate) fn extract_icon() -> std::io::Result<()>
     Ok(())
 }
 
-pub(crate) fn reg_uri_scheme(uri: &str) -> std::io::Result<()>
+pub(crate) fn reg_uri_scheme(uri: &str, desktop_file: &str) -> std::io::Result<()>
 {
-    let desktop_file = match uri {
-        "iw1x" => "CoDLinux.desktop".to_string(),
-        "t1x" => "CoDLinux_(uo).desktop".to_string(),
-        _ => "CoDLinux.desktop".to_string(),
-    };
     let cmd = format!(
         "xdg-mime default '{desktop_file}' x-scheme-handler/{uri}",
         desktop_file = desktop_file,
@@ -153,6 +200,20 @@ pub(crate) fn reg_uri_scheme(uri: &str) -> std::io::Result<()>
     Ok(exec_command(&cmd)?)
 }
 
+/// Desktop file currently registered for `uri`, if any.
+pub(crate) fn query_uri_scheme(uri: &str) -> Option<String>
+{
+    let output = Command::new("xdg-mime")
+        .arg("query")
+        .arg("default")
+        .arg(format!("x-scheme-handler/{uri}"))
+        .output()
+        .ok()?;
+
+    let desktop_file = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if desktop_file.is_empty() { None } else { Some(desktop_file) }
+}
+
 pub(crate) fn exec_command(cmd: &str) -> io::Result<()>
 {
     println!("exec_command: {}", cmd);
@@ -170,52 +231,48 @@ pub(crate) fn exec_command(cmd: &str) -> io::Result<()>
 
 pub(crate) fn launch_game(game: &GameInfo) -> std::io::Result<()>
 {
-    let wine_prefix = if game.wineprefix.trim().is_empty() {
-        //String::from("$HOME/.wine")
+    use std::os::unix::process::CommandExt;
 
-        let wineprefix = load_setting("default_wine_prefix").unwrap_or_else(|_| {
-            save_setting("default_wine_prefix", "$HOME/.wine").unwrap();
-            "$HOME/.wine".to_string()
-        });
+    let cmd = crate::launch::resolve(game).to_shell();
+    println!("exec_command: {}", cmd);
+    // Own process group, so `stop_game` ends wine and the wrappers but nothing else
+    let mut child = Command::new("bash")
+        .arg("-c")
+        .arg(&cmd)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    GAME_GROUP.store(child.id(), Ordering::Relaxed);
+    let status = child.wait();
+    GAME_GROUP.store(0, Ordering::Relaxed);
+    restore_display_mode().unwrap();
 
-        wineprefix
+    if !status?.success() {
+        eprintln!("Failed to exec command: {}", cmd);
+        return Err(io::Error::new(io::ErrorKind::Other, "Failed to exec shell command"));
     }
-    else {
-        game.wineprefix.to_string()
-    };
+    Ok(())
+}
 
-    let envars = if game.envars.is_empty() {
-        String::from("MESA_EXTENSION_MAX_YEAR=2008 force_s3tc_enable=true __GL_ExtensionStringVersion=17700")
+/// Stops the running game by ending its process group. Other wine
+/// programs in the same prefix keep running.
+pub(crate) fn stop_game() -> std::io::Result<()>
+{
+    let group = GAME_GROUP.load(Ordering::Relaxed);
+    if group == 0 {
+        return Ok(());
     }
-    else {
-        game.envars.to_string()
-    };
-
-    let args = if game.args.is_empty() {
-        String::from("+set r_ignorehwgamma 1")
+    let status = Command::new("kill").args(["-TERM", "--", &format!("-{group}")]).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("kill -TERM -{group} failed")));
     }
-    else {
-        game.args.to_string()
-    };
-
-    let cmd = format!(
-        "WINEPREFIX=\"{wine_prefix}\" {envars} wine {} {args}", game.path.to_string_lossy().to_string());
-    //GAME_RUNNING.store(true, Ordering::Relaxed);
-    exec_command(&cmd).unwrap();
-    //GAME_RUNNING.store(false, Ordering::Relaxed);
-    restore_display_mode().unwrap();
-
     Ok(())
 }
 
-pub(crate) fn verify_file(expected: &str, fpath: &Path) -> io::Result<bool>
+/// Hex md5 of a file, read in chunks.
+pub(crate) fn md5_file(fpath: &Path) -> io::Result<String>
 {
-    print!("[    --    ] Verifying file: {} ", &fpath.to_str().unwrap());
-    io::stdout().flush()?;
-    if !fpath.is_file() {
-        println!("\r{} Verifying file: {}  ", STATUS_FAILED, &fpath.to_str().unwrap());
-        return Ok(false);
-    }
     let mut file = File::open(fpath)?;
     let mut context = Context::new();
     let mut buffer = [0; 4096];
@@ -225,8 +282,19 @@ pub(crate) fn verify_file(expected: &str, fpath: &Path) -> io::Result<bool>
         if bytes_read == 0 { break; }
         context.consume(&buffer[..bytes_read]);
     }
+    Ok(format!("{:x}", context.finalize()))
+}
 
-    let hash = format!("{:x}", context.finalize());
+pub(crate) fn verify_file(expected: &str, fpath: &Path) -> io::Result<bool>
+{
+    print!("[    --    ] Verifying file: {} ", &fpath.to_str().unwrap());
+    io::stdout().flush()?;
+    if !fpath.is_file() {
+        println!("\r{} Verifying file: {}  ", STATUS_FAILED, &fpath.to_str().unwrap());
+        return Ok(false);
+    }
+
+    let hash = md5_file(fpath)?;
     //println!("{}", hash.as_str());
     if hash != expected {
         println!("\r{} Verifying file: {}  ", STATUS_FAILED, &fpath.to_str().unwrap());
@@ -364,13 +432,44 @@ pub(crate) fn get_game_config(game: &str) -> std::io::Result<BTreeMap<String, St
 
 pub(crate) fn notify(message: &str, expire_time: u32, transient: bool) -> std::io::Result<()>
 {
-    let cmd = if transient {
-        format!(r#"notify-send --app-name=CoDLinux --icon=codlinux --transient --expire-time={} CoDLinux "{}""#, expire_time, message)
+    // Run notify-send directly, messages can contain text from links
+    let mut cmd = Command::new("notify-send");
+    cmd.arg("--app-name=CoDLinux")
+        .arg("--icon=codlinux")
+        .arg(format!("--expire-time={}", expire_time));
+    if transient {
+        cmd.arg("--transient");
     }
-    else {
-        format!(r#"notify-send --app-name=CoDLinux --icon=codlinux --expire-time={} CoDLinux "{}""#, expire_time, message)
-    };
+    cmd.arg("CoDLinux").arg(message);
 
-    exec_command(&cmd).unwrap();
+    println!("notify: {}", message);
+    match cmd.status() {
+        Ok(status) if status.success() => (),
+        _ => eprintln!("Failed to send notification: {}", message),
+    }
     Ok(())
 }
+
+/// Like `notify`, with one action button. Blocks until the notification is
+/// closed and returns true if the action was clicked, so call it off the
+/// main thread.
+pub(crate) fn notify_action(message: &str, action: &str) -> bool
+{
+    let output = Command::new("notify-send")
+        .arg("--app-name=CoDLinux")
+        .arg("--icon=codlinux")
+        .arg(format!("--action=default={action}"))
+        .arg("--wait")
+        .arg("CoDLinux")
+        .arg(message)
+        .output();
+
+    println!("notify: {}", message);
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).trim() == "default",
+        Err(e) => {
+            eprintln!("Failed to send notification: {}: {}", message, e);
+            false
+        }
+    }
+}